@@ -0,0 +1,149 @@
+// Times a million-iteration counting loop run by the release binary. The same loop is also run
+// with a large branch that is never taken added to its body: since loop bodies are parsed once
+// and executed from the syntax tree, that text should cost next to nothing per iteration, where
+// re-tokenizing the body on every pass made it dominate.
+//
+//     cargo bench --bench counting_loop
+//
+// The plain loop is also run by the first, re-tokenizing evaluator, for comparison. That build is
+// made from commit b6ed0ce the first time the benchmark runs, and kept in Cargo's temporary
+// directory for the target. Setting BUGLANG_BASELINE to a binary uses it instead. The benchmark
+// fails if neither gives a baseline that runs the loop and prints the same total.
+//
+// That evaluator skips the rest of a loop body after an `if` whose condition is false, so the
+// padded loop is only run by this build.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+// The last commit with the re-tokenizing evaluator
+const BASELINE_COMMIT: &str = "b6ed0ce";
+const ITERATIONS: u32 = 1_000_000;
+const RUNS: u32 = 5;
+const PADDING: usize = 500;
+
+fn main() {
+    let directory = env::temp_dir().join("buglang-bench");
+    fs::create_dir_all(&directory).expect("cannot create the benchmark directory");
+
+    let tight = directory.join("tight.bug");
+    fs::write(&tight, counting_loop("")).expect("cannot write the benchmark script");
+    let padded = directory.join("padded.bug");
+    let unused = "        update total++;\n".repeat(PADDING);
+    let branch = format!("    if (i < 0) {{\n{}    }}\n", unused);
+    fs::write(&padded, counting_loop(&branch)).expect("cannot write the benchmark script");
+
+    let interpreter = env!("CARGO_BIN_EXE_pseudolanguage");
+    let (tight_time, expected) = fastest(interpreter, &tight);
+    let (padded_time, _) = fastest(interpreter, &padded);
+    report("tight loop", tight_time);
+    report(
+        &format!("loop with {} unused statements", PADDING),
+        padded_time,
+    );
+    println!(
+        "padded / tight: {:.2}x",
+        padded_time.as_secs_f64() / tight_time.as_secs_f64()
+    );
+
+    let (baseline_time, output) = fastest(baseline(), &tight);
+    assert_eq!(output, expected, "the baseline printed a different total");
+    report("tight loop on the baseline", baseline_time);
+    println!(
+        "baseline / this build: {:.2}x",
+        baseline_time.as_secs_f64() / tight_time.as_secs_f64()
+    );
+}
+
+// The binary given by BUGLANG_BASELINE, or else one built from `BASELINE_COMMIT`
+fn baseline() -> PathBuf {
+    if let Some(path) = env::var_os("BUGLANG_BASELINE") {
+        return PathBuf::from(path);
+    }
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("buglang-baseline");
+    let binary = directory
+        .join("target")
+        .join("release")
+        .join(format!("pseudolanguage{}", env::consts::EXE_SUFFIX));
+    if binary.exists() {
+        return binary;
+    }
+
+    println!("building the baseline from commit {}", BASELINE_COMMIT);
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("cannot create the baseline directory");
+    let archive = directory.join("source.tar");
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let built = run(Command::new("git")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["archive", "--format=tar", "-o"])
+        .arg(&archive)
+        .arg(BASELINE_COMMIT))
+        && run(Command::new("tar")
+            .arg("-xf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(&directory))
+        && run(Command::new(cargo)
+            .args(["build", "--release", "--quiet", "--manifest-path"])
+            .arg(directory.join("Cargo.toml"))
+            .arg("--target-dir")
+            .arg(directory.join("target"))
+            // Its warnings are no concern here
+            .env("RUSTFLAGS", "--cap-lints=allow"));
+    assert!(
+        built && binary.exists(),
+        "cannot build the baseline from commit {}; set BUGLANG_BASELINE to a build of it",
+        BASELINE_COMMIT
+    );
+    binary
+}
+
+fn counting_loop(extra: &str) -> String {
+    format!(
+        "variable i = 0;\nvariable total = 0;\nfor (i = 0; i < {}; i += 1) {{\n{}    update total++;\n}}\nprint total;\n",
+        ITERATIONS, extra
+    )
+}
+
+// Whether a command could be started and succeeded
+fn run(command: &mut Command) -> bool {
+    command.status().is_ok_and(|status| status.success())
+}
+
+// The best of several runs, which is the least disturbed by whatever else the machine is doing,
+// and what the script printed
+fn fastest(interpreter: impl AsRef<Path>, script: &Path) -> (Duration, String) {
+    let mut printed = String::new();
+    let time = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let output = Command::new(interpreter.as_ref())
+                .arg("-i")
+                .arg(script)
+                .output()
+                .expect("cannot run the interpreter");
+            let elapsed = start.elapsed();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            printed = String::from_utf8_lossy(&output.stdout).into_owned();
+            elapsed
+        })
+        .min()
+        .expect("at least one run");
+    (time, printed)
+}
+
+fn report(name: &str, time: Duration) {
+    println!(
+        "{}: {:.1} ms, {:.0} ns per iteration",
+        name,
+        time.as_secs_f64() * 1000.0,
+        time.as_nanos() as f64 / ITERATIONS as f64
+    );
+}
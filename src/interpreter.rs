@@ -1,550 +1,1596 @@
 // TODO: Modularise the interpreter
-#![allow(unused)]
+use crate::stdlib::{self, Native};
+use crate::utils::ast::*;
+use crate::utils::checker;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::integers::{self, IntOp};
+use crate::utils::memory;
+use crate::utils::optimizer::Optimizer;
+use crate::utils::options::{NilMode, Options, Overflow, DEFAULT_MAX_DEPTH};
+use crate::utils::parser::Parser;
+use crate::utils::symbols::Symbol;
 use crate::utils::tokeniser::*;
+use crate::utils::types::Type;
 use crate::utils::variables::*;
-use core::panic;
-use std::any::Any;
-use std::collections::*;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
+
+// Reading the clock takes far longer than a step, so the deadline is only checked this often
+const DEADLINE_INTERVAL: u64 = 1024;
+
+// How a statement finished, so `return` can unwind out of nested blocks
+enum Flow {
+    Normal,
+    Return(Value),
+}
 
 pub struct Interpreter {
     var_manager: VarManager,
+    options: Options,
+    // Every module evaluated so far, by canonical path
+    modules: HashMap<PathBuf, Rc<Module>>,
+    // The files currently being evaluated, outermost first
+    loading: Vec<PathBuf>,
+    // Built-in functions and constants, found when no variable of the same name exists
+    builtins: HashMap<Symbol, Value>,
+    // Where `input()` reads from; stdin unless replaced
+    input: Box<dyn BufRead>,
+    // Bytes printed so far, counted against the sandbox's `max_output`
+    output: usize,
+    // Steps taken so far, counted against `max_steps`
+    steps: u64,
+    // The step at which the limits are checked next
+    next_check: u64,
+    deadline: Option<Instant>,
+    // Function calls still running, counted against `max_depth`
+    calls: usize,
+    // How much this thread had allocated before the script started, plus what the interpreter
+    // has allocated for itself since, such as parsed code, so only the script's values count
+    // against `max_memory`
+    memory_baseline: isize,
 }
 
 impl Interpreter {
-    pub fn new() -> Self {
-        Self {
+    pub fn new(options: Options) -> Self {
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        let limited =
+            options.max_steps.is_some() || deadline.is_some() || options.max_memory.is_some();
+        // Any limit is first checked on the first step
+        let next_check = if limited { 0 } else { u64::MAX };
+        let mut interpreter = Self {
             var_manager: VarManager::new(),
+            options,
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            builtins: HashMap::new(),
+            input: Box::new(io::BufReader::new(io::stdin())),
+            output: 0,
+            steps: 0,
+            next_check,
+            deadline,
+            calls: 0,
+            memory_baseline: 0,
+        };
+        stdlib::register_all(&mut interpreter);
+        // Standard output sets up its buffer the first time it is used
+        drop(io::stdout());
+        interpreter.memory_baseline = memory::allocated();
+        interpreter
+    }
+
+    // Leaves what was allocated since `before` out of the memory counted against `max_memory`.
+    // Used for what the interpreter keeps for itself, such as the code it parses.
+    pub(crate) fn exclude_memory(&mut self, before: isize) {
+        self.memory_baseline += memory::allocated() - before;
+    }
+
+    pub fn register_native(&mut self, native: Native) {
+        self.builtins
+            .insert(Symbol::intern(native.name), Value::Native(native));
+    }
+
+    pub fn register_constant(&mut self, name: &'static str, value: Value) {
+        self.builtins.insert(Symbol::intern(name), value);
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = input;
+    }
+
+    // Reads one line without its line ending; None at end of input
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
         }
+        let trimmed = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(trimmed);
+        Ok(Some(line))
     }
 
-    pub fn interpret(&mut self, input: &str) {
-        let tokens = Tokenizer::tokenize(input);
-        let mut token_iter = tokens.iter().peekable();
-        while let Some(token) = token_iter.next() {
-            match token {
-                Token::Keyword(k) if k == "print" => {
-                    self.handle_print(&mut token_iter);
-                }
-                Token::Keyword(k) if k == "variable" => {
-                    self.handle_variable_declaration(k, &mut token_iter);
-                }
-                Token::Identifier(k) if k == "if" => {
-                    self.handle_if(&mut token_iter);
-                }
-                Token::Keyword(k) if k == "while" => {
-                    self.handle_while(&mut token_iter);
-                }
-                Token::Keyword(k) if k == "update" => {
-                    self.handle_variable_update(&mut token_iter);
-                }
-                Token::Identifier(k) if k == "for" => {
-                    self.handle_for(&mut token_iter);
-                }
+    // Counts a step, failing once the script has taken more steps or run for longer than allowed,
+    // or holds more memory
+    #[inline]
+    pub(crate) fn step(&mut self, span: Span) -> Result<(), BugError> {
+        self.steps += 1;
+        if self.steps < self.next_check {
+            return Ok(());
+        }
+        self.check_budget(span)
+    }
 
-                _ => panic!("Unexpected token: {:?}", token),
+    fn check_budget(&mut self, span: Span) -> Result<(), BugError> {
+        if let Some(max_steps) = self.options.max_steps {
+            if self.steps > max_steps {
+                return Err(BugError::new(
+                    ErrorKind::Budget,
+                    format!("Ran for more than {} steps", max_steps),
+                    span,
+                ));
             }
         }
+        self.check_deadline(span)?;
+        // Memory can grow with any step, so it is checked after every one
+        if self.options.max_memory.is_some() {
+            self.check_memory(span)?;
+            self.next_check = self.steps + 1;
+            return Ok(());
+        }
+        let steps_left = self.options.max_steps.map_or(u64::MAX, |max| max + 1);
+        let clock = match self.deadline {
+            Some(_) => self.steps + DEADLINE_INTERVAL,
+            None => u64::MAX,
+        };
+        self.next_check = steps_left.min(clock);
+        Ok(())
     }
 
-    fn handle_print<'a, I>(&mut self, tokens: &mut I)
-    where
-        I: Iterator<Item = &'a Token>,
-    {
-        let mut tokens_collected = Vec::new();
-        while let Some(token) = tokens.next() {
-            tokens_collected.push(token.clone());
-            if let Token::Symbol(';') = token {
-                break;
-            }
+    // When `--timeout` runs out, for builtins that wait on something outside the script
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn check_deadline(&self, span: Span) -> Result<(), BugError> {
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(self.out_of_time(span)),
+            _ => Ok(()),
         }
+    }
 
-        match tokens_collected.as_slice() {
-            [value_token, Token::Symbol(';')] => match value_token {
-                Token::StringLiteral(literal) => println!("{}", literal),
-                Token::Identifier(var_name) => {
-                    if let Some(variable) = self.get_var(var_name) {
-                        match &variable.Value {
-                            Value::Integer(i) => println!("{}", i),
-                            Value::Float(f) => println!("{}", f),
-                            Value::Boolean(b) => println!("{}", b),
-                            Value::String(s) => println!("{}", s),
-                        }
-                    } else {
-                        panic!("Error: Undefined variable '{}'.", var_name);
-                    }
-                }
-                Token::Number(num) => println!("{}", num),
-                Token::Float(f) => println!("{}", f),
-                Token::Boolean(b) => println!("{}", b),
+    pub fn out_of_time(&self, span: Span) -> BugError {
+        let timeout = self.options.timeout.unwrap_or_default();
+        BugError::new(
+            ErrorKind::Budget,
+            format!("Ran for longer than {:?}", timeout),
+            span,
+        )
+    }
 
-                _ => panic!("Error: invalid token type for print statement"),
-            },
-            _ => {
-                panic!("Error: invalid print syntax");
+    // Counts a function call that is starting, unless it would go deeper than allowed
+    pub(crate) fn enter_call(&mut self, span: Span) -> Result<(), BugError> {
+        let max_depth = self.options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        if self.calls >= max_depth {
+            return Err(BugError::new(
+                ErrorKind::Recursion,
+                format!("Nested more than {} function calls", max_depth),
+                span,
+            ));
+        }
+        self.calls += 1;
+        Ok(())
+    }
+
+    pub(crate) fn leave_call(&mut self) {
+        self.calls -= 1;
+    }
+
+    fn check_memory(&self, span: Span) -> Result<(), BugError> {
+        let Some(max_memory) = self.options.max_memory else {
+            return Ok(());
+        };
+        if memory::allocated() - self.memory_baseline > max_memory as isize {
+            return Err(BugError::new(
+                ErrorKind::Memory,
+                format!("Used more than {} bytes of memory", max_memory),
+                span,
+            ));
+        }
+        Ok(())
+    }
+
+    // Fails before an operation allocates `bytes` for its result if they would take the script
+    // over its memory limit, since the check after it would come too late for a result that does
+    // not fit in memory at all. It also fails once the timeout has passed, so a long operation is
+    // not started when the step before it only just made it in time.
+    pub fn reserve_memory(&self, bytes: usize, span: Span) -> Result<(), BugError> {
+        self.check_deadline(span)?;
+        let Some(max_memory) = self.options.max_memory else {
+            return Ok(());
+        };
+        let used = memory::allocated() - self.memory_baseline;
+        if used.saturating_add(bytes.try_into().unwrap_or(isize::MAX)) > max_memory as isize {
+            return Err(BugError::new(
+                ErrorKind::Memory,
+                format!("Would use more than {} bytes of memory", max_memory),
+                span,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn interpret_file(&mut self, path: &Path) -> Result<(), BugError> {
+        let canonical = Self::resolve(path, Span::default())?;
+        let before = memory::allocated();
+        let source = Self::read_source(&canonical, path, Span::default())?;
+        self.exclude_memory(before);
+        self.enter_file(canonical);
+        let result = self.interpret(&source);
+        self.leave_file();
+        result.map_err(|error| error.in_file(path))
+    }
+
+    // Imports inside a file are found relative to it
+    pub(crate) fn enter_file(&mut self, canonical: PathBuf) {
+        self.loading.push(canonical);
+    }
+
+    pub(crate) fn leave_file(&mut self) {
+        self.loading.pop();
+    }
+
+    pub fn interpret(&mut self, input: &str) -> Result<(), BugError> {
+        let before = memory::allocated();
+        let script = Self::parse(input, &self.options)?;
+        self.exclude_memory(before);
+        self.execute_program(&script.statements)
+    }
+
+    // Parses and checks a file, simplifying it for `options.nil_mode` when `options.optimize` is set
+    pub(crate) fn parse(input: &str, options: &Options) -> Result<Script, BugError> {
+        let tokens = Tokenizer::tokenize(input)?;
+        let mut statements = Parser::new(tokens).parse()?;
+        let (globals, report) = checker::check_program(&mut statements);
+        // Undefined names and type errors stop a program before any of it runs
+        if let Some(error) = report.errors.into_iter().next() {
+            return Err(error);
+        }
+        let mut script = Script {
+            statements,
+            globals,
+        };
+        if options.optimize {
+            Optimizer::optimize(&mut script, options.nil_mode);
+        }
+        Ok(script)
+    }
+
+    fn execute_program(&mut self, statements: &[Stmt]) -> Result<(), BugError> {
+        for statement in statements {
+            if let Flow::Return(_) = self.execute(statement)? {
+                return Err(BugError::new(
+                    ErrorKind::Syntax,
+                    "'return' outside of a function",
+                    statement.span,
+                ));
             }
         }
+        Ok(())
     }
 
-    // DO NOT TOUCH, IDK HOW I MADE IT WORK
-    fn handle_variable_declaration<'a, I>(&mut self, first_token: &String, tokens: &mut I)
-    where
-        I: Iterator<Item = &'a Token>,
-    {
-        let mut tokens_collected = Vec::new();
-        while let Some(token) = tokens.next() {
-            tokens_collected.push(token.clone());
-            if let Token::Symbol(';') = token {
-                break;
+    fn execute_block(&mut self, statements: &[Stmt]) -> Result<Flow, BugError> {
+        for statement in statements {
+            if let Flow::Return(value) = self.execute(statement)? {
+                return Ok(Flow::Return(value));
             }
         }
+        Ok(Flow::Normal)
+    }
 
-        if let [Token::Identifier(ref var_name), Token::Operator(ref op), value, Token::Symbol(';')]
-            = tokens_collected.as_slice()
-        {
-            if op == "=" {
-                match value {
-                    Token::Number(v) => self.set_var(var_name, &v.to_string()),
-                    Token::Float(v) => self.set_var(var_name, &v.to_string()),
-                    Token::StringLiteral(v) => self.set_var(var_name, v),
-                    Token::Boolean(v) => self.set_var(var_name, &v.to_string()),
-                    _ => panic!("Error: Unsupported data type in variable declaration."),
-                }
-            } else {
-                panic!("Error: Expected '=' operator in variable declaration.");
+    fn execute(&mut self, statement: &Stmt) -> Result<Flow, BugError> {
+        let span = statement.span;
+        self.step(span)?;
+        match &statement.kind {
+            StmtKind::Print(print) => self.handle_print(print, span)?,
+            StmtKind::Variable {
+                name,
+                ty,
+                init,
+                constant,
+                slot,
+            } => self.handle_variable_declaration(*name, *slot, *ty, init, *constant, span)?,
+            StmtKind::Update {
+                name,
+                op,
+                value,
+                slot,
+            } => self.handle_variable_update(*name, *slot, *op, value.as_ref(), span)?,
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => return self.handle_if(condition, then_branch, else_branch.as_deref()),
+            StmtKind::While { condition, body } => return self.handle_while(condition, body, span),
+            StmtKind::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                return self.handle_for(
+                    init.as_deref(),
+                    condition.as_ref(),
+                    update.as_deref(),
+                    body,
+                    span,
+                )
+            }
+            StmtKind::Function { decl, slot } => {
+                self.handle_function_declaration(decl, *slot, span)?
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(expr) => self.evaluate(expr)?,
+                    None => Value::Nil,
+                };
+                return Ok(Flow::Return(value));
+            }
+            StmtKind::Throw(expr) => return Err(self.handle_throw(expr)?),
+            StmtKind::Try {
+                body,
+                catch,
+                finally,
+            } => return self.handle_try(body, catch.as_ref(), finally.as_deref(), span),
+            StmtKind::Import { path, alias, slot } => {
+                self.handle_import(path, *alias, *slot, span)?
+            }
+            // Exports are read from a module's top level once it has run
+            StmtKind::Export(_) => {}
+            StmtKind::Expression(expr) => {
+                self.evaluate(expr)?;
             }
-        } else {
-            panic!("Error: Incorrect variable declaration syntax.");
         }
+        Ok(Flow::Normal)
     }
 
+    fn handle_print(&mut self, print: &PrintStmt, span: Span) -> Result<(), BugError> {
+        let mut values = Vec::with_capacity(print.values.len());
+        for expr in &print.values {
+            values.push(self.evaluate(expr)?.to_string());
+        }
+        let sep = self.print_option("sep", print.sep.as_ref(), " ")?;
+        let default_end = if print.newline { "\n" } else { "" };
+        let end = self.print_option("end", print.end.as_ref(), default_end)?;
+        self.write_output(print.target, &(values.join(&sep) + &end), span)
+    }
 
-    fn handle_if<'a, I>(&mut self, tokens: &mut I)
-    where
-        I: Iterator<Item = &'a Token>,
-    {
-        // Collect tokens until we encounter a closing parenthesis
-        let mut tokens_collected = Vec::new();
-        while let Some(token) = tokens.next() {
-            tokens_collected.push(token.clone()); // Clone is now valid
-            if let Token::Symbol(')') = token {
-                break;
+    // Prints text, as long as it fits in what is left of the sandbox's output limit
+    pub(crate) fn write_output(
+        &mut self,
+        target: PrintTarget,
+        text: &str,
+        span: Span,
+    ) -> Result<(), BugError> {
+        if let Some(max_output) = self.options.sandbox.max_output {
+            if self.output + text.len() > max_output {
+                return Err(BugError::new(
+                    ErrorKind::Permission,
+                    format!(
+                        "Printing this would go past the {} bytes of output allowed",
+                        max_output
+                    ),
+                    span,
+                ));
             }
         }
+        self.output += text.len();
+        let result = match target {
+            PrintTarget::Stdout => {
+                let mut out = io::stdout().lock();
+                out.write_all(text.as_bytes()).and_then(|_| out.flush())
+            }
+            PrintTarget::Stderr => io::stderr().lock().write_all(text.as_bytes()),
+        };
+        result.map_err(|e| BugError::new(ErrorKind::Io, format!("Could not print: {}", e), span))
+    }
 
-        // Extract condition tokens as references
-        let condition_tokens = tokens_collected
-            .iter()
-            .skip(1) // Skip the opening '('
-            .take_while(|&token| !matches!(token, Token::Symbol(')'))); // Use `&token`
+    // `sep` and `end` must be strings when given
+    fn print_option(
+        &mut self,
+        name: &str,
+        expr: Option<&Expr>,
+        default: &str,
+    ) -> Result<String, BugError> {
+        let Some(expr) = expr else {
+            return Ok(default.to_string());
+        };
+        let value = self.evaluate(expr)?;
+        Self::print_string(name, value, expr.span)
+    }
 
-        // Evaluate the condition
-        if self
-            .evaluate_condition(&mut condition_tokens.cloned())
-            .unwrap_or(false)
-        {
-            self.execute_block(tokens);
-        } else {
-            self.skip_block(tokens);
+    pub(crate) fn print_string(name: &str, value: Value, span: Span) -> Result<String, BugError> {
+        match value {
+            Value::String(s) => Ok(s.to_string()),
+            other => Err(BugError::new(
+                ErrorKind::Type,
+                format!("'{}' must be a string, got {}", name, other),
+                span,
+            )),
         }
     }
 
-    fn execute_block<'a, I>(&mut self, tokens: &mut I)
-    where
-        I: Iterator<Item = &'a Token>,
-    {
-        let mut block_depth = 1;
-        let mut block_tokens = Vec::new();
-
-        while let Some(token) = tokens.next() {
-            match token {
-                Token::Symbol('}') => {
-                    block_depth -= 1;
-                    if block_depth == 0 {
-                        break;
-                    }
-                }
-                Token::Symbol('{') => {
-                    block_depth += 1;
-                }
-                _ => {
-                    block_tokens.push(token.clone());
+    fn handle_variable_declaration(
+        &mut self,
+        name: Symbol,
+        slot: usize,
+        ty: Option<Type>,
+        init: &Expr,
+        constant: bool,
+        span: Span,
+    ) -> Result<(), BugError> {
+        let value = match &init.kind {
+            // String literals are still read as whatever type they look like, unless declared
+            // as strings
+            ExprKind::Literal(Value::String(s)) if ty != Some(Type::String) => {
+                match VarManager::parse_value(s) {
+                    Some(value) => value,
+                    None => return Err(BugError::new(ErrorKind::Type, "Invalid value", init.span)),
                 }
             }
+            _ => self.evaluate(init)?,
+        };
+        let value = Self::conform(value, ty, name.as_str(), init.span)?;
+        // A constant replaces any variable of the same name instead of assigning to it
+        self.declare(name, slot, value, constant, ty, span)
+    }
+
+    // Checks a value against a declared type, widening integers declared as floats
+    pub(crate) fn conform(
+        value: Value,
+        ty: Option<Type>,
+        what: &str,
+        span: Span,
+    ) -> Result<Value, BugError> {
+        let Some(ty) = ty else {
+            return Ok(value);
+        };
+        match value {
+            Value::Integer(i) if ty == Type::Float => Ok(Value::Float(i as f64)),
+            value if ty.accepts(Type::of(&value)) => Ok(value),
+            value => Err(BugError::new(
+                ErrorKind::Type,
+                format!("{} is declared {} but got {}", what, ty, value),
+                span,
+            )),
         }
+    }
 
-        self.interpret(&Tokenizer::reconstruct(&block_tokens));
+    fn handle_function_declaration(
+        &mut self,
+        decl: &Rc<FunctionDecl>,
+        slot: usize,
+        span: Span,
+    ) -> Result<(), BugError> {
+        let name = decl.name.unwrap_or_default();
+        let function = self.make_function(decl);
+        self.set_var(name, slot, function, span)
     }
 
-    fn skip_block<'a, I>(&mut self, tokens: &mut I)
-    where
-        I: Iterator<Item = &'a Token>,
-    {
-        let mut block_depth = 1;
-        while let Some(token) = tokens.next() {
-            match token {
-                Token::Symbol('}') => {
-                    block_depth -= 1;
-                    if block_depth == 0 {
-                        break;
-                    }
-                }
-                Token::Symbol('{') => {
-                    block_depth += 1;
-                }
-                _ => {}
+    // Builds the error raised by `throw`; rethrowing a caught error keeps its original details
+    fn handle_throw(&mut self, expr: &Expr) -> Result<BugError, BugError> {
+        let value = self.evaluate(expr)?;
+        Ok(Self::raised(value, expr.span))
+    }
+
+    pub(crate) fn raised(value: Value, span: Span) -> BugError {
+        match value {
+            Value::Error(error) => (*error).clone(),
+            value => BugError::thrown(value, span),
+        }
+    }
+
+    fn handle_try(
+        &mut self,
+        body: &[Stmt],
+        catch: Option<&CatchClause>,
+        finally: Option<&[Stmt]>,
+        span: Span,
+    ) -> Result<Flow, BugError> {
+        let mut result = self.execute_block(body);
+        // An error that can't be caught skips `finally` too, and ends the script at once
+        if result
+            .as_ref()
+            .is_err_and(|error| !error.kind.is_catchable())
+        {
+            return result;
+        }
+        result = match (result, catch) {
+            (Err(error), Some(catch)) => {
+                let value = Value::Error(Rc::new(error));
+                // Like the VM, which checks before binding the error, a script still over its
+                // memory limit gets the error again here
+                self.check_memory(span)
+                    .map(|()| self.bind_caught(catch.slot, value))
+                    .and_then(|()| self.execute_block(&catch.body))
+            }
+            (result, _) => result,
+        };
+        if let Some(finally) = finally {
+            // A `return` or error inside `finally` replaces whatever happened before it
+            if let Flow::Return(value) = self.execute_block(finally)? {
+                return Ok(Flow::Return(value));
             }
         }
+        result
     }
 
-    fn evaluate_condition<'a, I>(&mut self, tokens: &mut I) -> Option<bool>
-    where
-        I: Iterator<Item = Token>,
-    {
-        let mut left_operand: Option<i64> = None;
-        let mut operator: Option<char> = None;
+    fn handle_import(
+        &mut self,
+        path: &str,
+        alias: Symbol,
+        slot: usize,
+        span: Span,
+    ) -> Result<(), BugError> {
+        let canonical = self.locate_module(path, span)?;
+        let module = match self.modules.get(&canonical) {
+            Some(module) => Rc::clone(module),
+            None => self.load_module(canonical, span)?,
+        };
+        self.set_var(alias, slot, Value::Module(module), span)
+    }
 
-        // Parse the left operand
-        if let Some(token) = tokens.next() {
-            match token {
-                Token::Identifier(var_name) => {
-                    if let Some(var) = self.get_var(var_name.as_str()) {
-                        if let Value::Integer(i) = var.Value {
-                            left_operand = Some(i);
-                        } else {
-                            return None;
-                        }
-                    } else {
-                        return None;
-                    }
-                }
-                Token::Number(n) => left_operand = Some(n),
-                _ => return None,
+    // The canonical path of an imported file, refusing one that is still being evaluated
+    pub(crate) fn locate_module(&self, path: &str, span: Span) -> Result<PathBuf, BugError> {
+        // Imports are relative to the importing file, or the working directory for plain input
+        let relative = match self.loading.last().and_then(|file| file.parent()) {
+            Some(directory) => directory.join(path),
+            None => PathBuf::from(path),
+        };
+        // Modules beside the script that was run are always allowed. Anything else is a file
+        // like those the file builtins read, and the sandbox policy decides.
+        let trusted = match self.loading.first().and_then(|file| file.parent()) {
+            Some(directory) => Some(directory.to_path_buf()),
+            None => std::env::current_dir().ok(),
+        };
+        // The file that is loaded is the one that was checked, not the path looked up again
+        let canonical = match relative.canonicalize() {
+            Ok(canonical) if trusted.is_some_and(|directory| canonical.starts_with(directory)) => {
+                canonical
+            }
+            _ => {
+                let shown = relative.display().to_string();
+                let allowed = self
+                    .options
+                    .sandbox
+                    .allowed_path("import", &shown, false, span)?;
+                Self::resolve(&allowed, span)?
             }
+        };
+
+        if self.loading.contains(&canonical) {
+            let chain: Vec<String> = self
+                .loading
+                .iter()
+                .chain(std::iter::once(&canonical))
+                .map(|file| Self::display_path(file))
+                .collect();
+            return Err(BugError::new(
+                ErrorKind::Import,
+                format!("Import cycle: {}", chain.join(" -> ")),
+                span,
+            ));
         }
+        Ok(canonical)
+    }
+
+    pub(crate) fn loaded_module(&self, canonical: &Path) -> Option<Rc<Module>> {
+        self.modules.get(canonical).cloned()
+    }
+
+    // Runs a module in its own global scope and caches what it exports
+    fn load_module(&mut self, canonical: PathBuf, span: Span) -> Result<Rc<Module>, BugError> {
+        let path = Self::module_path(&canonical);
+        let before = memory::allocated();
+        let source = Self::read_source(&canonical, &path, span)?;
+        let script = Self::parse(&source, &self.options).map_err(|error| error.in_file(&path))?;
+        self.exclude_memory(before);
+        let exports: Vec<Symbol> = script
+            .statements
+            .iter()
+            .filter_map(|statement| match &statement.kind {
+                StmtKind::Export(names) => Some(names),
+                _ => None,
+            })
+            .flatten()
+            .copied()
+            .collect();
+
+        let importer_scopes = self.begin_module(canonical.clone());
+        let result = self.execute_program(&script.statements);
+        self.end_module(
+            canonical,
+            importer_scopes,
+            result,
+            &script.globals,
+            &exports,
+            span,
+        )
+    }
+
+    // How a module's path is shown in errors
+    pub(crate) fn module_path(canonical: &Path) -> PathBuf {
+        PathBuf::from(Self::display_path(canonical))
+    }
+
+    // Switches to a fresh global scope for a module, returning the importer's scopes
+    pub(crate) fn begin_module(&mut self, canonical: PathBuf) -> Vec<Scope> {
+        self.enter_file(canonical);
+        self.var_manager.enter(&[])
+    }
 
-        // Parse the operator
-        if let Some(token) = tokens.next() {
-            match token {
-                Token::Operator(op) => {
-                    if op.len() == 1 {
-                        operator = Some(op.chars().next().unwrap());
-                    } else {
-                        return None; // Handle multi-character operators if needed
+    // Collects what a module that has finished running exports, and goes back to the importer.
+    // A module without an export list exports everything it declares.
+    pub(crate) fn end_module(
+        &mut self,
+        canonical: PathBuf,
+        importer_scopes: Vec<Scope>,
+        result: Result<(), BugError>,
+        globals: &[Symbol],
+        export_names: &[Symbol],
+        span: Span,
+    ) -> Result<Rc<Module>, BugError> {
+        let locals: HashMap<Symbol, Variable> = globals
+            .iter()
+            .zip(self.var_manager.locals())
+            .filter_map(|(name, variable)| Some((*name, variable?)))
+            .collect();
+        self.var_manager.restore(importer_scopes);
+        self.leave_file();
+        let path = Self::module_path(&canonical);
+        result.map_err(|error| error.in_file(&path))?;
+
+        let exports = if export_names.is_empty() {
+            locals
+                .into_iter()
+                .map(|(name, variable)| (name, variable.value))
+                .collect()
+        } else {
+            let mut exports = HashMap::new();
+            for name in export_names {
+                match locals.get(name) {
+                    Some(variable) => {
+                        exports.insert(*name, variable.value.clone());
+                    }
+                    None => {
+                        return Err(BugError::new(
+                            ErrorKind::Name,
+                            format!("Module exports undefined name '{}'", name),
+                            span,
+                        )
+                        .in_file(&path))
                     }
                 }
-                _ => return None,
             }
+            exports
+        };
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let module = Rc::new(Module { name, exports });
+        self.modules.insert(canonical, Rc::clone(&module));
+        Ok(module)
+    }
+
+    pub(crate) fn resolve(path: &Path, span: Span) -> Result<PathBuf, BugError> {
+        path.canonicalize().map_err(|error| {
+            BugError::new(
+                ErrorKind::Import,
+                format!("Cannot find '{}': {}", path.display(), error),
+                span,
+            )
+        })
+    }
+
+    pub(crate) fn read_source(
+        canonical: &Path,
+        path: &Path,
+        span: Span,
+    ) -> Result<String, BugError> {
+        fs::read_to_string(canonical).map_err(|error| {
+            BugError::new(
+                ErrorKind::Import,
+                format!("Cannot read '{}': {}", path.display(), error),
+                span,
+            )
+        })
+    }
+
+    // Shortens a canonical path to be relative to the working directory where possible
+    fn display_path(path: &Path) -> String {
+        std::env::current_dir()
+            .ok()
+            .and_then(|directory| path.strip_prefix(directory).ok().map(Path::to_path_buf))
+            .unwrap_or_else(|| path.to_path_buf())
+            .display()
+            .to_string()
+    }
+
+    fn handle_if(
+        &mut self,
+        condition: &Expr,
+        then_branch: &[Stmt],
+        else_branch: Option<&[Stmt]>,
+    ) -> Result<Flow, BugError> {
+        if self.evaluate_condition(condition)? {
+            self.execute_block(then_branch)
+        } else if let Some(else_branch) = else_branch {
+            self.execute_block(else_branch)
+        } else {
+            Ok(Flow::Normal)
         }
+    }
 
-        // Parse the right operand
-        if let Some(token) = tokens.next() {
-            match token {
-                Token::Number(n) => {
-                    if let (Some(lhs), Some(op)) = (left_operand, operator) {
-                        match op {
-                            '<' => return Some(lhs < n),
-                            '>' => return Some(lhs > n),
-                            '=' => return Some(lhs == n),
-                            _ => return None,
-                        }
-                    }
+    // Every iteration is a step, so a loop with an empty body still uses up the budget
+    fn handle_while(
+        &mut self,
+        condition: &Expr,
+        body: &[Stmt],
+        span: Span,
+    ) -> Result<Flow, BugError> {
+        while self.evaluate_condition(condition)? {
+            self.step(span)?;
+            if let Flow::Return(value) = self.execute_block(body)? {
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn handle_for(
+        &mut self,
+        init: Option<&Stmt>,
+        condition: Option<&Expr>,
+        update: Option<&Stmt>,
+        body: &[Stmt],
+        span: Span,
+    ) -> Result<Flow, BugError> {
+        if let Some(init) = init {
+            self.execute(init)?;
+        }
+
+        // Execute the for loop
+        loop {
+            self.step(span)?;
+            // Evaluate the condition dynamically on each iteration
+            if let Some(condition) = condition {
+                if !self.evaluate_condition(condition)? {
+                    break;
                 }
-                _ => return None,
+            }
+
+            // Execute the block
+            if let Flow::Return(value) = self.execute_block(body)? {
+                return Ok(Flow::Return(value));
+            }
+
+            // Process update
+            if let Some(update) = update {
+                self.execute(update)?;
             }
         }
+        Ok(Flow::Normal)
+    }
 
-        None
+    fn handle_variable_update(
+        &mut self,
+        var_name: Symbol,
+        slot: Slot,
+        op: UpdateOp,
+        value: Option<&Expr>,
+        span: Span,
+    ) -> Result<(), BugError> {
+        let current = match self.get_var(slot) {
+            Some(variable) => variable.value,
+            None => return Err(Self::undeclared(var_name, span)),
+        };
+        let amount = match value {
+            Some(expr) => Some(self.evaluate(expr)?),
+            None => None,
+        };
+        let new_value = self.updated(op, current, amount, span)?;
+        self.assign(var_name, slot, new_value, span)
     }
 
-    fn handle_while<'a, I>(&mut self, tokens: &mut I)
-    where
-        I: Iterator<Item = &'a Token>,
-    {
-        // Collect tokens until we encounter a closing parenthesis
-        let mut tokens_collected = Vec::new();
-        while let Some(token) = tokens.next() {
-            tokens_collected.push(token.clone());
-            if let Token::Symbol(')') = token {
-                break;
+    // Stores an updated value in an existing variable
+    pub(crate) fn assign(
+        &mut self,
+        var_name: Symbol,
+        slot: Slot,
+        value: Value,
+        span: Span,
+    ) -> Result<(), BugError> {
+        let value = match self.var_manager.annotation(slot) {
+            Some(ty) => Self::conform(value, Some(ty), var_name.as_str(), span)?,
+            None => value,
+        };
+        let found = value.type_name();
+        match self.var_manager.assign(slot, value) {
+            Err(AssignError::Constant) => Err(BugError::new(
+                ErrorKind::Type,
+                format!("Cannot update constant {}", var_name),
+                span,
+            )),
+            Err(AssignError::Type(held)) => Err(Self::mismatched(var_name, held, found, span)),
+            Err(AssignError::Undefined) => Err(Self::undeclared(var_name, span)),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    pub(crate) fn undeclared(var_name: Symbol, span: Span) -> BugError {
+        BugError::new(
+            ErrorKind::Name,
+            format!("Tried updating a non-existing variable: {}", var_name),
+            span,
+        )
+    }
+
+    // The value an `update` statement stores, given the variable's current value
+    pub(crate) fn updated(
+        &mut self,
+        op: UpdateOp,
+        current: Value,
+        amount: Option<Value>,
+        span: Span,
+    ) -> Result<Value, BugError> {
+        let new_value = match (op, current, amount) {
+            (UpdateOp::Assign, _, Some(value)) => value,
+            (UpdateOp::Increment, Value::Integer(i), _) => {
+                self.integer_op(IntOp::Add, i, 1, span)?
+            }
+            (UpdateOp::Increment, Value::BigInt(n), _) => integers::normalize(n.as_ref() + 1),
+            (UpdateOp::Increment, Value::Float(f), _) => Value::Float(f + 1.0),
+            (UpdateOp::Decrement, Value::Integer(i), _) => {
+                self.integer_op(IntOp::Subtract, i, 1, span)?
             }
+            (UpdateOp::Decrement, Value::BigInt(n), _) => integers::normalize(n.as_ref() - 1),
+            (UpdateOp::Decrement, Value::Float(f), _) => Value::Float(f - 1.0),
+            (UpdateOp::Increment, Value::Nil, _) => {
+                self.arithmetic(BinaryOp::Add, Value::Nil, Value::Integer(1), span)?
+            }
+            (UpdateOp::Decrement, Value::Nil, _) => {
+                self.arithmetic(BinaryOp::Subtract, Value::Nil, Value::Integer(1), span)?
+            }
+            (UpdateOp::Increment | UpdateOp::Decrement, current, _) => {
+                return Err(BugError::new(
+                    ErrorKind::Type,
+                    format!(
+                        "Cannot use {} on a value of type {}",
+                        op,
+                        current.type_name()
+                    ),
+                    span,
+                ))
+            }
+            (UpdateOp::Add, current, Some(amount)) => {
+                self.arithmetic(BinaryOp::Add, current, amount, span)?
+            }
+            (UpdateOp::Subtract, current, Some(amount)) => {
+                self.arithmetic(BinaryOp::Subtract, current, amount, span)?
+            }
+            _ => {
+                return Err(BugError::new(
+                    ErrorKind::Syntax,
+                    "Invalid variable update syntax",
+                    span,
+                ))
+            }
+        };
+        Ok(new_value)
+    }
+
+    fn evaluate_condition(&mut self, condition: &Expr) -> Result<bool, BugError> {
+        let value = self.evaluate(condition)?;
+        self.condition(value, condition.span)
+    }
+
+    pub(crate) fn condition(&self, value: Value, span: Span) -> Result<bool, BugError> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            Value::Nil => {
+                self.check_nil(span)?;
+                Ok(false)
+            }
+            other => Err(BugError::new(
+                ErrorKind::Type,
+                format!("Invalid condition: expected a boolean, got {}", other),
+                span,
+            )),
         }
+    }
 
-        // Extract condition tokens (excluding the surrounding parentheses)
-        let condition_tokens: Vec<Token> = tokens_collected
-            .iter()
-            .skip(1) // Skip the opening '('
-            .take_while(|&token| !matches!(token, Token::Symbol(')')))
-            .cloned()
-            .collect();
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value, BugError> {
+        let value = self.compute(expr)?;
+        // The VM checks memory before every instruction, so a value that takes the script over
+        // the limit is dropped before it can be stored. Checking every result does the same here.
+        if self.options.max_memory.is_some() {
+            self.check_memory(expr.span)?;
+        }
+        Ok(value)
+    }
 
-        // Prepare to collect the body tokens
-        let mut body_tokens = Vec::new();
-        let mut open_braces = 0;
-
-        // Collect the body tokens (assuming a `{}` block)
-        while let Some(token) = tokens.next() {
-            match token {
-                Token::Symbol('{') => open_braces += 1,
-                Token::Symbol('}') => {
-                    open_braces -= 1;
-                    if open_braces == 0 {
-                        break;
-                    }
+    fn compute(&mut self, expr: &Expr) -> Result<Value, BugError> {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Literal(value) => Ok(value.clone()),
+            ExprKind::Variable { name, slot } => self.lookup(*name, *slot, span),
+            ExprKind::Unary { op, expr } => {
+                let value = self.evaluate(expr)?;
+                self.unary(*op, value, span)
+            }
+            ExprKind::Binary { left, op, right } => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                self.arithmetic(*op, left, right, span)
+            }
+            ExprKind::Logical {
+                left,
+                op: LogicalOp::Coalesce,
+                right,
+            } => match self.evaluate(left)? {
+                Value::Nil => self.evaluate(right),
+                value => Ok(value),
+            },
+            ExprKind::Logical { left, op, right } => {
+                let left = self.evaluate_condition(left)?;
+                match op {
+                    LogicalOp::And if !left => Ok(Value::Boolean(false)),
+                    LogicalOp::Or if left => Ok(Value::Boolean(true)),
+                    _ => Ok(Value::Boolean(self.evaluate_condition(right)?)),
                 }
-                _ => {}
             }
-            body_tokens.push(token.clone());
+            ExprKind::Call { callee, args } => self.call(callee, args, span),
+            ExprKind::List(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.evaluate(item)?);
+                }
+                Ok(Value::List(Rc::new(values)))
+            }
+            ExprKind::Index { object, index } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                self.get_index(object, index, span)
+            }
+            ExprKind::Get {
+                object,
+                name,
+                optional,
+            } => match self.evaluate(object)? {
+                Value::Nil if *optional => Ok(Value::Nil),
+                object => self.get_property(object, *name, span),
+            },
+            ExprKind::Function(decl) => Ok(self.make_function(decl)),
         }
+    }
 
-        // Execute the loop
-        loop {
-            // Evaluate the condition
-            let condition_result =
-                self.evaluate_condition(&mut condition_tokens.clone().into_iter());
-            if !condition_result.unwrap_or(false) {
-                break;
+    // A variable's value, or the builtin of that name if the variable has not been declared yet
+    pub(crate) fn lookup(
+        &self,
+        name: Symbol,
+        slot: Option<Slot>,
+        span: Span,
+    ) -> Result<Value, BugError> {
+        match slot.and_then(|slot| self.var_manager.get(slot)) {
+            Some(variable) => Ok(variable.value),
+            None => match self.builtins.get(&name) {
+                Some(value) => Ok(value.clone()),
+                None => Err(BugError::new(
+                    ErrorKind::Name,
+                    format!("Undefined variable '{}'.", name),
+                    span,
+                )),
+            },
+        }
+    }
+
+    pub(crate) fn unary(&self, op: UnaryOp, value: Value, span: Span) -> Result<Value, BugError> {
+        match (op, value) {
+            (UnaryOp::TypeOf, value) => Ok(Value::String(value.type_name().into())),
+            (UnaryOp::Negate, Value::Integer(i)) => self.negate(i, "-", span),
+            (UnaryOp::Negate, Value::BigInt(n)) => Ok(integers::normalize(-n.as_ref())),
+            (UnaryOp::BitNot, Value::Integer(i)) => Ok(Value::Integer(!i)),
+            (UnaryOp::BitNot, Value::BigInt(n)) => Ok(integers::normalize(!n.as_ref())),
+            (UnaryOp::Negate, Value::Float(f)) => Ok(Value::Float(-f)),
+            (UnaryOp::Not, Value::Boolean(b)) => Ok(Value::Boolean(!b)),
+            (UnaryOp::Negate, Value::Nil) => {
+                self.check_nil(span)?;
+                Ok(Value::Integer(0))
+            }
+            (UnaryOp::Not, Value::Nil) => {
+                self.check_nil(span)?;
+                Ok(Value::Boolean(true))
             }
-            self.execute_block(&mut body_tokens.iter());
+            (op, value) => Err(BugError::new(
+                ErrorKind::Type,
+                format!("Cannot apply {} to {}", op, value),
+                span,
+            )),
         }
     }
 
-    fn get_var(&mut self, var_name: &str) -> Option<&Variable> {
-        return self.var_manager.get(var_name);
+    pub(crate) fn get_index(
+        &mut self,
+        object: Value,
+        index: Value,
+        span: Span,
+    ) -> Result<Value, BugError> {
+        match (&object, index) {
+            (Value::List(items), Value::Integer(i)) => usize::try_from(i)
+                .ok()
+                .and_then(|i| items.get(i))
+                .cloned()
+                .ok_or_else(|| {
+                    BugError::new(
+                        ErrorKind::Index,
+                        format!(
+                            "Index {} is out of range for a list of length {}",
+                            i,
+                            items.len()
+                        ),
+                        span,
+                    )
+                }),
+            (_, index) => Err(BugError::new(
+                ErrorKind::Type,
+                format!("Cannot index {} with {}", object, index),
+                span,
+            )),
+        }
     }
 
-    fn set_var(&mut self, var_name: &str, value: &str) {
-        if !self.get_var(var_name).is_none() {
-            self.var_manager.assign(var_name, value);
-        } else {
-            match self.var_manager.parse_value(value) {
-                Some(Value::Integer(i)) => {
-                    let variable = Variable {
-                        Value: Value::Integer(i),
-                    };
-                    self.var_manager.define(var_name.to_string(), variable);
+    pub(crate) fn get_property(
+        &mut self,
+        object: Value,
+        name: Symbol,
+        span: Span,
+    ) -> Result<Value, BugError> {
+        if let Value::Module(module) = &object {
+            return match module.exports.get(&name) {
+                Some(value) => Ok(value.clone()),
+                None => Err(BugError::new(
+                    ErrorKind::Name,
+                    format!("Module '{}' does not export '{}'", module.name, name),
+                    span,
+                )),
+            };
+        }
+        if let Value::Error(error) = &object {
+            match name.as_str() {
+                "kind" => return Ok(Value::String(error.kind.name().into())),
+                "message" => return Ok(Value::String(error.message.as_str().into())),
+                "line" => return Ok(Value::Integer(error.span.line as i64)),
+                "column" => return Ok(Value::Integer(error.span.column as i64)),
+                "file" => {
+                    return Ok(match &error.file {
+                        Some(file) => Value::String(file.as_str().into()),
+                        None => Value::Nil,
+                    })
                 }
-                Some(Value::Float(f)) => {
-                    let variable = Variable {
-                        Value: Value::Float(f),
-                    };
-                    self.var_manager.define(var_name.to_string(), variable);
+                // Runtime errors have no thrown value
+                "value" => return Ok(error.value.clone().unwrap_or(Value::Nil)),
+                _ => {}
+            }
+        }
+        Err(BugError::new(
+            ErrorKind::Name,
+            format!("{} has no property '{}'", object, name),
+            span,
+        ))
+    }
+
+    pub(crate) fn arithmetic(
+        &mut self,
+        op: BinaryOp,
+        left: Value,
+        right: Value,
+        span: Span,
+    ) -> Result<Value, BugError> {
+        let (left, right) = match (left, right) {
+            (Value::Nil, Value::Nil) if Self::is_equality(op) => {
+                return Ok(Value::Boolean(op == BinaryOp::Equal))
+            }
+            (Value::Nil, _) | (_, Value::Nil) if Self::is_equality(op) => {
+                return Ok(Value::Boolean(op == BinaryOp::NotEqual))
+            }
+            (Value::Nil, other) => {
+                self.check_nil(span)?;
+                (Self::zero_like(&other), other)
+            }
+            (other, Value::Nil) => {
+                self.check_nil(span)?;
+                let zero = Self::zero_like(&other);
+                (other, zero)
+            }
+            operands => operands,
+        };
+        if Self::is_number(&left) && Self::is_number(&right) {
+            return self.numeric(op, left, right, span);
+        }
+        let result = match (left, right) {
+            (Value::String(a), Value::String(b)) => match op {
+                BinaryOp::Add => {
+                    self.reserve_memory(a.len().saturating_add(b.len()), span)?;
+                    Value::String([&*a, &*b].concat().into())
                 }
-                Some(Value::Boolean(b)) => {
-                    let variable = Variable {
-                        Value: Value::Boolean(b),
-                    };
-                    self.var_manager.define(var_name.to_string(), variable);
+                BinaryOp::Equal
+                | BinaryOp::NotEqual
+                | BinaryOp::Less
+                | BinaryOp::LessEqual
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEqual => Value::Boolean(Self::compare(op, a.cmp(&b))),
+                _ => {
+                    return Err(BugError::new(
+                        ErrorKind::Type,
+                        format!("Cannot apply {} to strings", op),
+                        span,
+                    ))
                 }
-                Some(Value::String(s)) => {
-                    let variable = Variable {
-                        Value: Value::String(s),
-                    };
-                    self.var_manager.define(var_name.to_string(), variable);
+            },
+            (Value::Boolean(a), Value::Boolean(b)) => match op {
+                BinaryOp::Equal => Value::Boolean(a == b),
+                BinaryOp::NotEqual => Value::Boolean(a != b),
+                _ => {
+                    return Err(BugError::new(
+                        ErrorKind::Type,
+                        format!("Cannot apply {} to booleans", op),
+                        span,
+                    ))
                 }
-                None => panic!("Invalid value"),
+            },
+            (left, right) => {
+                return Err(BugError::new(
+                    ErrorKind::Type,
+                    format!("Cannot apply {} to {} and {}", op, left, right),
+                    span,
+                ))
             }
-        }
+        };
+        Ok(result)
     }
 
-    fn handle_variable_update<'a, I>(&mut self, tokens: &mut I)
-    where
-        I: Iterator<Item = &'a Token>,
-    {
-        let mut tokens_collected = Vec::new();
-        while let Some(token) = tokens.next() {
-            tokens_collected.push(token.clone());
-            if let Token::Symbol(';') = token {
-                break;
+    // Integers stay integers where the result is exact; otherwise both sides become floats
+    fn numeric(
+        &mut self,
+        op: BinaryOp,
+        left: Value,
+        right: Value,
+        span: Span,
+    ) -> Result<Value, BugError> {
+        let zero = match right {
+            Value::Integer(i) => i == 0,
+            Value::Float(f) => f == 0.0,
+            _ => false,
+        };
+        // A negative power of zero is one divided by zero
+        let reciprocal_of_zero =
+            op == BinaryOp::Power && Self::to_float(&left) == 0.0 && Self::to_float(&right) < 0.0;
+        if (zero
+            && matches!(
+                op,
+                BinaryOp::Divide | BinaryOp::FloorDivide | BinaryOp::Modulo
+            ))
+            || reciprocal_of_zero
+        {
+            return Err(BugError::new(
+                ErrorKind::ZeroDivision,
+                format!("{} {} {} divides by zero", left, op, right),
+                span,
+            ));
+        }
+        // Comparisons are exact between integers of any size
+        if Self::is_comparison(op) {
+            let ordering = match (&left, &right) {
+                (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+                _ => integers::big_operands(&left, &right).map(|(a, b)| a.cmp(&b)),
+            };
+            if let Some(ordering) = ordering {
+                return Ok(Value::Boolean(Self::compare(op, ordering)));
             }
         }
-
-        match tokens_collected.as_slice() {
-            // Increment operator (++)
-            [Token::Identifier(var_name), Token::Symbol('+'), Token::Symbol('+'), Token::Symbol(';')] => {
-                if let Some(variable) = self.get_var(var_name).cloned() {
-                    match &variable.Value {
-                        Value::Integer(i) => self.set_var(var_name, &(*i + 1).to_string()),
-                        Value::Float(f) => self.set_var(var_name, &(*f + 1.0).to_string()),
-                        _ => panic!("Invalid variable type for increment"),
-                    }
-                } else {
-                    panic!("Tried updating a non-existing variable: {}", var_name);
-                }
+        if Self::is_bitwise(op) {
+            self.check_bitwise(op, &left, &right, span)?;
+        }
+        // `/` always gives a float, as does `**` with a negative exponent
+        let int_op = match (op, &right) {
+            (BinaryOp::Power, Value::Integer(b)) if u32::try_from(*b).is_err() => None,
+            (BinaryOp::Power, Value::BigInt(_)) => None,
+            _ => IntOp::from_binary(op),
+        };
+        if let Some(int_op) = int_op {
+            if let (Value::Integer(a), Value::Integer(b)) = (&left, &right) {
+                return self.integer_op(int_op, *a, *b, span);
             }
-            // Decrement operator (--)
-            [Token::Identifier(var_name), Token::Symbol('-'), Token::Symbol('-'), Token::Symbol(';')] => {
-                if let Some(variable) = self.get_var(var_name).cloned() {
-                    match &variable.Value {
-                        Value::Integer(i) => self.set_var(var_name, &(*i - 1).to_string()),
-                        Value::Float(f) => self.set_var(var_name, &(*f - 1.0).to_string()),
-                        _ => panic!("Invalid variable type for decrement"),
-                    }
+            if let Some((a, b)) = integers::big_operands(&left, &right) {
+                self.reserve_big(int_op, &a, &b, span)?;
+                return integers::apply_big(int_op, &a, &b)
+                    .map_err(|message| BugError::new(ErrorKind::Overflow, message, span));
+            }
+        }
+
+        let (a, b) = (Self::to_float(&left), Self::to_float(&right));
+        Ok(match op {
+            BinaryOp::Add => Value::Float(a + b),
+            BinaryOp::Subtract => Value::Float(a - b),
+            BinaryOp::Multiply => Value::Float(a * b),
+            BinaryOp::Divide => Value::Float(a / b),
+            BinaryOp::FloorDivide => Value::Float((a / b).floor()),
+            BinaryOp::Modulo => {
+                // Like integer `%`, the result takes the sign of the divisor
+                let remainder = a % b;
+                if remainder != 0.0 && (remainder < 0.0) != (b < 0.0) {
+                    Value::Float(remainder + b)
                 } else {
-                    panic!("Tried updating a non-existing variable: {}", var_name);
+                    Value::Float(remainder)
                 }
             }
-            // Arithmetic update with `+` or `-`
-            [Token::Identifier(var_name), Token::Symbol(op), Token::Number(amount), Token::Symbol(';')]
-            if *op == '+' || *op == '-' =>
-                {
-                    if let Some(variable) = self.get_var(var_name).clone() {
-                        match &variable.Value {
-                            Value::Integer(i) => {
-                                let new_value = if *op == '+' { *i + amount } else { *i - amount };
-                                self.set_var(var_name, &new_value.to_string());
-                            }
-                            Value::Float(f) => {
-                                let new_value = if *op == '+' { *f + *amount as f64 } else { *f - *amount as f64 };
-                                self.set_var(var_name, &new_value.to_string());
-                            }
-                            _ => panic!("Invalid variable type for arithmetic update"),
-                        }
-                    } else {
-                        panic!("Tried updating a non-existing variable: {}", var_name);
-                    }
-                }
-            [Token::Identifier(var_name), Token::Symbol(op), Token::Float(amount), Token::Symbol(';')]
-            if *op == '+' || *op == '-' =>
-                {
-                    if let Some(variable) = self.get_var(var_name).clone() {
-                        match &variable.Value {
-                            Value::Float(f) => {
-                                let new_value = if *op == '+' { *f + amount } else { *f - amount };
-                                self.set_var(var_name, &new_value.to_string());
-                            }
-                            _ => panic!("Invalid variable type for arithmetic update with float"),
-                        }
-                    } else {
-                        panic!("Tried updating a non-existing variable: {}", var_name);
-                    }
-                }
-            // General variable assignment (`=`)
-            [Token::Identifier(var_name), Token::Operator(op), value_token, Token::Symbol(';')]
-            if op == "=" =>
-                {
-                    let value_str = match value_token {
-                        Token::Number(n) => n.to_string(),
-                        Token::Float(f) => f.to_string(),
-                        Token::StringLiteral(s) => s.to_string(), // Ensure strings are quoted
-                        Token::Boolean(b) => b.to_string(),
-                        _ => panic!("Unsupported value type in assignment"),
-                    };
-
-                    self.set_var(var_name, &value_str);
-                }
-            _ => panic!("Invalid variable update syntax: {:?}", tokens_collected),
+            BinaryOp::Power => Value::Float(a.powf(b)),
+            _ => match a.partial_cmp(&b) {
+                Some(ordering) => Value::Boolean(Self::compare(op, ordering)),
+                None => Value::Boolean(op == BinaryOp::NotEqual),
+            },
+        })
+    }
+
+    // Bitwise operators only take integers, and shifts a width of zero or more
+    fn check_bitwise(
+        &self,
+        op: BinaryOp,
+        left: &Value,
+        right: &Value,
+        span: Span,
+    ) -> Result<(), BugError> {
+        if matches!(left, Value::Float(_)) || matches!(right, Value::Float(_)) {
+            return Err(BugError::new(
+                ErrorKind::Type,
+                format!("Cannot apply {} to {} and {}", op, left, right),
+                span,
+            ));
+        }
+        if !matches!(op, BinaryOp::ShiftLeft | BinaryOp::ShiftRight) {
+            return Ok(());
+        }
+        match right {
+            Value::Integer(width) if *width < 0 => Err(BugError::new(
+                ErrorKind::Value,
+                format!("Cannot shift by a negative width: {}", width),
+                span,
+            )),
+            Value::Integer(width) if u32::try_from(*width).is_ok() => Ok(()),
+            _ => Err(BugError::new(
+                ErrorKind::Overflow,
+                format!("Shift width {} is too large", right),
+                span,
+            )),
         }
     }
 
-    fn handle_for<'a, I>(&mut self, tokens: &mut I)
-    where
-        I: Iterator<Item = &'a Token>,
-    {
-        // Parse initialization, condition, and update sections
-        let (init_tokens, condition_tokens, update_tokens) = self.parse_for_header(tokens);
+    fn is_bitwise(op: BinaryOp) -> bool {
+        matches!(
+            op,
+            BinaryOp::BitAnd
+                | BinaryOp::BitOr
+                | BinaryOp::BitXor
+                | BinaryOp::ShiftLeft
+                | BinaryOp::ShiftRight
+        )
+    }
 
-        // Parse the body
-        let body_tokens = self.parse_block(tokens);
+    fn is_number(value: &Value) -> bool {
+        matches!(
+            value,
+            Value::Integer(_) | Value::BigInt(_) | Value::Float(_)
+        )
+    }
 
-        if !init_tokens.is_empty() {
-            self.process_tokens(&init_tokens, "initialization");
+    fn to_float(value: &Value) -> f64 {
+        match value {
+            Value::Integer(i) => *i as f64,
+            Value::BigInt(n) => n.to_f64().unwrap_or(f64::NAN),
+            Value::Float(f) => *f,
+            _ => f64::NAN,
         }
+    }
 
-        // Execute the for loop
-        loop {
-            // Evaluate the condition dynamically on each iteration
-            let condition_result = self
-                .evaluate_condition(&mut condition_tokens.clone().into_iter())
-                .unwrap_or(false);
+    // An integer operation under the configured `Overflow` mode
+    pub fn integer_op(&self, op: IntOp, a: i64, b: i64, span: Span) -> Result<Value, BugError> {
+        // Only these can give a result much larger than an i64
+        if self.options.overflow == Overflow::Big && matches!(op, IntOp::Power | IntOp::ShiftLeft) {
+            self.reserve_big(op, &BigInt::from(a), &BigInt::from(b), span)?;
+        }
+        integers::apply(op, a, b, self.options.overflow)
+            .map_err(|message| BugError::new(ErrorKind::Overflow, message, span))
+    }
 
-            // Break the loop if the condition is false
-            if !condition_result {
-                break;
-            }
+    // Reserves the memory a big integer result could take
+    pub fn reserve_big(
+        &self,
+        op: IntOp,
+        a: &BigInt,
+        b: &BigInt,
+        span: Span,
+    ) -> Result<(), BugError> {
+        match integers::result_bits(op, a, b) {
+            Some(bits) => self.reserve_memory((bits / 8) as usize, span),
+            // Too large to work out, which `apply_big` refuses
+            None => Ok(()),
+        }
+    }
 
-            // Execute the block
-            self.execute_block(&mut body_tokens.iter());
+    // `-i`, which only overflows for the smallest integer. The error shows the operation, a minus
+    // sign or a builtin's name, applied to `i`.
+    pub fn negate(&self, i: i64, operation: &str, span: Span) -> Result<Value, BugError> {
+        integers::apply(IntOp::Subtract, 0, i, self.options.overflow).map_err(|_| {
+            BugError::new(
+                ErrorKind::Overflow,
+                format!("{}({}) overflows", operation, i),
+                span,
+            )
+        })
+    }
 
-            // Process update
-            if !update_tokens.is_empty() {
-                self.process_tokens(&update_tokens, "update");
-            }
-        }
-    }
-
-    fn parse_for_header<'a, I>(&mut self, tokens: &mut I) -> (Vec<Token>, Vec<Token>, Vec<Token>)
-    where
-        I: Iterator<Item = &'a Token>,
-    {
-        let mut init_tokens = Vec::new();
-        let mut condition_tokens = Vec::new();
-        let mut update_tokens = Vec::new();
-        let mut stage = 0;
-
-        while let Some(token) = tokens.next() {
-            match token {
-                Token::Symbol(';') => stage += 1,
-                Token::Symbol('(') | Token::Symbol(')') => continue,
-                Token::Symbol('{') => break, // End of the header
-                _ => match stage {
-                    0 => init_tokens.push(token.clone()),
-                    1 => condition_tokens.push(token.clone()),
-                    2 => update_tokens.push(token.clone()),
-                    _ => panic!("Unexpected token in for loop header: {:?}", token),
-                },
+    fn is_comparison(op: BinaryOp) -> bool {
+        matches!(
+            op,
+            BinaryOp::Equal
+                | BinaryOp::NotEqual
+                | BinaryOp::Less
+                | BinaryOp::LessEqual
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEqual
+        )
+    }
+
+    fn is_equality(op: BinaryOp) -> bool {
+        matches!(op, BinaryOp::Equal | BinaryOp::NotEqual)
+    }
+
+    // The value nil is coerced to when it meets `other` in arithmetic
+    fn zero_like(other: &Value) -> Value {
+        match other {
+            Value::Float(_) => Value::Float(0.0),
+            Value::String(_) => Value::String("".into()),
+            Value::Boolean(_) => Value::Boolean(false),
+            _ => Value::Integer(0),
+        }
+    }
+
+    // Reports a nil operand according to the configured `NilMode`
+    fn check_nil(&self, span: Span) -> Result<(), BugError> {
+        match self.options.nil_mode {
+            NilMode::Coerce => Ok(()),
+            NilMode::Warn => {
+                eprintln!(
+                    "Warning: nil used as an operand (line {}, column {})",
+                    span.line, span.column
+                );
+                Ok(())
             }
+            NilMode::Strict => Err(BugError::new(
+                ErrorKind::Type,
+                "nil used as an operand",
+                span,
+            )),
         }
+    }
 
-        (init_tokens, condition_tokens, update_tokens)
+    fn compare(op: BinaryOp, ordering: std::cmp::Ordering) -> bool {
+        match op {
+            BinaryOp::Equal => ordering.is_eq(),
+            BinaryOp::NotEqual => ordering.is_ne(),
+            BinaryOp::Less => ordering.is_lt(),
+            BinaryOp::LessEqual => ordering.is_le(),
+            BinaryOp::Greater => ordering.is_gt(),
+            BinaryOp::GreaterEqual => ordering.is_ge(),
+            _ => unreachable!("{:?} is not a comparison", op),
+        }
     }
 
-    fn parse_block<'a, I>(&mut self, tokens: &mut I) -> Vec<Token>
-    where
-        I: Iterator<Item = &'a Token>,
-    {
-        let mut block_tokens = Vec::new();
-        let mut open_braces = 1; // We've already seen the opening `{`
+    fn make_function(&self, decl: &Rc<FunctionDecl>) -> Value {
+        Value::Function(Rc::new(Function {
+            decl: Rc::clone(decl),
+            closure: self.var_manager.capture(),
+        }))
+    }
 
-        while let Some(token) = tokens.next() {
-            match token {
-                Token::Symbol('{') => open_braces += 1,
-                Token::Symbol('}') => {
-                    open_braces -= 1;
-                    if open_braces == 0 {
-                        break; // End of block
-                    }
-                }
-                _ => {}
+    fn call(&mut self, callee: &Expr, args: &[Expr], span: Span) -> Result<Value, BugError> {
+        let function = match self.evaluate(callee)? {
+            Value::Function(function) => function,
+            Value::Native(native) => return self.call_native(native, args, span),
+            other => {
+                return Err(BugError::new(
+                    ErrorKind::Type,
+                    format!("{} is not a function", other),
+                    span,
+                ))
             }
-            block_tokens.push(token.clone());
+        };
+        if args.len() != function.decl.params.len() {
+            return Err(BugError::new(
+                ErrorKind::Type,
+                format!(
+                    "{} expects {} arguments but got {}",
+                    Value::Function(Rc::clone(&function)),
+                    function.decl.params.len(),
+                    args.len()
+                ),
+                span,
+            ));
+        }
+        let mut values = Vec::with_capacity(args.len());
+        for (param, arg) in function.decl.params.iter().zip(args) {
+            let value = self.evaluate(arg)?;
+            values.push(Self::conform(
+                value,
+                param.ty,
+                param.name.as_str(),
+                arg.span,
+            )?);
         }
 
-        block_tokens
+        self.enter_call(span)?;
+        let caller_scopes = self.var_manager.enter(&function.closure);
+        for (slot, (param, value)) in function.decl.params.iter().zip(values).enumerate() {
+            self.var_manager.define(
+                slot,
+                Variable {
+                    value,
+                    constant: false,
+                    ty: param.ty,
+                },
+            );
+        }
+        // Restore the caller's scopes before propagating any error
+        let flow = self.execute_block(&function.decl.body);
+        self.var_manager.restore(caller_scopes);
+        self.leave_call();
+
+        let value = match flow? {
+            Flow::Return(value) => value,
+            Flow::Normal => Value::Nil,
+        };
+        if function.decl.return_type.is_none() {
+            return Ok(value);
+        }
+        let what = format!("The result of {}", Value::Function(Rc::clone(&function)));
+        Self::conform(value, function.decl.return_type, &what, span)
     }
 
-    fn process_tokens(&mut self, tokens: &[Token], context: &str) {
-        match tokens {
-            [Token::Identifier(var), Token::Operator(ref op), Token::Number(value)]
-                if op == "=" =>
-            {
-                self.set_var(var, value.to_string().as_str());
-            }
-            [Token::Identifier(var), Token::Symbol('+'), Token::Operator(ref op), Token::Number(value)]
-                if op == "=" =>
-            {
-                if let Some(existing_value) = self.get_var(var.clone().as_str()) {
-                    if let Value::Integer(existing_int) = existing_value.Value {
-                        self.set_var(var, (existing_int + value).to_string().as_str());
-                    } else {
-                        panic!("Variable {} is not an integer", var);
-                    }
-                } else {
-                    panic!("Variable {} not found", var);
-                }
-            }
-            // Handle other patterns (if needed)
-            _ => panic!("Invalid token pattern for {}: {:?}", context, tokens),
+    fn call_native(
+        &mut self,
+        native: Native,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<Value, BugError> {
+        Self::check_arity(native, args.len(), span)?;
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.evaluate(arg)?);
         }
+        (native.function)(self, &values, span)
+    }
+
+    pub(crate) fn check_arity(native: Native, count: usize, span: Span) -> Result<(), BugError> {
+        if count < native.min_args || count > native.max_args {
+            let noun = if native.min_args == 1 {
+                "argument"
+            } else {
+                "arguments"
+            };
+            let expected = if native.max_args == stdlib::VARIADIC {
+                format!("at least {} {}", native.min_args, noun)
+            } else if native.min_args == native.max_args {
+                format!("{} {}", native.min_args, noun)
+            } else {
+                format!("{} to {} arguments", native.min_args, native.max_args)
+            };
+            return Err(BugError::new(
+                ErrorKind::Type,
+                format!("{}() expects {} but got {}", native.name, expected, count),
+                span,
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn var_manager(&mut self) -> &mut VarManager {
+        &mut self.var_manager
+    }
+
+    fn get_var(&mut self, slot: Slot) -> Option<Variable> {
+        self.var_manager.get(slot)
+    }
+
+    fn set_var(
+        &mut self,
+        var_name: Symbol,
+        slot: usize,
+        value: Value,
+        span: Span,
+    ) -> Result<(), BugError> {
+        self.declare(var_name, slot, value, false, None, span)
+    }
+
+    pub(crate) fn declare(
+        &mut self,
+        var_name: Symbol,
+        slot: usize,
+        value: Value,
+        constant: bool,
+        ty: Option<Type>,
+        span: Span,
+    ) -> Result<(), BugError> {
+        let local = Slot {
+            depth: 0,
+            index: slot,
+        };
+        // Declaring an annotated variable again without a type assigns to it
+        let value = match self.var_manager.annotation(local) {
+            Some(declared) if ty.is_none() && !constant => {
+                Self::conform(value, Some(declared), var_name.as_str(), span)?
+            }
+            _ => value,
+        };
+        let found = value.type_name();
+        self.var_manager
+            .declare(slot, value, constant, ty)
+            .map_err(|error| match error {
+                AssignError::Type(held) => Self::mismatched(var_name, held, found, span),
+                _ => Self::redeclared(var_name, span),
+            })
+    }
+
+    // Binds a caught error to its catch clause's slot, which belongs to the clause alone, so
+    // whatever the previous error left there is simply replaced
+    pub(crate) fn bind_caught(&mut self, slot: usize, value: Value) {
+        self.var_manager.define(
+            slot,
+            Variable {
+                value,
+                constant: false,
+                ty: None,
+            },
+        );
+    }
+
+    pub(crate) fn redeclared(var_name: Symbol, span: Span) -> BugError {
+        BugError::new(
+            ErrorKind::Type,
+            format!("Cannot redeclare constant {}", var_name),
+            span,
+        )
+    }
+
+    // A variable declared without a type keeps the type of its value, and a new value that cannot
+    // be stored as that type is refused
+    fn mismatched(var_name: Symbol, held: &str, found: &str, span: Span) -> BugError {
+        BugError::new(
+            ErrorKind::Type,
+            format!(
+                "{} holds {} and cannot be updated with {}",
+                var_name, held, found
+            ),
+            span,
+        )
     }
-    
 }
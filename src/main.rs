@@ -1,8 +1,23 @@
 mod interpreter;
+mod stdlib;
 mod utils;
-use clap::{Arg, Command};
+mod vm;
+use clap::{Arg, ArgAction, Command};
 use interpreter::Interpreter;
-use std::{env, fs};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs, process, thread};
+use utils::ast::Script;
+use utils::errors::BugError;
+use utils::options::{MathDomain, NilMode, Options, Overflow};
+use utils::sandbox::{FileAccess, SandboxPolicy};
+use utils::{checker, printer};
+use vm::{Compiler, Vm};
+
+// Room for about ten times the default call depth of a debug build's tree-walker. It is only
+// reserved, so pages the script never reaches are never used.
+const SCRIPT_STACK_SIZE: usize = 1 << 30;
 
 fn main() {
     // Define the CLI arguments and subcommands
@@ -17,27 +32,398 @@ fn main() {
                 .help("Sets an input file")
                 .required(false), // Make it optiona
         )
+        .arg(
+            Arg::new("stdin")
+                .long("stdin")
+                .value_name("FILE")
+                .help("Reads the script's input from FILE instead of the terminal"),
+        )
+        .arg(
+            Arg::new("fs")
+                .long("fs")
+                .value_name("ACCESS")
+                .value_parser(["off", "read", "full"])
+                .help("Which file builtins scripts may use, overriding the sandbox policy (default: off)"),
+        )
+        .arg(
+            Arg::new("fs-root")
+                .long("fs-root")
+                .value_name("DIR")
+                .help("Limits file builtins to paths inside DIR, overriding the sandbox policy"),
+        )
+        .arg(
+            Arg::new("sandbox")
+                .long("sandbox")
+                .value_name("POLICY")
+                .help("Reads what scripts may do from a TOML or JSON policy file (default: no files, environment, processes or clock)"),
+        )
+        .arg(
+            Arg::new("nil-mode")
+                .long("nil-mode")
+                .value_name("MODE")
+                .value_parser(["coerce", "warn", "strict"])
+                .help("How nil is treated in arithmetic and conditions"),
+        )
+        .arg(
+            Arg::new("math-domain")
+                .long("math-domain")
+                .value_name("POLICY")
+                .value_parser(["nan", "error"])
+                .help("Whether math functions return NaN or raise an error outside their domain"),
+        )
+        .arg(
+            Arg::new("overflow")
+                .long("overflow")
+                .value_name("MODE")
+                .value_parser(["checked", "wrapping", "saturating", "big"])
+                .help("What happens when an integer result does not fit in 64 bits (default: checked)"),
+        )
+        .arg(
+            Arg::new("engine")
+                .long("engine")
+                .value_name("ENGINE")
+                .value_parser(["tree", "vm"])
+                .help("Runs scripts by walking the syntax tree or by compiling them to bytecode (default: tree)"),
+        )
+        .arg(
+            Arg::new("max-steps")
+                .long("max-steps")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64))
+                .help("Stops scripts with a BudgetExceeded error after N steps"),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .value_name("SECONDS")
+                .value_parser(parse_timeout)
+                .help("Stops scripts with a BudgetExceeded error once they have run this long"),
+        )
+        .arg(
+            Arg::new("max-memory")
+                .long("max-memory")
+                .value_name("SIZE")
+                .value_parser(parse_size)
+                .help("Raises a MemoryLimitExceeded error when scripts hold more than SIZE bytes (K, M and G suffixes allowed)"),
+        )
+        .arg(
+            Arg::new("max-depth")
+                .long("max-depth")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Raises a RecursionError when more than N function calls are running at once (default: 10000)"),
+        )
+        .arg(
+            Arg::new("optimize")
+                .short('O')
+                .long("optimize")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("Simplifies scripts before running or compiling them"),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Checks a file for undefined names and type errors without running it")
+                .arg(Arg::new("file").value_name("FILE").required(true))
+                .arg(
+                    Arg::new("show-types")
+                        .long("show-types")
+                        .action(ArgAction::SetTrue)
+                        .help("Prints the type of every declaration"),
+                ),
+        )
+        .subcommand(
+            Command::new("compile")
+                .about("Compiles a file to bytecode that runs without being parsed again")
+                .arg(Arg::new("file").value_name("FILE").required(true))
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("OUT")
+                        .help("Where to write the bytecode (default: FILE with a .bugc extension)"),
+                ),
+        )
+        .subcommand(
+            Command::new("disasm")
+                .about("Prints the bytecode of a compiled file, or of a source file compiled on the spot")
+                .arg(Arg::new("file").value_name("FILE").required(true)),
+        )
+        .subcommand(
+            Command::new("dump")
+                .about("Prints a file as it is run, after any optimization given with -O")
+                .arg(Arg::new("file").value_name("FILE").required(true)),
+        )
         .get_matches();
+    let optimize = matches.get_flag("optimize");
+    let nil_mode = match matches.get_one::<String>("nil-mode").map(String::as_str) {
+        Some("warn") => NilMode::Warn,
+        Some("strict") => NilMode::Strict,
+        _ => NilMode::Coerce,
+    };
+    // What `-O` simplifies a script for, when it is not run here
+    let parsing = Options {
+        optimize,
+        nil_mode,
+        ..Options::default()
+    };
+    if let Some(check) = matches.subcommand_matches("check") {
+        let path = check.get_one::<String>("file").expect("FILE is required");
+        run_check(Path::new(path), check.get_flag("show-types"));
+        return;
+    }
+    if let Some(compile) = matches.subcommand_matches("compile") {
+        let path = Path::new(compile.get_one::<String>("file").expect("FILE is required"));
+        let output = match compile.get_one::<String>("output") {
+            Some(output) => PathBuf::from(output),
+            None => path.with_extension("bugc"),
+        };
+        run_compile(path, &output, &parsing);
+        return;
+    }
+    if let Some(disasm) = matches.subcommand_matches("disasm") {
+        let path = disasm.get_one::<String>("file").expect("FILE is required");
+        run_disasm(Path::new(path), &parsing);
+        return;
+    }
+    if let Some(dump) = matches.subcommand_matches("dump") {
+        let path = dump.get_one::<String>("file").expect("FILE is required");
+        run_dump(Path::new(path), &parsing);
+        return;
+    }
     let arg = matches.args_present();
     if !arg {
         println!("This is the cli tool for bugland, cause im bored")
     } else {
-        if let Some(input) = matches.get_one::<String>("input") {
-            let path = matches.get_one::<String>("input");
-            if let Some(path_str) = path {
-                let current_dir = env::current_dir().expect("Failed to get current directory");
-                let relative_path = current_dir.join(path_str);
-
-                if fs::metadata(&relative_path).is_ok() {
-                    let interpreter = &mut Interpreter::new();
-                    let contents = fs::read_to_string(path_str).expect("Failed to read test file");
-                    interpreter.interpret(&contents);
-                } else {
-                    panic!("File not found.");
+        let path = matches.get_one::<String>("input");
+        if let Some(path_str) = path {
+            let current_dir = env::current_dir().expect("Failed to get current directory");
+            let relative_path = current_dir.join(path_str);
+
+            if fs::metadata(&relative_path).is_ok() {
+                let math_domain = match matches.get_one::<String>("math-domain").map(String::as_str)
+                {
+                    Some("error") => MathDomain::Error,
+                    _ => MathDomain::Nan,
+                };
+                let overflow = match matches.get_one::<String>("overflow").map(String::as_str) {
+                    Some("wrapping") => Overflow::Wrapping,
+                    Some("saturating") => Overflow::Saturating,
+                    Some("big") => Overflow::Big,
+                    _ => Overflow::Checked,
+                };
+                let mut sandbox = match matches.get_one::<String>("sandbox") {
+                    Some(policy_path) => match SandboxPolicy::load(Path::new(policy_path)) {
+                        Ok(policy) => policy,
+                        Err(problem) => {
+                            eprintln!("Cannot load sandbox policy {}: {}", policy_path, problem);
+                            process::exit(1);
+                        }
+                    },
+                    None => SandboxPolicy::default(),
+                };
+                match matches.get_one::<String>("fs").map(String::as_str) {
+                    Some("off") => sandbox.files = FileAccess::Off,
+                    Some("read") => sandbox.files = FileAccess::ReadOnly,
+                    Some("full") => sandbox.files = FileAccess::Full,
+                    _ => {}
+                }
+                if let Some(root) = matches.get_one::<String>("fs-root") {
+                    sandbox.file_root = Some(PathBuf::from(root));
+                }
+                let options = Options {
+                    nil_mode,
+                    math_domain,
+                    overflow,
+                    sandbox,
+                    optimize,
+                    max_steps: matches.get_one::<u64>("max-steps").copied(),
+                    timeout: matches.get_one::<Duration>("timeout").copied(),
+                    max_memory: matches.get_one::<usize>("max-memory").copied(),
+                    max_depth: matches.get_one::<usize>("max-depth").copied(),
+                };
+                let input =
+                    matches.get_one::<String>("stdin").map(|stdin_path| {
+                        match fs::File::open(stdin_path) {
+                            Ok(file) => BufReader::new(file),
+                            Err(error) => {
+                                eprintln!("Cannot open {}: {}", stdin_path, error);
+                                process::exit(1);
+                            }
+                        }
+                    });
+                let path = Path::new(path_str);
+                let compiled = fs::read(path).ok().filter(|bytes| vm::is_compiled(bytes));
+                // Compiled files can only be run by the VM
+                let engine = matches.get_one::<String>("engine").map(String::as_str);
+                let use_vm = engine == Some("vm");
+                let path = path.to_path_buf();
+                let result = run_script(move || match compiled {
+                    Some(bytes) => {
+                        let vm = &mut Vm::new(options);
+                        if let Some(input) = input {
+                            vm.set_input(Box::new(input));
+                        }
+                        vm.run_compiled(&path, &bytes)
+                    }
+                    None if use_vm => {
+                        let vm = &mut Vm::new(options);
+                        if let Some(input) = input {
+                            vm.set_input(Box::new(input));
+                        }
+                        vm.run_file(&path)
+                    }
+                    None => {
+                        let interpreter = &mut Interpreter::new(options);
+                        if let Some(input) = input {
+                            interpreter.set_input(Box::new(input));
+                        }
+                        interpreter.interpret_file(&path)
+                    }
+                });
+                if let Err(error) = result {
+                    eprintln!("{}", error);
+                    process::exit(1);
                 }
             } else {
-                panic!("No input file provided.");
+                panic!("File not found.");
             }
+        } else {
+            panic!("No input file provided.");
+        }
+    }
+}
+
+// Runs a script on a thread of its own, whose stack leaves the tree-walker room to nest function
+// calls as deeply as `--max-depth` allows. Errors come back as their message, since the values
+// they may hold stay on that thread.
+fn run_script(
+    script: impl FnOnce() -> Result<(), BugError> + Send + 'static,
+) -> Result<(), String> {
+    thread::Builder::new()
+        .stack_size(SCRIPT_STACK_SIZE)
+        .spawn(|| script().map_err(|error| error.to_string()))
+        .expect("cannot start the script's thread")
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+// A timeout in seconds, which may have a fractional part
+fn parse_timeout(seconds: &str) -> Result<Duration, String> {
+    seconds
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("'{}' is not a number of seconds", seconds))
+}
+
+// A number of bytes, optionally in kilobytes, megabytes or gigabytes as `64K`, `16M` or `1G`
+fn parse_size(size: &str) -> Result<usize, String> {
+    let (digits, unit) = match size.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&size[..index], 1 << 10),
+        Some((index, 'M' | 'm')) => (&size[..index], 1 << 20),
+        Some((index, 'G' | 'g')) => (&size[..index], 1 << 30),
+        _ => (size, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|count| count.checked_mul(unit))
+        .ok_or_else(|| format!("'{}' is not a size in bytes", size))
+}
+
+// Reports every undefined name and type error in a file, exiting with an error status if there were any
+fn run_check(path: &Path, show_types: bool) {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Cannot read {}: {}", path.display(), error);
+            process::exit(1);
+        }
+    };
+    let report = match checker::check_source(&source) {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("{}", error.in_file(path));
+            process::exit(1);
+        }
+    };
+    if show_types {
+        let mut types = report.types;
+        types.sort_by_key(|(span, _)| (span.line, span.column));
+        for (span, text) in types {
+            println!("{}:{}:{}: {}", path.display(), span.line, span.column, text);
+        }
+    }
+    let errors = report.errors;
+    for error in &errors {
+        eprintln!("{}", error.clone().in_file(path));
+    }
+    if !errors.is_empty() {
+        eprintln!("{} error(s) found", errors.len());
+        process::exit(1);
+    }
+    println!("No errors found in {}", path.display());
+}
+
+// Parses and checks a file, optimizing it if asked, exiting with an error status if it has any
+// errors
+fn parse_file(path: &Path, options: &Options) -> Script {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Cannot read {}: {}", path.display(), error);
+            process::exit(1);
         }
+    };
+    match Interpreter::parse(&source, options) {
+        Ok(script) => script,
+        Err(error) => {
+            eprintln!("{}", error.in_file(path));
+            process::exit(1);
+        }
+    }
+}
+
+// Writes a file's bytecode to `output`
+fn run_compile(path: &Path, output: &Path, options: &Options) {
+    let program = Compiler::compile(&parse_file(path, options));
+    let bytes = vm::encode(&program, &path.display().to_string());
+    if let Err(error) = fs::write(output, bytes) {
+        eprintln!("Cannot write {}: {}", output.display(), error);
+        process::exit(1);
     }
+    println!("Compiled {} to {}", path.display(), output.display());
+}
+
+// Lists the bytecode of a `.bugc` file, or of a source file after compiling it
+fn run_disasm(path: &Path, options: &Options) {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            eprintln!("Cannot read {}: {}", path.display(), error);
+            process::exit(1);
+        }
+    };
+    let listing = if vm::is_compiled(&bytes) {
+        match vm::decode(&bytes) {
+            Ok((program, source)) => vm::disassemble(&program, &source),
+            Err(problem) => {
+                eprintln!("{} {}", path.display(), problem);
+                process::exit(1);
+            }
+        }
+    } else {
+        let program = Compiler::compile(&parse_file(path, options));
+        vm::disassemble(&program, &path.display().to_string())
+    };
+    print!("{}", listing);
+}
+
+// Prints a file back out as source, as it would run
+fn run_dump(path: &Path, options: &Options) {
+    print!(
+        "{}",
+        printer::program(&parse_file(path, options).statements)
+    );
 }
@@ -0,0 +1,126 @@
+// Bit counting and integers written in other bases. Negative numbers are written with a leading
+// minus sign rather than in two's complement, so every result reads back with `parse_int`.
+use super::{integer_arg, string_arg, Native};
+use crate::interpreter::Interpreter;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::integers;
+use crate::utils::options::Overflow;
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+use num_bigint::{BigInt, Sign};
+
+pub const NATIVES: &[Native] = &[
+    Native::new("popcount", 1, 1, Type::Int, popcount),
+    Native::new("to_hex", 1, 2, Type::String, to_hex),
+    Native::new("to_bin", 1, 2, Type::String, to_bin),
+    Native::new("parse_int", 1, 2, Type::Int, parse_int),
+];
+
+fn big_arg(native: &str, args: &[Value], index: usize, span: Span) -> Result<BigInt, BugError> {
+    match &args[index] {
+        Value::BigInt(n) => Ok(n.as_ref().clone()),
+        _ => Ok(BigInt::from(integer_arg(native, args, index, span)?)),
+    }
+}
+
+// The number of one bits in the magnitude, so `popcount(-5)` is 2
+fn popcount(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let n = big_arg("popcount", args, 0, span)?;
+    Ok(Value::Integer(n.magnitude().count_ones() as i64))
+}
+
+fn to_hex(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    to_radix("to_hex", 16, args, span)
+}
+
+fn to_bin(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    to_radix("to_bin", 2, args, span)
+}
+
+// The widest to_bin() and to_hex() will pad to. Padding only lines numbers up, and 4096 is far more
+// than the 64 binary digits of any ordinary integer, while a much larger width would let one call
+// build a string of any size. Numbers with more digits, which `--overflow big` allows, are written
+// out in full and just not padded.
+const MAX_WIDTH: i64 = 4096;
+
+// The digits without a prefix, zero-padded to the optional width (not counting a minus sign)
+fn to_radix(native: &str, radix: u32, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let n = big_arg(native, args, 0, span)?;
+    let width = match args.get(1) {
+        Some(_) => integer_arg(native, args, 1, span)?,
+        None => 0,
+    };
+    if !(0..=MAX_WIDTH).contains(&width) {
+        return Err(BugError::new(
+            ErrorKind::Value,
+            format!(
+                "{}() width must be between 0 and {}, got {}",
+                native, MAX_WIDTH, width
+            ),
+            span,
+        ));
+    }
+    let digits = n.magnitude().to_str_radix(radix);
+    let mut text = String::new();
+    if n.sign() == Sign::Minus {
+        text.push('-');
+    }
+    for _ in digits.len()..width as usize {
+        text.push('0');
+    }
+    text.push_str(&digits);
+    Ok(Value::String(text.into()))
+}
+
+// An integer in the given radix (10 by default), with an optional sign and, for radix 16, 8 or 2,
+// an optional 0x, 0o or 0b prefix
+fn parse_int(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let text = string_arg("parse_int", args, 0, span)?;
+    let radix = match args.get(1) {
+        Some(_) => integer_arg("parse_int", args, 1, span)?,
+        None => 10,
+    };
+    if !(2..=36).contains(&radix) {
+        return Err(BugError::new(
+            ErrorKind::Value,
+            format!("parse_int() radix must be between 2 and 36, got {}", radix),
+            span,
+        ));
+    }
+    let radix = radix as u32;
+    let invalid = || {
+        BugError::new(
+            ErrorKind::Value,
+            format!("parse_int() cannot parse {:?} in base {}", text, radix),
+            span,
+        )
+    };
+    let trimmed = text.trim();
+    let (negative, unsigned) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let prefix = match radix {
+        16 => Some("0x"),
+        8 => Some("0o"),
+        2 => Some("0b"),
+        _ => None,
+    };
+    let digits = prefix
+        .and_then(|prefix| unsigned.strip_prefix(prefix))
+        .unwrap_or(unsigned);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err(invalid());
+    }
+    let n = BigInt::parse_bytes(digits.as_bytes(), radix).ok_or_else(invalid)?;
+    let n = if negative { -n } else { n };
+    match integers::normalize(n) {
+        Value::BigInt(n) if interpreter.options().overflow != Overflow::Big => Err(BugError::new(
+            ErrorKind::Overflow,
+            format!("parse_int() result is too large: {}", n),
+            span,
+        )),
+        value => Ok(value),
+    }
+}
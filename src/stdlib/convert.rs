@@ -0,0 +1,91 @@
+// Explicit conversions between the basic types. Anything without an obvious meaning raises an
+// error rather than guessing.
+use super::Native;
+use crate::interpreter::Interpreter;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::integers;
+use crate::utils::options::Overflow;
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+
+pub const NATIVES: &[Native] = &[
+    Native::new("int", 1, 1, Type::Int, int),
+    Native::new("float", 1, 1, Type::Float, float),
+    Native::new("str", 1, 1, Type::String, str),
+    Native::new("bool", 1, 1, Type::Bool, bool),
+];
+
+fn cannot_convert(name: &str, value: &Value, span: Span) -> BugError {
+    let kind = match value {
+        // A string of the wrong form is a bad value; anything else is the wrong type entirely
+        Value::String(_) | Value::Float(_) => ErrorKind::Value,
+        _ => ErrorKind::Type,
+    };
+    let shown = match value {
+        Value::String(s) => format!("{:?}", s),
+        other => other.to_string(),
+    };
+    BugError::new(kind, format!("{}() cannot convert {}", name, shown), span)
+}
+
+// Floats are truncated towards zero; strings must hold a whole number
+fn int(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    match &args[0] {
+        Value::Integer(_) | Value::BigInt(_) => Ok(args[0].clone()),
+        Value::Float(f) if f.is_finite() && *f >= i64::MIN as f64 && *f < i64::MAX as f64 => {
+            Ok(Value::Integer(f.trunc() as i64))
+        }
+        Value::Boolean(b) => Ok(Value::Integer(*b as i64)),
+        Value::String(s) => match s.trim().parse::<i64>() {
+            Ok(i) => Ok(Value::Integer(i)),
+            // Digits beyond an i64 only make sense when big integers are allowed
+            Err(_) if interpreter.options().overflow == Overflow::Big => {
+                match s.trim().parse::<BigInt>() {
+                    Ok(n) => Ok(integers::normalize(n)),
+                    Err(_) => Err(cannot_convert("int", &args[0], span)),
+                }
+            }
+            Err(_) => Err(cannot_convert("int", &args[0], span)),
+        },
+        other => Err(cannot_convert("int", other, span)),
+    }
+}
+
+fn float(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    match &args[0] {
+        Value::Integer(i) => Ok(Value::Float(*i as f64)),
+        Value::BigInt(n) => Ok(Value::Float(n.to_f64().unwrap_or(f64::NAN))),
+        Value::Float(f) => Ok(Value::Float(*f)),
+        Value::Boolean(b) => Ok(Value::Float(if *b { 1.0 } else { 0.0 })),
+        Value::String(s) => match s.trim().parse::<f64>() {
+            Ok(f) => Ok(Value::Float(f)),
+            Err(_) => Err(cannot_convert("float", &args[0], span)),
+        },
+        other => Err(cannot_convert("float", other, span)),
+    }
+}
+
+// Every value has a printed form, so this never fails
+fn str(_: &mut Interpreter, args: &[Value], _: Span) -> Result<Value, BugError> {
+    Ok(Value::String(args[0].to_string().into()))
+}
+
+// Numbers are true unless zero, nil is false, and strings must spell out "true" or "false"
+fn bool(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    match &args[0] {
+        Value::Boolean(b) => Ok(Value::Boolean(*b)),
+        Value::Integer(i) => Ok(Value::Boolean(*i != 0)),
+        Value::BigInt(n) => Ok(Value::Boolean(!n.is_zero())),
+        Value::Float(f) => Ok(Value::Boolean(*f != 0.0)),
+        Value::Nil => Ok(Value::Boolean(false)),
+        Value::String(s) => match s.trim() {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ => Err(cannot_convert("bool", &args[0], span)),
+        },
+        other => Err(cannot_convert("bool", other, span)),
+    }
+}
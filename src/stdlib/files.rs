@@ -0,0 +1,172 @@
+// File builtins, limited by the files and file root of the interpreter's sandbox policy
+use super::{string_arg, Native};
+use crate::interpreter::Interpreter;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+pub const NATIVES: &[Native] = &[
+    Native::new("read_file", 1, 1, Type::String, read_file),
+    Native::new("write_file", 2, 2, Type::Any, write_file),
+    Native::new("append_file", 2, 2, Type::Any, append_file),
+    Native::new("file_exists", 1, 1, Type::Bool, file_exists),
+    Native::new("list_dir", 1, 1, Type::List, list_dir),
+    Native::new("read_lines", 1, 1, Type::List, read_lines),
+];
+
+// Checks the policy allows this access and returns the path to use
+fn allowed_path(
+    name: &str,
+    interpreter: &Interpreter,
+    args: &[Value],
+    write: bool,
+    span: Span,
+) -> Result<PathBuf, BugError> {
+    let path = string_arg(name, args, 0, span)?;
+    interpreter
+        .options()
+        .sandbox
+        .allowed_path(&format!("{}()", name), path, write, span)
+}
+
+// Opens a file the policy allows, checking it again once it is open
+fn open_allowed(
+    name: &str,
+    interpreter: &Interpreter,
+    args: &[Value],
+    options: &fs::OpenOptions,
+    write: bool,
+    span: Span,
+) -> Result<(File, PathBuf), BugError> {
+    let path = allowed_path(name, interpreter, args, write, span)?;
+    let file = options
+        .open(&path)
+        .map_err(|error| io_error(name, &path, error, span))?;
+    interpreter
+        .options()
+        .sandbox
+        .check_opened(&format!("{}()", name), &path, &file, span)?;
+    Ok((file, path))
+}
+
+// The whole of a file opened for reading
+fn read_all(
+    name: &str,
+    interpreter: &Interpreter,
+    args: &[Value],
+    span: Span,
+) -> Result<String, BugError> {
+    let (mut file, path) = open_allowed(
+        name,
+        interpreter,
+        args,
+        fs::OpenOptions::new().read(true),
+        false,
+        span,
+    )?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|error| io_error(name, &path, error, span))?;
+    Ok(contents)
+}
+
+fn io_error(name: &str, path: &Path, error: std::io::Error, span: Span) -> BugError {
+    BugError::new(
+        ErrorKind::Io,
+        format!("{}() failed for '{}': {}", name, path.display(), error),
+        span,
+    )
+}
+
+fn read_file(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let contents = read_all("read_file", interpreter, args, span)?;
+    Ok(Value::String(contents.into()))
+}
+
+fn read_lines(
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    span: Span,
+) -> Result<Value, BugError> {
+    let contents = read_all("read_lines", interpreter, args, span)?;
+    Ok(Value::List(Rc::new(
+        contents
+            .lines()
+            .map(|line| Value::String(line.into()))
+            .collect(),
+    )))
+}
+
+// The file is only emptied once it is known to be inside the file root
+fn write_file(
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    span: Span,
+) -> Result<Value, BugError> {
+    let (mut file, path) = open_allowed(
+        "write_file",
+        interpreter,
+        args,
+        fs::OpenOptions::new().write(true).create(true),
+        true,
+        span,
+    )?;
+    let result = file
+        .set_len(0)
+        .and_then(|()| file.write_all(args[1].to_string().as_bytes()));
+    match result {
+        Ok(()) => Ok(Value::Nil),
+        Err(error) => Err(io_error("write_file", &path, error, span)),
+    }
+}
+
+fn append_file(
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    span: Span,
+) -> Result<Value, BugError> {
+    let (mut file, path) = open_allowed(
+        "append_file",
+        interpreter,
+        args,
+        fs::OpenOptions::new().create(true).append(true),
+        true,
+        span,
+    )?;
+    match file.write_all(args[1].to_string().as_bytes()) {
+        Ok(()) => Ok(Value::Nil),
+        Err(error) => Err(io_error("append_file", &path, error, span)),
+    }
+}
+
+fn file_exists(
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    span: Span,
+) -> Result<Value, BugError> {
+    let path = allowed_path("file_exists", interpreter, args, false, span)?;
+    Ok(Value::Boolean(path.exists()))
+}
+
+// The names of the entries in a directory, sorted
+fn list_dir(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let path = allowed_path("list_dir", interpreter, args, false, span)?;
+    let entries = fs::read_dir(&path).map_err(|error| io_error("list_dir", &path, error, span))?;
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|error| io_error("list_dir", &path, error, span))?;
+        names.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+    Ok(Value::List(Rc::new(
+        names
+            .into_iter()
+            .map(|name| Value::String(name.into()))
+            .collect(),
+    )))
+}
@@ -0,0 +1,74 @@
+// Reading from the interpreter's input, which is stdin unless the embedder replaces it
+use super::{string_arg, Native};
+use crate::interpreter::Interpreter;
+use crate::utils::ast::PrintTarget;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+
+// Each of these gives nil once the input runs out, so none has a single result type
+pub const NATIVES: &[Native] = &[
+    Native::new("input", 0, 1, Type::Any, input),
+    Native::new("read_int", 0, 1, Type::Any, read_int),
+    Native::new("read_float", 0, 1, Type::Any, read_float),
+];
+
+// Reads a line after showing the optional prompt argument; nil at end of input
+fn read(
+    name: &str,
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    span: Span,
+) -> Result<Option<String>, BugError> {
+    if !args.is_empty() {
+        let prompt = string_arg(name, args, 0, span)?;
+        interpreter.write_output(PrintTarget::Stdout, prompt, span)?;
+    }
+    interpreter.read_line().map_err(|error| {
+        BugError::new(
+            ErrorKind::Io,
+            format!("{}() could not read input: {}", name, error),
+            span,
+        )
+    })
+}
+
+fn input(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    Ok(match read("input", interpreter, args, span)? {
+        Some(line) => Value::String(line.into()),
+        None => Value::Nil,
+    })
+}
+
+fn read_int(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let Some(line) = read("read_int", interpreter, args, span)? else {
+        return Ok(Value::Nil);
+    };
+    match line.trim().parse::<i64>() {
+        Ok(i) => Ok(Value::Integer(i)),
+        Err(_) => Err(BugError::new(
+            ErrorKind::Value,
+            format!("read_int() expected an integer, got {:?}", line),
+            span,
+        )),
+    }
+}
+
+fn read_float(
+    interpreter: &mut Interpreter,
+    args: &[Value],
+    span: Span,
+) -> Result<Value, BugError> {
+    let Some(line) = read("read_float", interpreter, args, span)? else {
+        return Ok(Value::Nil);
+    };
+    match line.trim().parse::<f64>() {
+        Ok(f) => Ok(Value::Float(f)),
+        Err(_) => Err(BugError::new(
+            ErrorKind::Value,
+            format!("read_float() expected a number, got {:?}", line),
+            span,
+        )),
+    }
+}
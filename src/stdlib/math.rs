@@ -0,0 +1,201 @@
+// Math functions. Integer arguments are promoted to floats where needed, and integer results
+// stay integers when every argument was one.
+use super::{number_arg, Native, VARIADIC};
+use crate::interpreter::Interpreter;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::integers::{self, IntOp};
+use crate::utils::options::MathDomain;
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+use num_traits::Signed;
+use std::cmp::Ordering;
+
+pub const NATIVES: &[Native] = &[
+    Native::new("abs", 1, 1, Type::Any, abs),
+    Native::new("min", 1, VARIADIC, Type::Any, min),
+    Native::new("max", 1, VARIADIC, Type::Any, max),
+    Native::new("pow", 2, 2, Type::Any, pow),
+    Native::new("sqrt", 1, 1, Type::Float, sqrt),
+    Native::new("floor", 1, 1, Type::Int, floor),
+    Native::new("ceil", 1, 1, Type::Int, ceil),
+    Native::new("round", 1, 1, Type::Int, round),
+    Native::new("sin", 1, 1, Type::Float, sin),
+    Native::new("cos", 1, 1, Type::Float, cos),
+    Native::new("tan", 1, 1, Type::Float, tan),
+    Native::new("log", 1, 2, Type::Float, log),
+    Native::new("exp", 1, 1, Type::Float, exp),
+];
+
+pub const CONSTANTS: &[(&str, f64)] = &[("PI", std::f64::consts::PI), ("E", std::f64::consts::E)];
+
+// Applies the configured `MathDomain` policy when an argument is out of range
+fn domain_error(interpreter: &Interpreter, call: String, span: Span) -> Result<Value, BugError> {
+    match interpreter.options().math_domain {
+        MathDomain::Nan => Ok(Value::Float(f64::NAN)),
+        MathDomain::Error => Err(BugError::new(
+            ErrorKind::Value,
+            format!("Math domain error: {}", call),
+            span,
+        )),
+    }
+}
+
+fn abs(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    match &args[0] {
+        Value::Integer(i) if *i < 0 => interpreter.negate(*i, "abs", span),
+        Value::Integer(i) => Ok(Value::Integer(*i)),
+        Value::BigInt(n) => Ok(integers::normalize(n.abs())),
+        _ => Ok(Value::Float(number_arg("abs", args, 0, span)?.abs())),
+    }
+}
+
+fn min(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    extreme("min", args, span, Ordering::Less)
+}
+
+fn max(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    extreme("max", args, span, Ordering::Greater)
+}
+
+// Picks the argument that orders `better` than all the others, or the item of a single list
+// argument. Integers are compared exactly and only promoted when compared with a float.
+fn extreme(name: &str, args: &[Value], span: Span, better: Ordering) -> Result<Value, BugError> {
+    let values = match args {
+        [Value::List(items)] if !items.is_empty() => items.as_slice(),
+        [Value::List(_)] => {
+            return Err(BugError::new(
+                ErrorKind::Value,
+                format!("{}() of an empty list", name),
+                span,
+            ))
+        }
+        _ => args,
+    };
+    let mut best = 0;
+    for index in 0..values.len() {
+        let candidate = number_arg(name, values, index, span)?;
+        let ordering = match (&values[index], &values[best]) {
+            (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+            (a, b) => match integers::big_operands(a, b) {
+                Some((a, b)) => Some(a.cmp(&b)),
+                None => candidate.partial_cmp(&number_arg(name, values, best, span)?),
+            },
+        };
+        if ordering == Some(better) {
+            best = index;
+        }
+    }
+    // A float anywhere in the arguments makes the result a float
+    if values.iter().any(|value| matches!(value, Value::Float(_))) {
+        return Ok(Value::Float(number_arg(name, values, best, span)?));
+    }
+    Ok(values[best].clone())
+}
+
+fn pow(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    match (&args[0], &args[1]) {
+        (Value::Integer(base), Value::Integer(exponent)) if u32::try_from(*exponent).is_ok() => {
+            return interpreter.integer_op(IntOp::Power, *base, *exponent, span);
+        }
+        (Value::BigInt(base), Value::Integer(exponent)) if u32::try_from(*exponent).is_ok() => {
+            let exponent = (*exponent).into();
+            interpreter.reserve_big(IntOp::Power, base, &exponent, span)?;
+            return integers::apply_big(IntOp::Power, base, &exponent)
+                .map_err(|message| BugError::new(ErrorKind::Overflow, message, span));
+        }
+        _ => {}
+    }
+    let base = number_arg("pow", args, 0, span)?;
+    let exponent = number_arg("pow", args, 1, span)?;
+    // A negative base only has a real power for whole exponents
+    if base < 0.0 && exponent.fract() != 0.0 {
+        return domain_error(interpreter, format!("pow({}, {})", base, exponent), span);
+    }
+    // Like `0 ** -1`
+    if base == 0.0 && exponent < 0.0 {
+        return Err(BugError::new(
+            ErrorKind::ZeroDivision,
+            format!("pow({}, {}) divides by zero", base, exponent),
+            span,
+        ));
+    }
+    Ok(Value::Float(base.powf(exponent)))
+}
+
+fn sqrt(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let x = number_arg("sqrt", args, 0, span)?;
+    if x < 0.0 {
+        return domain_error(interpreter, format!("sqrt({})", x), span);
+    }
+    Ok(Value::Float(x.sqrt()))
+}
+
+fn floor(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    to_integer("floor", args, span, f64::floor)
+}
+
+fn ceil(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    to_integer("ceil", args, span, f64::ceil)
+}
+
+// Rounds halfway cases away from zero
+fn round(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    to_integer("round", args, span, f64::round)
+}
+
+fn to_integer(
+    name: &str,
+    args: &[Value],
+    span: Span,
+    rounding: fn(f64) -> f64,
+) -> Result<Value, BugError> {
+    if let Value::Integer(_) | Value::BigInt(_) = args[0] {
+        return Ok(args[0].clone());
+    }
+    let x = rounding(number_arg(name, args, 0, span)?);
+    if !x.is_finite() || x < i64::MIN as f64 || x >= i64::MAX as f64 {
+        return Err(BugError::new(
+            ErrorKind::Value,
+            format!("{}() cannot turn {} into an integer", name, x),
+            span,
+        ));
+    }
+    Ok(Value::Integer(x as i64))
+}
+
+fn sin(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    Ok(Value::Float(number_arg("sin", args, 0, span)?.sin()))
+}
+
+fn cos(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    Ok(Value::Float(number_arg("cos", args, 0, span)?.cos()))
+}
+
+fn tan(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    Ok(Value::Float(number_arg("tan", args, 0, span)?.tan()))
+}
+
+// log(x) is the natural logarithm; log(x, base) uses the given base
+fn log(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let x = number_arg("log", args, 0, span)?;
+    let base = match args.get(1) {
+        Some(_) => Some(number_arg("log", args, 1, span)?),
+        None => None,
+    };
+    if x <= 0.0 || base.is_some_and(|base| base <= 0.0 || base == 1.0) {
+        let call = match base {
+            Some(base) => format!("log({}, {})", x, base),
+            None => format!("log({})", x),
+        };
+        return domain_error(interpreter, call, span);
+    }
+    Ok(Value::Float(match base {
+        Some(base) => x.log(base),
+        None => x.ln(),
+    }))
+}
+
+fn exp(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    Ok(Value::Float(number_arg("exp", args, 0, span)?.exp()))
+}
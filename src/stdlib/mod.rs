@@ -0,0 +1,150 @@
+// Functions implemented in Rust and callable from scripts
+use crate::interpreter::Interpreter;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+use num_traits::ToPrimitive;
+use std::rc::Rc;
+
+mod bits;
+mod convert;
+mod files;
+mod io;
+mod math;
+mod strings;
+mod system;
+
+// The `max_args` of a function that takes any number of arguments from `min_args` on
+pub const VARIADIC: usize = usize::MAX;
+
+pub type NativeFn = fn(&mut Interpreter, &[Value], Span) -> Result<Value, BugError>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Native {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: usize,
+    // What the checker assumes a call gives, Any when it depends on the arguments
+    pub returns: Type,
+    pub function: NativeFn,
+}
+
+impl Native {
+    pub const fn new(
+        name: &'static str,
+        min_args: usize,
+        max_args: usize,
+        returns: Type,
+        function: NativeFn,
+    ) -> Self {
+        Self {
+            name,
+            min_args,
+            max_args,
+            returns,
+            function,
+        }
+    }
+}
+
+const TABLES: [&[Native]; 7] = [
+    strings::NATIVES,
+    math::NATIVES,
+    convert::NATIVES,
+    io::NATIVES,
+    files::NATIVES,
+    bits::NATIVES,
+    system::NATIVES,
+];
+
+// Adds every standard library function and constant to the interpreter's native table
+pub fn register_all(interpreter: &mut Interpreter) {
+    for native in TABLES.into_iter().flatten() {
+        interpreter.register_native(*native);
+    }
+    for (name, value) in math::CONSTANTS {
+        interpreter.register_constant(name, Value::Float(*value));
+    }
+}
+
+// The standard library function with this name
+pub fn find(name: &str) -> Option<&'static Native> {
+    TABLES
+        .into_iter()
+        .flatten()
+        .find(|native| native.name == name)
+}
+
+// Whether a name is a standard library function or constant, so scripts can use it undeclared
+pub fn is_builtin(name: &str) -> bool {
+    find(name).is_some()
+        || math::CONSTANTS
+            .iter()
+            .any(|(constant, _)| *constant == name)
+}
+
+pub fn string_arg<'a>(
+    native: &str,
+    args: &'a [Value],
+    index: usize,
+    span: Span,
+) -> Result<&'a str, BugError> {
+    match &args[index] {
+        Value::String(s) => Ok(s),
+        other => Err(argument_error(native, index, "a string", other, span)),
+    }
+}
+
+pub fn integer_arg(
+    native: &str,
+    args: &[Value],
+    index: usize,
+    span: Span,
+) -> Result<i64, BugError> {
+    match &args[index] {
+        Value::Integer(i) => Ok(*i),
+        Value::BigInt(n) => Err(BugError::new(
+            ErrorKind::Overflow,
+            format!("{}() argument {} is too large: {}", native, index + 1, n),
+            span,
+        )),
+        other => Err(argument_error(native, index, "an integer", other, span)),
+    }
+}
+
+// Integers are accepted wherever a float is expected
+pub fn number_arg(native: &str, args: &[Value], index: usize, span: Span) -> Result<f64, BugError> {
+    match &args[index] {
+        Value::Integer(i) => Ok(*i as f64),
+        Value::BigInt(n) => Ok(n.to_f64().unwrap_or(f64::NAN)),
+        Value::Float(f) => Ok(*f),
+        other => Err(argument_error(native, index, "a number", other, span)),
+    }
+}
+
+pub fn list_arg<'a>(
+    native: &str,
+    args: &'a [Value],
+    index: usize,
+    span: Span,
+) -> Result<&'a Rc<Vec<Value>>, BugError> {
+    match &args[index] {
+        Value::List(items) => Ok(items),
+        other => Err(argument_error(native, index, "a list", other, span)),
+    }
+}
+
+fn argument_error(native: &str, index: usize, expected: &str, got: &Value, span: Span) -> BugError {
+    BugError::new(
+        ErrorKind::Type,
+        format!(
+            "{}() expects {} as argument {}, got {}",
+            native,
+            expected,
+            index + 1,
+            got
+        ),
+        span,
+    )
+}
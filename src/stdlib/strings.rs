@@ -0,0 +1,166 @@
+// String functions. Lengths and positions count Unicode characters, not bytes.
+use super::{integer_arg, list_arg, string_arg, Native};
+use crate::interpreter::Interpreter;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+use std::rc::Rc;
+
+pub const NATIVES: &[Native] = &[
+    Native::new("len", 1, 1, Type::Int, len),
+    Native::new("upper", 1, 1, Type::String, upper),
+    Native::new("lower", 1, 1, Type::String, lower),
+    Native::new("trim", 1, 1, Type::String, trim),
+    Native::new("split", 2, 2, Type::List, split),
+    Native::new("join", 2, 2, Type::String, join),
+    Native::new("replace", 3, 3, Type::String, replace),
+    Native::new("contains", 2, 2, Type::Bool, contains),
+    Native::new("starts_with", 2, 2, Type::Bool, starts_with),
+    Native::new("ends_with", 2, 2, Type::Bool, ends_with),
+    Native::new("substring", 2, 3, Type::String, substring),
+    Native::new("char_at", 2, 2, Type::String, char_at),
+];
+
+fn len(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let length = match &args[0] {
+        Value::List(items) => items.len(),
+        _ => string_arg("len", args, 0, span)?.chars().count(),
+    };
+    Ok(Value::Integer(length as i64))
+}
+
+fn upper(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    Ok(Value::String(
+        string_arg("upper", args, 0, span)?.to_uppercase().into(),
+    ))
+}
+
+fn lower(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    Ok(Value::String(
+        string_arg("lower", args, 0, span)?.to_lowercase().into(),
+    ))
+}
+
+fn trim(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    Ok(Value::String(
+        string_arg("trim", args, 0, span)?.trim().into(),
+    ))
+}
+
+fn split(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let s = string_arg("split", args, 0, span)?;
+    let separator = string_arg("split", args, 1, span)?;
+    // At worst every character becomes a string of its own
+    let parts = if separator.is_empty() {
+        s.chars().count()
+    } else {
+        s.matches(separator).count() + 1
+    };
+    interpreter.reserve_memory(s.len() + parts * size_of::<Value>(), span)?;
+    // An empty separator splits into single characters
+    let parts: Vec<Value> = if separator.is_empty() {
+        s.chars()
+            .map(|c| Value::String(c.to_string().into()))
+            .collect()
+    } else {
+        s.split(separator)
+            .map(|part| Value::String(part.into()))
+            .collect()
+    };
+    Ok(Value::List(Rc::new(parts)))
+}
+
+fn join(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let items = list_arg("join", args, 0, span)?;
+    let separator = string_arg("join", args, 1, span)?;
+    let parts: Vec<String> = items.iter().map(Value::to_string).collect();
+    let length = parts.iter().map(String::len).sum::<usize>()
+        + separator
+            .len()
+            .saturating_mul(parts.len().saturating_sub(1));
+    interpreter.reserve_memory(length, span)?;
+    Ok(Value::String(parts.join(separator).into()))
+}
+
+fn replace(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let s = string_arg("replace", args, 0, span)?;
+    let from = string_arg("replace", args, 1, span)?;
+    let to = string_arg("replace", args, 2, span)?;
+    if from.is_empty() {
+        return Err(BugError::new(
+            ErrorKind::Value,
+            "replace() cannot replace an empty string",
+            span,
+        ));
+    }
+    let growth = to.len().saturating_sub(from.len());
+    let length = s.len() + s.matches(from).count().saturating_mul(growth);
+    interpreter.reserve_memory(length, span)?;
+    Ok(Value::String(s.replace(from, to).into()))
+}
+
+fn contains(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let s = string_arg("contains", args, 0, span)?;
+    let needle = string_arg("contains", args, 1, span)?;
+    Ok(Value::Boolean(s.contains(needle)))
+}
+
+fn starts_with(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let s = string_arg("starts_with", args, 0, span)?;
+    let prefix = string_arg("starts_with", args, 1, span)?;
+    Ok(Value::Boolean(s.starts_with(prefix)))
+}
+
+fn ends_with(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let s = string_arg("ends_with", args, 0, span)?;
+    let suffix = string_arg("ends_with", args, 1, span)?;
+    Ok(Value::Boolean(s.ends_with(suffix)))
+}
+
+// substring(s, start, end) takes characters start..end; end defaults to the end of s
+fn substring(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let s = string_arg("substring", args, 0, span)?;
+    let length = s.chars().count() as i64;
+    let start = integer_arg("substring", args, 1, span)?;
+    let end = match args.get(2) {
+        Some(_) => integer_arg("substring", args, 2, span)?,
+        None => length,
+    };
+    if start < 0 || end < start || end > length {
+        return Err(BugError::new(
+            ErrorKind::Index,
+            format!(
+                "substring({}, {}) is out of range for a string of length {}",
+                start, end, length
+            ),
+            span,
+        ));
+    }
+    let result: String = s
+        .chars()
+        .skip(start as usize)
+        .take((end - start) as usize)
+        .collect();
+    Ok(Value::String(result.into()))
+}
+
+fn char_at(_: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let s = string_arg("char_at", args, 0, span)?;
+    let index = integer_arg("char_at", args, 1, span)?;
+    let found = usize::try_from(index)
+        .ok()
+        .and_then(|index| s.chars().nth(index));
+    match found {
+        Some(c) => Ok(Value::String(c.to_string().into())),
+        None => Err(BugError::new(
+            ErrorKind::Index,
+            format!(
+                "char_at({}) is out of range for a string of length {}",
+                index,
+                s.chars().count()
+            ),
+            span,
+        )),
+    }
+}
@@ -0,0 +1,135 @@
+// Builtins that reach outside the interpreter, each allowed only when the sandbox policy grants
+// its capability
+use super::{list_arg, string_arg, Native};
+use crate::interpreter::Interpreter;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::sandbox::Capability;
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+use std::env;
+use std::io::{self, Read};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const NATIVES: &[Native] = &[
+    Native::new("env", 1, 1, Type::Any, env),
+    Native::new("time", 0, 0, Type::Float, time),
+    Native::new("run", 1, 2, Type::String, run),
+];
+
+// An environment variable, or nil if it is not set
+fn env(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let name = string_arg("env", args, 0, span)?;
+    interpreter
+        .options()
+        .sandbox
+        .require(Capability::Env, "env", span)?;
+    Ok(match env::var(name) {
+        Ok(value) => Value::String(value.into()),
+        Err(_) => Value::Nil,
+    })
+}
+
+// Seconds since the Unix epoch, with a fractional part
+fn time(interpreter: &mut Interpreter, _: &[Value], span: Span) -> Result<Value, BugError> {
+    interpreter
+        .options()
+        .sandbox
+        .require(Capability::Clock, "time", span)?;
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(Value::Float(elapsed.as_secs_f64()))
+}
+
+// Runs a program with an optional list of arguments and gives what it printed. A program that
+// fails raises an IOError with what it printed to stderr.
+fn run(interpreter: &mut Interpreter, args: &[Value], span: Span) -> Result<Value, BugError> {
+    let program = string_arg("run", args, 0, span)?;
+    let arguments: Vec<String> = match args.get(1) {
+        Some(_) => list_arg("run", args, 1, span)?
+            .iter()
+            .map(Value::to_string)
+            .collect(),
+        None => Vec::new(),
+    };
+    interpreter
+        .options()
+        .sandbox
+        .require(Capability::Process, "run", span)?;
+    let child = Command::new(program)
+        .args(&arguments)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| {
+            BugError::new(
+                ErrorKind::Io,
+                format!("run() could not start '{}': {}", program, error),
+                span,
+            )
+        })?;
+    let output = match interpreter.deadline() {
+        Some(deadline) => wait_until(child, deadline),
+        None => child.wait_with_output().map(Some),
+    }
+    .map_err(|error| {
+        BugError::new(
+            ErrorKind::Io,
+            format!("run() could not wait for '{}': {}", program, error),
+            span,
+        )
+    })?
+    .ok_or_else(|| interpreter.out_of_time(span))?;
+    if !output.status.success() {
+        let mut message = format!("run() '{}' failed with {}", program, output.status);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.trim_end().is_empty() {
+            message = format!("{}: {}", message, stderr.trim_end());
+        }
+        return Err(BugError::new(ErrorKind::Io, message, span));
+    }
+    Ok(Value::String(
+        String::from_utf8_lossy(&output.stdout).as_ref().into(),
+    ))
+}
+
+// How often a program run under `--timeout` is checked on
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Waits for a program to finish, killing it and giving None if it is still running at the
+// deadline. Its output is read on other threads so that a full pipe cannot stall it.
+fn wait_until(mut child: Child, deadline: Instant) -> io::Result<Option<Output>> {
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    };
+    Ok(Some(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    }))
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        bytes
+    })
+}
@@ -0,0 +1,262 @@
+use crate::utils::symbols::Symbol;
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+    TypeOf,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    FloorDivide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnaryOp::Negate => write!(f, "-"),
+            UnaryOp::Not => write!(f, "not"),
+            UnaryOp::TypeOf => write!(f, "typeof"),
+            UnaryOp::BitNot => write!(f, "~"),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::FloorDivide => "//",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Power => "**",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogicalOp {
+    And,
+    Or,
+    // `??`: the right side, only if the left is nil
+    Coalesce,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateOp {
+    Assign,
+    Add,
+    Subtract,
+    Increment,
+    Decrement,
+}
+
+impl fmt::Display for UpdateOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            UpdateOp::Assign => "=",
+            UpdateOp::Add => "+=",
+            UpdateOp::Subtract => "-=",
+            UpdateOp::Increment => "++",
+            UpdateOp::Decrement => "--",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+// A function body shared between its declaration and every closure made from it
+#[derive(Debug)]
+pub struct FunctionDecl {
+    pub name: Option<Symbol>,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: Symbol,
+    pub ty: Option<Type>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrintTarget {
+    Stdout,
+    Stderr,
+}
+
+// `print`, `write` and `eprint`, which differ only in where they write and the default `end`
+#[derive(Debug, Clone)]
+pub struct PrintStmt {
+    pub values: Vec<Expr>,
+    pub sep: Option<Expr>,
+    pub end: Option<Expr>,
+    pub newline: bool,
+    pub target: PrintTarget,
+}
+
+#[derive(Debug, Clone)]
+pub struct CatchClause {
+    pub name: Symbol,
+    pub slot: usize,
+    pub body: Vec<Stmt>,
+}
+
+// Where a variable lives, filled in by the resolver: slot `index` of the scope `depth` functions
+// out from the code using it. A function's parameters take the first slots of its scope.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Literal(Value),
+    Variable {
+        name: Symbol,
+        // None when the name is only a builtin
+        slot: Option<Slot>,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Binary {
+        left: Box<Expr>,
+        op: BinaryOp,
+        right: Box<Expr>,
+    },
+    Logical {
+        left: Box<Expr>,
+        op: LogicalOp,
+        right: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    List(Vec<Expr>),
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Symbol,
+        // `?.` gives nil instead of failing when the object is nil
+        optional: bool,
+    },
+    Function(Rc<FunctionDecl>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
+    Print(PrintStmt),
+    Variable {
+        name: Symbol,
+        ty: Option<Type>,
+        init: Expr,
+        // Declared with `const` rather than `variable`
+        constant: bool,
+        // Its slot in the current scope
+        slot: usize,
+    },
+    Update {
+        name: Symbol,
+        op: UpdateOp,
+        value: Option<Expr>,
+        slot: Slot,
+    },
+    If {
+        condition: Expr,
+        then_branch: Vec<Stmt>,
+        else_branch: Option<Vec<Stmt>>,
+    },
+    While {
+        condition: Expr,
+        body: Vec<Stmt>,
+    },
+    For {
+        init: Option<Box<Stmt>>,
+        condition: Option<Expr>,
+        update: Option<Box<Stmt>>,
+        body: Vec<Stmt>,
+    },
+    Function {
+        decl: Rc<FunctionDecl>,
+        slot: usize,
+    },
+    Return(Option<Expr>),
+    Throw(Expr),
+    Try {
+        body: Vec<Stmt>,
+        catch: Option<CatchClause>,
+        finally: Option<Vec<Stmt>>,
+    },
+    Import {
+        path: String,
+        alias: Symbol,
+        slot: usize,
+    },
+    Export(Vec<Symbol>),
+    Expression(Expr),
+}
+
+// A parsed and resolved file
+#[derive(Debug)]
+pub struct Script {
+    pub statements: Vec<Stmt>,
+    // The names of its top-level variables, by slot
+    pub globals: Vec<Symbol>,
+}
@@ -0,0 +1,682 @@
+// Checks a program before it runs, against its type annotations and the types it infers from
+// each variable's first value. Anything whose type cannot be known is `Type::Any` and passes
+// every check, so that code is left to the interpreter.
+use crate::stdlib;
+use crate::utils::ast::*;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::parser::Parser;
+use crate::utils::resolver::Resolver;
+use crate::utils::symbols::Symbol;
+use crate::utils::tokeniser::{Span, Tokenizer};
+use crate::utils::types::Type;
+use crate::utils::variables::{Value, VarManager};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Clone)]
+struct Binding {
+    ty: Type,
+    // Set when the type was written out, so later assignments must agree with it
+    declared: bool,
+    // The declaration, when the name is known to hold that function
+    function: Option<Rc<FunctionDecl>>,
+    // Declared with `const`
+    constant: bool,
+}
+
+// A function being checked
+struct Frame {
+    declared: Option<Type>,
+    // Every type its return statements give, joined
+    returned: Option<Type>,
+}
+
+pub struct Report {
+    // Every undefined name and type error in the program, in source order
+    pub errors: Vec<BugError>,
+    // The type of each declaration, such as `x: int`
+    pub types: Vec<(Span, String)>,
+}
+
+pub struct Checker {
+    scopes: Vec<HashMap<Symbol, Binding>>,
+    frames: Vec<Frame>,
+    // Result types of unannotated functions, worked out when their bodies were checked
+    inferred: HashMap<*const FunctionDecl, Type>,
+    report: Report,
+}
+
+// Parses and checks a source file; a syntax error is returned rather than collected
+pub fn check_source(source: &str) -> Result<Report, BugError> {
+    let tokens = Tokenizer::tokenize(source)?;
+    let mut statements = Parser::new(tokens).parse()?;
+    Ok(check_program(&mut statements).1)
+}
+
+// Resolves a parsed file's variables and checks it, giving the names of its top-level variables
+// by slot along with the report, which includes any undefined names
+pub fn check_program(statements: &mut [Stmt]) -> (Vec<Symbol>, Report) {
+    let (globals, undefined) = Resolver::resolve(statements, &stdlib::is_builtin);
+    let mut report = Checker::check(statements);
+    report.errors.extend(undefined);
+    report
+        .errors
+        .sort_by_key(|error| (error.span.line, error.span.column));
+    (globals, report)
+}
+
+impl Checker {
+    pub fn check(statements: &[Stmt]) -> Report {
+        let mut checker = Checker {
+            scopes: vec![HashMap::new()],
+            frames: Vec::new(),
+            inferred: HashMap::new(),
+            report: Report {
+                errors: Vec::new(),
+                types: Vec::new(),
+            },
+        };
+        checker.check_body(statements);
+        checker.report
+    }
+
+    fn error(&mut self, message: String, span: Span) {
+        self.report
+            .errors
+            .push(BugError::new(ErrorKind::Type, message, span));
+    }
+
+    fn note(&mut self, span: Span, text: String) {
+        self.report.types.push((span, text));
+    }
+
+    // Functions declared anywhere in a body can be called from any function in it
+    fn check_body(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            if let StmtKind::Function { decl, .. } = &statement.kind {
+                self.bind_function(decl);
+            }
+        }
+        self.check_block(statements);
+    }
+
+    fn check_block(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.check_statement(statement);
+        }
+    }
+
+    fn check_statement(&mut self, statement: &Stmt) {
+        match &statement.kind {
+            StmtKind::Print(print) => {
+                for expr in print.values.iter().chain(&print.sep).chain(&print.end) {
+                    self.expr_type(expr);
+                }
+            }
+            StmtKind::Variable {
+                name,
+                ty,
+                init,
+                constant,
+                ..
+            } => self.check_declaration(*name, *ty, init, *constant, statement.span),
+            StmtKind::Update {
+                name, op, value, ..
+            } => self.check_update(*name, *op, value.as_ref(), statement.span),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.check_condition(condition);
+                self.check_block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.check_block(else_branch);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.check_condition(condition);
+                self.check_block(body);
+            }
+            StmtKind::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.check_statement(init);
+                }
+                if let Some(condition) = condition {
+                    self.check_condition(condition);
+                }
+                self.check_block(body);
+                if let Some(update) = update {
+                    self.check_statement(update);
+                }
+            }
+            StmtKind::Function { decl, .. } => {
+                let redeclared = decl
+                    .name
+                    .is_some_and(|name| self.redeclares_constant(name, statement.span));
+                if !redeclared {
+                    self.bind_function(decl);
+                }
+                self.check_function(decl, statement.span);
+            }
+            StmtKind::Return(value) => {
+                let ty = match value {
+                    Some(expr) => self.expr_type(expr),
+                    None => Type::Nil,
+                };
+                let Some(frame) = self.frames.last_mut() else {
+                    return;
+                };
+                frame.returned = Some(frame.returned.map_or(ty, |returned| returned.join(ty)));
+                if let Some(expected) = frame.declared {
+                    if !expected.accepts(ty) {
+                        let span = value.as_ref().map_or(statement.span, |expr| expr.span);
+                        self.error(
+                            format!("The result is declared {} but got {}", expected, ty),
+                            span,
+                        );
+                    }
+                }
+            }
+            StmtKind::Throw(expr) | StmtKind::Expression(expr) => {
+                self.expr_type(expr);
+            }
+            StmtKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.check_block(body);
+                if let Some(catch) = catch {
+                    self.check_catch(catch, statement.span);
+                }
+                if let Some(finally) = finally {
+                    self.check_block(finally);
+                }
+            }
+            StmtKind::Import { alias, .. } => {
+                if !self.redeclares_constant(*alias, statement.span) {
+                    self.bind(*alias, Type::Module, false, None);
+                }
+                self.note(statement.span, format!("{}: module", alias));
+            }
+            StmtKind::Export(_) => {}
+        }
+    }
+
+    fn check_declaration(
+        &mut self,
+        name: Symbol,
+        ty: Option<Type>,
+        init: &Expr,
+        constant: bool,
+        span: Span,
+    ) {
+        let init_type = self.assigned_type(init, ty);
+        let function = match &init.kind {
+            ExprKind::Function(decl) => Some(Rc::clone(decl)),
+            _ => None,
+        };
+        if self.redeclares_constant(name, span) {
+            return;
+        }
+        let existing = self
+            .scopes
+            .last()
+            .and_then(|scope| scope.get(&name))
+            .cloned();
+        let binding = match (ty, existing) {
+            (Some(ty), _) => {
+                if !ty.accepts(init_type) {
+                    self.error(
+                        format!("{} is declared {} but got {}", name, ty, init_type),
+                        init.span,
+                    );
+                }
+                Binding {
+                    ty,
+                    declared: true,
+                    function,
+                    constant,
+                }
+            }
+            (None, None) => Binding {
+                ty: init_type,
+                declared: false,
+                function,
+                constant,
+            },
+            // A constant replaces an existing local rather than assigning to it
+            (None, Some(_)) if constant => Binding {
+                ty: init_type,
+                declared: false,
+                function,
+                constant: true,
+            },
+            // Declaring an existing local again assigns to it
+            (None, Some(existing)) if existing.declared => {
+                if !existing.ty.accepts(init_type) {
+                    self.error(
+                        format!("{} is declared {} but got {}", name, existing.ty, init_type),
+                        init.span,
+                    );
+                }
+                existing
+            }
+            // The two declarations may be in different branches, so either could come first
+            (None, Some(existing)) => Binding {
+                ty: existing.ty.join(init_type),
+                declared: false,
+                function: None,
+                constant: false,
+            },
+        };
+        self.note(span, format!("{}: {}", name, binding.ty));
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, binding);
+        }
+    }
+
+    fn check_update(&mut self, name: Symbol, op: UpdateOp, value: Option<&Expr>, span: Span) {
+        let binding = self.lookup(name);
+        let known = binding.as_ref().map(|binding| binding.ty);
+        let value_type = value.map(|expr| self.assigned_type(expr, known));
+        let Some(binding) = binding else {
+            return;
+        };
+        if binding.constant {
+            self.error(format!("Cannot update constant {}", name), span);
+            return;
+        }
+        let value_span = value.map_or(span, |expr| expr.span);
+        let result = match (op, value_type) {
+            (UpdateOp::Assign, Some(ty)) => ty,
+            (UpdateOp::Add, Some(ty)) => self.binary_type(BinaryOp::Add, binding.ty, ty, span),
+            (UpdateOp::Subtract, Some(ty)) => {
+                self.binary_type(BinaryOp::Subtract, binding.ty, ty, span)
+            }
+            (UpdateOp::Increment | UpdateOp::Decrement, _) => {
+                if !matches!(binding.ty, Type::Int | Type::Float | Type::Nil | Type::Any) {
+                    self.error(
+                        format!(
+                            "Cannot use {} on {}, which {}",
+                            op,
+                            name,
+                            Self::typed(&binding)
+                        ),
+                        span,
+                    );
+                }
+                Type::Int
+            }
+            _ => return,
+        };
+        let fits = if binding.declared {
+            binding.ty.accepts(result)
+        } else {
+            // A string literal has already been read as the type it looks like
+            let literal = matches!(value.map(|expr| &expr.kind), Some(ExprKind::Literal(_)));
+            Self::stores(binding.ty, result, literal)
+        };
+        if !fits {
+            self.error(
+                format!("{} {} but got {}", name, Self::typed(&binding), result),
+                value_span,
+            );
+            return;
+        }
+        // Only numbers, booleans and strings keep their type when something else is assigned,
+        // and integers become floats when a float is
+        let promoted = binding.ty == Type::Int && result == Type::Float;
+        if !binding.declared
+            && (promoted
+                || !matches!(
+                    binding.ty,
+                    Type::Int | Type::Float | Type::Bool | Type::String
+                ))
+        {
+            self.retype(name, binding.ty.join(result));
+        }
+    }
+
+    // Whether a variable first given a `target` can take a `value`, following `VarManager::assign`
+    fn stores(target: Type, value: Type, literal: bool) -> bool {
+        match (target, value) {
+            (_, Type::Nil | Type::Any) => true,
+            (Type::Int | Type::Float | Type::Bool, Type::String) => !literal,
+            (Type::Int | Type::Float, _) => matches!(value, Type::Int | Type::Float),
+            (Type::Bool, _) => value == target,
+            _ => true,
+        }
+    }
+
+    // Says where a variable's type came from: `is declared int` or `has type int`
+    fn typed(binding: &Binding) -> String {
+        if binding.declared {
+            format!("is declared {}", binding.ty)
+        } else {
+            format!("has type {}", binding.ty)
+        }
+    }
+
+    fn retype(&mut self, name: Symbol, ty: Type) {
+        if let Some(binding) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&name))
+        {
+            binding.ty = ty;
+        }
+    }
+
+    fn check_condition(&mut self, condition: &Expr) {
+        let ty = self.expr_type(condition);
+        if !Type::Bool.accepts(ty) {
+            self.error(
+                format!("Invalid condition: expected a bool, got {}", ty),
+                condition.span,
+            );
+        }
+    }
+
+    fn check_function(&mut self, decl: &Rc<FunctionDecl>, span: Span) {
+        let mut scope = HashMap::new();
+        for param in &decl.params {
+            let binding = Binding {
+                ty: param.ty.unwrap_or(Type::Any),
+                declared: param.ty.is_some(),
+                function: None,
+                constant: false,
+            };
+            scope.insert(param.name, binding);
+        }
+        self.scopes.push(scope);
+        self.frames.push(Frame {
+            declared: decl.return_type,
+            returned: None,
+        });
+        self.check_body(&decl.body);
+        let frame = self.frames.pop();
+        self.scopes.pop();
+
+        // Without a return statement the result is always nil
+        let result = decl
+            .return_type
+            .unwrap_or_else(|| frame.and_then(|frame| frame.returned).unwrap_or(Type::Nil));
+        self.inferred.insert(Rc::as_ptr(decl), result);
+        let params: Vec<String> = decl
+            .params
+            .iter()
+            .map(|param| format!("{}: {}", param.name, param.ty.unwrap_or(Type::Any)))
+            .collect();
+        let name = decl.name.map_or("fn", Symbol::as_str);
+        self.note(
+            span,
+            format!("{}({}) -> {}", name, params.join(", "), result),
+        );
+    }
+
+    fn bind_function(&mut self, decl: &Rc<FunctionDecl>) {
+        if let Some(name) = decl.name {
+            self.bind(name, Type::Function, false, Some(Rc::clone(decl)));
+        }
+    }
+
+    fn bind(&mut self, name: Symbol, ty: Type, declared: bool, function: Option<Rc<FunctionDecl>>) {
+        let binding = Binding {
+            ty,
+            declared,
+            function,
+            constant: false,
+        };
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, binding);
+        }
+    }
+
+    // The caught error is only bound in the catch clause, so whatever its name meant before comes
+    // back afterwards
+    fn check_catch(&mut self, catch: &CatchClause, span: Span) {
+        let hidden = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.remove(&catch.name));
+        self.bind(catch.name, Type::Error, false, None);
+        self.note(span, format!("{}: error", catch.name));
+        self.check_block(&catch.body);
+        if let Some(scope) = self.scopes.last_mut() {
+            match hidden {
+                Some(binding) => scope.insert(catch.name, binding),
+                None => scope.remove(&catch.name),
+            };
+        }
+    }
+
+    // Constants may be shadowed in an inner scope, but not declared again in their own. Reports
+    // the error and returns true if `name` is one.
+    fn redeclares_constant(&mut self, name: Symbol, span: Span) -> bool {
+        let constant = self
+            .scopes
+            .last()
+            .and_then(|scope| scope.get(&name))
+            .is_some_and(|binding| binding.constant);
+        if constant {
+            self.error(format!("Cannot redeclare constant {}", name), span);
+        }
+        constant
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name))
+            .cloned()
+    }
+
+    // String literals stored in variables are read as whatever type they look like, unless the
+    // variable is a string
+    fn assigned_type(&mut self, expr: &Expr, declared: Option<Type>) -> Type {
+        match &expr.kind {
+            ExprKind::Literal(Value::String(s)) if declared != Some(Type::String) => {
+                VarManager::parse_value(s).map_or(Type::String, |value| Type::of(&value))
+            }
+            _ => self.expr_type(expr),
+        }
+    }
+
+    fn expr_type(&mut self, expr: &Expr) -> Type {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Literal(value) => Type::of(value),
+            ExprKind::Variable { name, .. } => {
+                self.lookup(*name).map_or(Type::Any, |binding| binding.ty)
+            }
+            ExprKind::Unary { op, expr } => {
+                let ty = self.expr_type(expr);
+                match (op, ty) {
+                    (UnaryOp::TypeOf, _) => Type::String,
+                    (UnaryOp::Negate, Type::Int | Type::Float | Type::Any) => ty,
+                    (UnaryOp::Negate, Type::Nil) => Type::Int,
+                    (UnaryOp::Not, Type::Bool | Type::Nil | Type::Any) => Type::Bool,
+                    (UnaryOp::BitNot, Type::Int | Type::Any) => ty,
+                    (UnaryOp::BitNot, Type::Nil) => Type::Int,
+                    (op, ty) => {
+                        self.error(format!("Cannot apply {} to {}", op, ty), span);
+                        Type::Any
+                    }
+                }
+            }
+            ExprKind::Binary { left, op, right } => {
+                let left = self.expr_type(left);
+                let right = self.expr_type(right);
+                self.binary_type(*op, left, right, span)
+            }
+            ExprKind::Logical {
+                left,
+                op: LogicalOp::Coalesce,
+                right,
+            } => {
+                let left = self.expr_type(left);
+                let right = self.expr_type(right);
+                match left {
+                    Type::Nil => right,
+                    Type::Any => Type::Any,
+                    _ => left,
+                }
+            }
+            ExprKind::Logical { left, right, .. } => {
+                self.check_condition(left);
+                self.check_condition(right);
+                Type::Bool
+            }
+            ExprKind::Call { callee, args } => self.call_type(callee, args, span),
+            ExprKind::List(items) => {
+                for item in items {
+                    self.expr_type(item);
+                }
+                Type::List
+            }
+            ExprKind::Index { object, index } => {
+                self.expr_type(object);
+                self.expr_type(index);
+                Type::Any
+            }
+            ExprKind::Get { object, .. } => {
+                self.expr_type(object);
+                Type::Any
+            }
+            ExprKind::Function(decl) => {
+                self.check_function(decl, span);
+                Type::Function
+            }
+        }
+    }
+
+    // The type of `left op right`, following `Interpreter::arithmetic`
+    fn binary_type(&mut self, op: BinaryOp, left: Type, right: Type, span: Span) -> Type {
+        let equality = matches!(op, BinaryOp::Equal | BinaryOp::NotEqual);
+        let arithmetic = !equality
+            && !matches!(
+                op,
+                BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual
+            );
+        let (left, right) = match (left, right) {
+            (Type::Nil, _) | (_, Type::Nil) if equality => return Type::Bool,
+            (Type::Any, _) | (_, Type::Any) if arithmetic => return Type::Any,
+            (Type::Any, _) | (_, Type::Any) => return Type::Bool,
+            // Nil becomes the other side's zero value
+            (Type::Nil, other) => (Self::zero_like(other), other),
+            (other, Type::Nil) => (other, Self::zero_like(other)),
+            operands => operands,
+        };
+        let bitwise = matches!(
+            op,
+            BinaryOp::BitAnd
+                | BinaryOp::BitOr
+                | BinaryOp::BitXor
+                | BinaryOp::ShiftLeft
+                | BinaryOp::ShiftRight
+        );
+        let valid = match (left, right) {
+            (Type::Int, Type::Int) => true,
+            (Type::Int | Type::Float, Type::Int | Type::Float) => !bitwise,
+            (Type::String, Type::String) => !arithmetic || op == BinaryOp::Add,
+            (Type::Bool, Type::Bool) => equality,
+            _ => false,
+        };
+        if !valid {
+            if left == right {
+                self.error(format!("Cannot apply {} to {}s", op, left), span);
+            } else {
+                self.error(
+                    format!("Cannot apply {} to {} and {}", op, left, right),
+                    span,
+                );
+            }
+            return Type::Any;
+        }
+        match (op, left, right) {
+            _ if !arithmetic => Type::Bool,
+            (BinaryOp::Divide, _, _) => Type::Float,
+            // A negative exponent gives a float
+            (BinaryOp::Power, Type::Int, Type::Int) => Type::Any,
+            (_, Type::Int, Type::Int) => Type::Int,
+            (_, Type::String, _) => Type::String,
+            _ => Type::Float,
+        }
+    }
+
+    fn zero_like(other: Type) -> Type {
+        match other {
+            Type::Float | Type::String | Type::Bool => other,
+            _ => Type::Int,
+        }
+    }
+
+    fn call_type(&mut self, callee: &Expr, args: &[Expr], span: Span) -> Type {
+        let binding = match &callee.kind {
+            ExprKind::Variable { name, .. } => match self.lookup(*name) {
+                Some(binding) => Some(binding),
+                // Not a variable, so it can only be a builtin
+                None => {
+                    for arg in args {
+                        self.expr_type(arg);
+                    }
+                    return stdlib::find(name.as_str()).map_or(Type::Any, |native| native.returns);
+                }
+            },
+            _ => None,
+        };
+        let callee_type = match &binding {
+            Some(binding) => binding.ty,
+            None => self.expr_type(callee),
+        };
+        let arg_types: Vec<Type> = args.iter().map(|arg| self.expr_type(arg)).collect();
+        if !Type::Function.accepts(callee_type) {
+            self.error(
+                format!("Value of type {} is not callable", callee_type),
+                span,
+            );
+            return Type::Any;
+        }
+        let Some(decl) = binding.and_then(|binding| binding.function) else {
+            return Type::Any;
+        };
+
+        let shown = match &decl.name {
+            Some(name) => format!("<fn {}>", name),
+            None => "<fn>".to_string(),
+        };
+        if args.len() != decl.params.len() {
+            self.error(
+                format!(
+                    "{} expects {} arguments but got {}",
+                    shown,
+                    decl.params.len(),
+                    args.len()
+                ),
+                span,
+            );
+        }
+        for ((param, arg), ty) in decl.params.iter().zip(args).zip(arg_types) {
+            if let Some(expected) = param.ty {
+                if !expected.accepts(ty) {
+                    self.error(
+                        format!("{} is declared {} but got {}", param.name, expected, ty),
+                        arg.span,
+                    );
+                }
+            }
+        }
+        decl.return_type
+            .or_else(|| self.inferred.get(&Rc::as_ptr(&decl)).copied())
+            .unwrap_or(Type::Any)
+    }
+}
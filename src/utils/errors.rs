@@ -0,0 +1,104 @@
+use crate::utils::tokeniser::Span;
+use crate::utils::variables::Value;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    Syntax,
+    Name,
+    Type,
+    Value,
+    Index,
+    Overflow,
+    ZeroDivision,
+    Import,
+    Io,
+    Permission,
+    // The script ran for more steps or longer than it was allowed to
+    Budget,
+    // The script's values took up more memory than it was allowed
+    Memory,
+    // Functions called each other more deeply than allowed
+    Recursion,
+    // Raised by a script with `throw`
+    Thrown,
+}
+
+impl ErrorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Syntax => "SyntaxError",
+            ErrorKind::Name => "NameError",
+            ErrorKind::Type => "TypeError",
+            ErrorKind::Value => "ValueError",
+            ErrorKind::Index => "IndexError",
+            ErrorKind::Overflow => "OverflowError",
+            ErrorKind::ZeroDivision => "ZeroDivisionError",
+            ErrorKind::Import => "ImportError",
+            ErrorKind::Io => "IOError",
+            ErrorKind::Permission => "PermissionError",
+            ErrorKind::Budget => "BudgetExceeded",
+            ErrorKind::Memory => "MemoryLimitExceeded",
+            ErrorKind::Recursion => "RecursionError",
+            ErrorKind::Thrown => "Error",
+        }
+    }
+
+    // Whether `try` can catch the error. Running out of budget ends the script, since a handler
+    // could otherwise keep it going.
+    pub fn is_catchable(&self) -> bool {
+        *self != ErrorKind::Budget
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BugError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Span,
+    // The source file, when the error came from one
+    pub file: Option<String>,
+    // The value passed to `throw`, if a script raised this error
+    pub value: Option<Value>,
+}
+
+impl BugError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            span,
+            file: None,
+            value: None,
+        }
+    }
+
+    pub fn thrown(value: Value, span: Span) -> Self {
+        Self {
+            kind: ErrorKind::Thrown,
+            message: value.to_string(),
+            span,
+            file: None,
+            value: Some(value),
+        }
+    }
+
+    // Records the file the error happened in, unless a more specific one is already known
+    pub fn in_file(mut self, path: &Path) -> Self {
+        if self.file.is_none() {
+            self.file = Some(path.display().to_string());
+        }
+        self
+    }
+}
+
+impl fmt::Display for BugError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} (", self.kind.name(), self.message)?;
+        if let Some(file) = &self.file {
+            write!(f, "{}, ", file)?;
+        }
+        write!(f, "line {}, column {})", self.span.line, self.span.column)
+    }
+}
@@ -0,0 +1,350 @@
+// Integer arithmetic under the configured `Overflow` mode. Big integers only appear once a result
+// no longer fits in an i64, and go back to plain integers as soon as one does again.
+use crate::utils::ast::BinaryOp;
+use crate::utils::options::Overflow;
+use crate::utils::variables::Value;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::ToPrimitive;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntOp {
+    Add,
+    Subtract,
+    Multiply,
+    FloorDivide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl IntOp {
+    pub fn from_binary(op: BinaryOp) -> Option<IntOp> {
+        match op {
+            BinaryOp::Add => Some(IntOp::Add),
+            BinaryOp::Subtract => Some(IntOp::Subtract),
+            BinaryOp::Multiply => Some(IntOp::Multiply),
+            BinaryOp::FloorDivide => Some(IntOp::FloorDivide),
+            BinaryOp::Modulo => Some(IntOp::Modulo),
+            BinaryOp::Power => Some(IntOp::Power),
+            BinaryOp::BitAnd => Some(IntOp::BitAnd),
+            BinaryOp::BitOr => Some(IntOp::BitOr),
+            BinaryOp::BitXor => Some(IntOp::BitXor),
+            BinaryOp::ShiftLeft => Some(IntOp::ShiftLeft),
+            BinaryOp::ShiftRight => Some(IntOp::ShiftRight),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for IntOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            IntOp::Add => "+",
+            IntOp::Subtract => "-",
+            IntOp::Multiply => "*",
+            IntOp::FloorDivide => "//",
+            IntOp::Modulo => "%",
+            IntOp::Power => "**",
+            IntOp::BitAnd => "&",
+            IntOp::BitOr => "|",
+            IntOp::BitXor => "^",
+            IntOp::ShiftLeft => "<<",
+            IntOp::ShiftRight => ">>",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+// The most bits a big integer result may have. Past this a power or shift is refused before it is
+// worked out, since its size alone would take seconds and many megabytes to produce.
+pub const MAX_BITS: u64 = 1 << 24;
+
+// `a op b`, or a message saying it overflowed when the mode is `Checked`. A divisor must already
+// be known not to be zero. A power's exponent or shift's width outside a u32 is an error.
+pub fn apply(op: IntOp, a: i64, b: i64, mode: Overflow) -> Result<Value, String> {
+    let checked = match op {
+        IntOp::Add => a.checked_add(b),
+        IntOp::Subtract => a.checked_sub(b),
+        IntOp::Multiply => a.checked_mul(b),
+        IntOp::FloorDivide => floor_divide(a, b),
+        IntOp::Modulo => Some(floor_modulo(a, b)),
+        IntOp::Power => a.checked_pow(width(op, &a, &b)?),
+        IntOp::BitAnd => Some(a & b),
+        IntOp::BitOr => Some(a | b),
+        IntOp::BitXor => Some(a ^ b),
+        IntOp::ShiftLeft => shift_left(a, width(op, &a, &b)?),
+        IntOp::ShiftRight => Some(shift_right(a, width(op, &a, &b)?)),
+    };
+    if let Some(result) = checked {
+        return Ok(Value::Integer(result));
+    }
+    let result = match mode {
+        Overflow::Checked => return Err(format!("{} {} {} overflows", a, op, b)),
+        Overflow::Wrapping => match op {
+            IntOp::Add => a.wrapping_add(b),
+            IntOp::Subtract => a.wrapping_sub(b),
+            IntOp::Multiply => a.wrapping_mul(b),
+            // Only i64::MIN // -1 overflows
+            IntOp::FloorDivide => a.wrapping_div(b),
+            IntOp::Modulo => floor_modulo(a, b),
+            IntOp::Power => a.wrapping_pow(width(op, &a, &b)?),
+            // Bits shifted past the top are lost
+            IntOp::ShiftLeft => a.checked_shl(width(op, &a, &b)?).unwrap_or(0),
+            IntOp::BitAnd | IntOp::BitOr | IntOp::BitXor | IntOp::ShiftRight => {
+                unreachable!("{} cannot overflow", op)
+            }
+        },
+        Overflow::Saturating => match op {
+            IntOp::Add => a.saturating_add(b),
+            IntOp::Subtract => a.saturating_sub(b),
+            IntOp::Multiply => a.saturating_mul(b),
+            IntOp::FloorDivide => a.saturating_div(b),
+            IntOp::Modulo => floor_modulo(a, b),
+            IntOp::Power => a.saturating_pow(width(op, &a, &b)?),
+            IntOp::ShiftLeft if a < 0 => i64::MIN,
+            IntOp::ShiftLeft => i64::MAX,
+            IntOp::BitAnd | IntOp::BitOr | IntOp::BitXor | IntOp::ShiftRight => {
+                unreachable!("{} cannot overflow", op)
+            }
+        },
+        Overflow::Big => return apply_big(op, &BigInt::from(a), &BigInt::from(b)),
+    };
+    Ok(Value::Integer(result))
+}
+
+// `a op b` for integers of any size, or a message saying the result would have more than
+// `MAX_BITS` bits. A divisor must already be known not to be zero.
+pub fn apply_big(op: IntOp, a: &BigInt, b: &BigInt) -> Result<Value, String> {
+    if result_bits(op, a, b).is_some_and(|bits| bits <= MAX_BITS) {
+        return Ok(normalize(match op {
+            IntOp::Add => a + b,
+            IntOp::Subtract => a - b,
+            IntOp::Multiply => a * b,
+            IntOp::FloorDivide => a.div_floor(b),
+            IntOp::Modulo => a.mod_floor(b),
+            IntOp::Power => a.pow(width(op, &operand(a), b)?),
+            IntOp::BitAnd => a & b,
+            IntOp::BitOr => a | b,
+            IntOp::BitXor => a ^ b,
+            IntOp::ShiftLeft => a << width(op, &operand(a), b)?,
+            IntOp::ShiftRight => a >> width(op, &operand(a), b)?,
+        }));
+    }
+    Err(format!(
+        "{} {} {} would have more than {} bits",
+        operand(a),
+        op,
+        operand(b),
+        MAX_BITS
+    ))
+}
+
+// How an error message shows a big integer: written out if short, otherwise by its size, since the
+// digits of a huge one take long to work out and say nothing useful
+fn operand(n: &BigInt) -> String {
+    if n.bits() <= 256 {
+        n.to_string()
+    } else {
+        format!("a {}-bit integer", n.bits())
+    }
+}
+
+// At most how many bits `a op b` has, or None if that does not even fit in a u64
+pub fn result_bits(op: IntOp, a: &BigInt, b: &BigInt) -> Option<u64> {
+    let (a_bits, b_bits) = (a.bits(), b.bits());
+    match op {
+        // 0, 1 and -1 stay that size whatever the exponent
+        IntOp::Power if a_bits <= 1 => Some(1),
+        IntOp::Power => a_bits.checked_mul(b.to_u64()?),
+        IntOp::ShiftLeft if a_bits == 0 => Some(0),
+        IntOp::ShiftLeft => a_bits.checked_add(b.to_u64()?),
+        IntOp::Multiply => Some(a_bits + b_bits),
+        _ => Some(a_bits.max(b_bits) + 1),
+    }
+}
+
+// A power's exponent or a shift's width, which must fit in a u32
+fn width(
+    op: IntOp,
+    a: &impl fmt::Display,
+    b: &(impl ToPrimitive + fmt::Display),
+) -> Result<u32, String> {
+    b.to_u32()
+        .ok_or_else(|| format!("{} {} {} is out of range", a, op, b))
+}
+
+// Division rounding towards negative infinity, so that `a == (a // b) * b + a % b`
+fn floor_divide(a: i64, b: i64) -> Option<i64> {
+    let quotient = a.checked_div(b)?;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        Some(quotient - 1)
+    } else {
+        Some(quotient)
+    }
+}
+
+// The remainder of `floor_divide`, which takes the sign of the divisor
+fn floor_modulo(a: i64, b: i64) -> i64 {
+    let remainder = a.wrapping_rem(b);
+    if remainder != 0 && (remainder < 0) != (b < 0) {
+        remainder + b
+    } else {
+        remainder
+    }
+}
+
+// `a << width`, unless bits other than copies of the sign would be lost
+fn shift_left(a: i64, width: u32) -> Option<i64> {
+    if width >= 64 {
+        return (a == 0).then_some(0);
+    }
+    let shifted = a << width;
+    (shifted >> width == a).then_some(shifted)
+}
+
+// An arithmetic shift, rounding towards negative infinity like `//`
+fn shift_right(a: i64, width: u32) -> i64 {
+    a >> width.min(63)
+}
+
+// A plain integer if the value fits in one
+pub fn normalize(n: BigInt) -> Value {
+    match n.to_i64() {
+        Some(i) => Value::Integer(i),
+        None => Value::BigInt(Rc::new(n)),
+    }
+}
+
+// Both operands as big integers, when at least one of them is big and neither is anything else
+pub fn big_operands(left: &Value, right: &Value) -> Option<(BigInt, BigInt)> {
+    match (left, right) {
+        (Value::BigInt(a), Value::BigInt(b)) => Some((a.as_ref().clone(), b.as_ref().clone())),
+        (Value::BigInt(a), Value::Integer(b)) => Some((a.as_ref().clone(), BigInt::from(*b))),
+        (Value::Integer(a), Value::BigInt(b)) => Some((BigInt::from(*a), b.as_ref().clone())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [Overflow; 4] = [
+        Overflow::Checked,
+        Overflow::Wrapping,
+        Overflow::Saturating,
+        Overflow::Big,
+    ];
+
+    fn int(result: Result<Value, String>) -> i64 {
+        match result {
+            Ok(Value::Integer(i)) => i,
+            other => panic!("expected an integer, got {:?}", other),
+        }
+    }
+
+    fn big(result: Result<Value, String>) -> BigInt {
+        match result {
+            Ok(Value::BigInt(n)) => n.as_ref().clone(),
+            other => panic!("expected a big integer, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn division_rounds_down() {
+        for mode in MODES {
+            assert_eq!(int(apply(IntOp::FloorDivide, 7, 2, mode)), 3);
+            assert_eq!(int(apply(IntOp::FloorDivide, -7, 2, mode)), -4);
+            assert_eq!(int(apply(IntOp::FloorDivide, 7, -2, mode)), -4);
+            assert_eq!(int(apply(IntOp::Modulo, -7, 3, mode)), 2);
+            assert_eq!(int(apply(IntOp::Modulo, 7, -3, mode)), -2);
+        }
+    }
+
+    #[test]
+    fn smallest_integer_divided_by_minus_one() {
+        let op = IntOp::FloorDivide;
+        let error = apply(op, i64::MIN, -1, Overflow::Checked).unwrap_err();
+        assert_eq!(error, "-9223372036854775808 // -1 overflows");
+        assert_eq!(int(apply(op, i64::MIN, -1, Overflow::Wrapping)), i64::MIN);
+        assert_eq!(int(apply(op, i64::MIN, -1, Overflow::Saturating)), i64::MAX);
+        assert_eq!(
+            big(apply(op, i64::MIN, -1, Overflow::Big)),
+            -BigInt::from(i64::MIN)
+        );
+        // The remainder is zero, which always fits
+        for mode in MODES {
+            assert_eq!(int(apply(IntOp::Modulo, i64::MIN, -1, mode)), 0);
+        }
+    }
+
+    #[test]
+    fn shifts_of_64_bits_or_more() {
+        let left = IntOp::ShiftLeft;
+        assert!(apply(left, 1, 64, Overflow::Checked).is_err());
+        assert_eq!(int(apply(left, 0, 100, Overflow::Checked)), 0);
+        assert_eq!(int(apply(left, 1, 64, Overflow::Wrapping)), 0);
+        assert_eq!(int(apply(left, 1, 64, Overflow::Saturating)), i64::MAX);
+        assert_eq!(int(apply(left, -1, 64, Overflow::Saturating)), i64::MIN);
+        assert_eq!(
+            big(apply(left, 1, 64, Overflow::Big)),
+            BigInt::from(1) << 64
+        );
+        for mode in MODES {
+            assert_eq!(int(apply(IntOp::ShiftRight, 8, 64, mode)), 0);
+            assert_eq!(int(apply(IntOp::ShiftRight, -8, 100, mode)), -1);
+        }
+    }
+
+    #[test]
+    fn exponents_and_widths_outside_a_u32_are_errors() {
+        for mode in MODES {
+            let error = apply(IntOp::Power, 2, -1, mode).unwrap_err();
+            assert_eq!(error, "2 ** -1 is out of range");
+            assert!(apply(IntOp::ShiftLeft, 1, -1, mode).is_err());
+            assert!(apply(IntOp::Power, 1, i64::from(u32::MAX) + 1, mode).is_err());
+        }
+        let (two, minus_one) = (BigInt::from(2), BigInt::from(-1));
+        assert!(apply_big(IntOp::Power, &two, &minus_one).is_err());
+        assert!(apply_big(IntOp::ShiftRight, &two, &minus_one).is_err());
+    }
+
+    #[test]
+    fn big_results_are_capped() {
+        let three = BigInt::from(3);
+        let error = apply_big(IntOp::Power, &three, &BigInt::from(100_000_000)).unwrap_err();
+        assert_eq!(error, "3 ** 100000000 would have more than 16777216 bits");
+        assert!(apply_big(IntOp::ShiftLeft, &BigInt::from(1), &BigInt::from(MAX_BITS)).is_err());
+        let wide = BigInt::from(1) << (MAX_BITS / 2 + 1);
+        let error = apply_big(IntOp::Multiply, &wide, &wide).unwrap_err();
+        assert!(error.starts_with("a 8388610-bit integer * a 8388610-bit integer"));
+        // Powers of 0, 1 and -1 never grow
+        let exponent = BigInt::from(u32::MAX);
+        assert_eq!(int(apply_big(IntOp::Power, &BigInt::from(1), &exponent)), 1);
+        assert_eq!(
+            int(apply_big(IntOp::Power, &BigInt::from(-1), &exponent)),
+            -1
+        );
+        assert_eq!(
+            big(apply_big(IntOp::Power, &BigInt::from(2), &BigInt::from(64))),
+            BigInt::from(1) << 64
+        );
+    }
+
+    #[test]
+    fn results_that_fit_are_plain_integers() {
+        let huge = BigInt::from(1) << 70;
+        assert_eq!(int(apply_big(IntOp::Subtract, &huge, &huge)), 0);
+        assert_eq!(
+            int(apply_big(IntOp::ShiftRight, &huge, &BigInt::from(10))),
+            1 << 60
+        );
+    }
+}
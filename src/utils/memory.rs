@@ -0,0 +1,56 @@
+// Counts the bytes allocated and not yet freed on each thread, so an interpreter can tell roughly
+// how much memory its script's values hold. Values live in `Rc`s, so they never leave the thread
+// that made them.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct Counter;
+
+#[global_allocator]
+static COUNTER: Counter = Counter;
+
+thread_local! {
+    // Below zero on a thread that freed memory another thread allocated
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+}
+
+fn record(change: isize) {
+    // Nothing is counted while the thread is being torn down
+    let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get().wrapping_add(change)));
+}
+
+unsafe impl GlobalAlloc for Counter {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let pointer = System.alloc(layout);
+        if !pointer.is_null() {
+            record(layout.size() as isize);
+        }
+        pointer
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let pointer = System.alloc_zeroed(layout);
+        if !pointer.is_null() {
+            record(layout.size() as isize);
+        }
+        pointer
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        System.dealloc(pointer, layout);
+        record(-(layout.size() as isize));
+    }
+
+    unsafe fn realloc(&self, pointer: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let moved = System.realloc(pointer, layout, new_size);
+        if !moved.is_null() {
+            record(new_size as isize - layout.size() as isize);
+        }
+        moved
+    }
+}
+
+// Bytes allocated on this thread and not yet freed
+pub fn allocated() -> isize {
+    ALLOCATED.with(Cell::get)
+}
@@ -1,2 +1,15 @@
+pub mod ast;
+pub mod checker;
+pub mod errors;
+pub mod integers;
+pub mod memory;
+pub mod optimizer;
+pub mod options;
+pub mod parser;
+pub mod printer;
+pub mod resolver;
+pub mod sandbox;
+pub mod symbols;
 pub mod tokeniser;
+pub mod types;
 pub mod variables;
@@ -0,0 +1,585 @@
+// Rewrites a resolved program into a simpler one that behaves the same, for `-O`:
+//
+// - operations on constants are worked out in advance, as are conditions like `1 < 2`
+// - `if` branches that can never run are removed, and the one that always runs takes the `if`'s
+//   place, since blocks share their function's scope
+// - loops whose condition is always false are removed
+// - statements after a `return` or `throw` in the same block are removed
+// - `x + 0`, `x * 1` and similar are replaced by `x`, where `x` is sure to give a number
+// - operations in a loop condition that give the same value on every pass are worked out once,
+//   into a hidden variable, before the loop starts, unless nil operands print a warning
+//
+// Constants are worked out by the interpreter itself, so they give exactly what the program would.
+// Anything that would fail, involves nil, or overflows is left for the program to do, which makes
+// the result the same whatever options the program is then run with. The one exception is
+// `--nil-mode warn`: a hoisted operation on nil would warn once instead of on every pass, so
+// nothing is hoisted for that mode, and a program optimized for another mode warns less in it.
+use crate::interpreter::Interpreter;
+use crate::utils::ast::*;
+use crate::utils::options::{NilMode, Options};
+use crate::utils::symbols::Symbol;
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+use std::mem::take;
+use std::rc::Rc;
+
+// What an expression is sure to give, if it gives anything
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Numeric {
+    Integer,
+    Number,
+}
+
+pub struct Optimizer {
+    // Works out constant operations, with checked integers and no nil
+    evaluator: Interpreter,
+    // How many slots each enclosing scope uses, innermost last
+    scopes: Vec<usize>,
+    // The program's top-level variable names by slot, which hoisted values at the top level join
+    globals: Vec<Symbol>,
+    // How many values have been hoisted out of loops, for naming their variables
+    hoisted: usize,
+    // Whether loop conditions may be hoisted, which is not done when nil operands warn
+    hoist: bool,
+}
+
+impl Optimizer {
+    pub fn optimize(script: &mut Script, nil_mode: NilMode) {
+        let mut optimizer = Optimizer {
+            evaluator: Interpreter::new(Options::default()),
+            scopes: vec![script.globals.len()],
+            globals: take(&mut script.globals),
+            hoisted: 0,
+            hoist: nil_mode != NilMode::Warn,
+        };
+        script.statements = optimizer.block(take(&mut script.statements));
+        script.globals = optimizer.globals;
+    }
+
+    fn block(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        let mut optimized = Vec::with_capacity(statements.len());
+        for statement in statements {
+            self.statement(statement, &mut optimized);
+            // Nothing after a return or throw in the same block can run
+            if let Some(last) = optimized.last() {
+                if matches!(last.kind, StmtKind::Return(_) | StmtKind::Throw(_)) {
+                    break;
+                }
+            }
+        }
+        optimized
+    }
+
+    // Adds the optimized form of a statement to `out`: none, one or several statements
+    fn statement(&mut self, mut statement: Stmt, out: &mut Vec<Stmt>) {
+        match &mut statement.kind {
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                if let ExprKind::Literal(Value::Boolean(taken)) = condition.kind {
+                    let branch = if taken {
+                        take(then_branch)
+                    } else {
+                        else_branch.take().unwrap_or_default()
+                    };
+                    out.extend(self.block(branch));
+                    return;
+                }
+                *then_branch = self.block(take(then_branch));
+                if let Some(else_branch) = else_branch {
+                    *else_branch = self.block(take(else_branch));
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.expression(condition);
+                if matches!(condition.kind, ExprKind::Literal(Value::Boolean(false))) {
+                    return;
+                }
+                *body = self.block(take(body));
+                out.extend(self.hoist(condition, body, None));
+            }
+            StmtKind::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.simple(init);
+                }
+                if let Some(condition) = condition {
+                    self.expression(condition);
+                    if matches!(condition.kind, ExprKind::Literal(Value::Boolean(false))) {
+                        out.extend(init.take().map(|init| *init));
+                        return;
+                    }
+                }
+                if let Some(update) = update {
+                    self.simple(update);
+                }
+                *body = self.block(take(body));
+                if let Some(condition) = condition {
+                    let hoisted = self.hoist(condition, body, update.as_deref());
+                    // The hoisted values may use what the loop starts with
+                    if !hoisted.is_empty() {
+                        out.extend(init.take().map(|init| *init));
+                        out.extend(hoisted);
+                    }
+                }
+            }
+            StmtKind::Function { decl, .. } => self.function(decl),
+            StmtKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                *body = self.block(take(body));
+                if let Some(catch) = catch {
+                    catch.body = self.block(take(&mut catch.body));
+                }
+                if let Some(finally) = finally {
+                    *finally = self.block(take(finally));
+                }
+            }
+            _ => self.simple(&mut statement),
+        }
+        out.push(statement);
+    }
+
+    // Statements that contain expressions but no other statements
+    fn simple(&mut self, statement: &mut Stmt) {
+        match &mut statement.kind {
+            StmtKind::Print(print) => {
+                for expr in print
+                    .values
+                    .iter_mut()
+                    .chain(&mut print.sep)
+                    .chain(&mut print.end)
+                {
+                    self.expression(expr);
+                }
+            }
+            StmtKind::Variable { init, ty, .. } => {
+                self.operands(init);
+                // A string literal given to a variable is read as whatever type it looks like,
+                // so a string worked out in advance must not become one
+                match self.simplify(init) {
+                    Some(simpler)
+                        if *ty != Some(Type::String)
+                            && matches!(simpler.kind, ExprKind::Literal(Value::String(_))) => {}
+                    Some(simpler) => *init = simpler,
+                    None => {}
+                }
+            }
+            StmtKind::Update {
+                value: Some(expr), ..
+            }
+            | StmtKind::Return(Some(expr))
+            | StmtKind::Throw(expr)
+            | StmtKind::Expression(expr) => self.expression(expr),
+            _ => {}
+        }
+    }
+
+    // A function body is a new scope, which starts with as many slots as it declares
+    fn function(&mut self, decl: &mut Rc<FunctionDecl>) {
+        let decl = Rc::get_mut(decl).expect("functions are not shared until they are run");
+        let mut slots = decl.params.len();
+        Self::declared(&decl.body, &mut slots);
+        self.scopes.push(slots);
+        decl.body = self.block(take(&mut decl.body));
+        self.scopes.pop();
+    }
+
+    // Raises `slots` to cover every slot declared in a scope
+    fn declared(statements: &[Stmt], slots: &mut usize) {
+        for statement in statements {
+            match &statement.kind {
+                StmtKind::Variable { slot, .. }
+                | StmtKind::Function { slot, .. }
+                | StmtKind::Import { slot, .. } => *slots = (*slots).max(slot + 1),
+                StmtKind::If {
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    Self::declared(then_branch, slots);
+                    if let Some(else_branch) = else_branch {
+                        Self::declared(else_branch, slots);
+                    }
+                }
+                StmtKind::While { body, .. } => Self::declared(body, slots),
+                StmtKind::For {
+                    init, update, body, ..
+                } => {
+                    for clause in init.iter().chain(update) {
+                        Self::declared(std::slice::from_ref(clause), slots);
+                    }
+                    Self::declared(body, slots);
+                }
+                StmtKind::Try {
+                    body,
+                    catch,
+                    finally,
+                } => {
+                    Self::declared(body, slots);
+                    if let Some(catch) = catch {
+                        *slots = (*slots).max(catch.slot + 1);
+                        Self::declared(&catch.body, slots);
+                    }
+                    if let Some(finally) = finally {
+                        Self::declared(finally, slots);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expr) {
+        self.operands(expr);
+        if let Some(simpler) = self.simplify(expr) {
+            *expr = simpler;
+        }
+    }
+
+    fn operands(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Literal(_) | ExprKind::Variable { .. } => {}
+            ExprKind::Unary { expr, .. } => self.expression(expr),
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExprKind::Call { callee, args } => {
+                self.expression(callee);
+                for arg in args {
+                    self.expression(arg);
+                }
+            }
+            ExprKind::List(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            }
+            ExprKind::Index { object, index } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            ExprKind::Get { object, .. } => self.expression(object),
+            ExprKind::Function(decl) => self.function(decl),
+        }
+    }
+
+    // What an expression whose operands are already optimized can be replaced with, if anything
+    fn simplify(&mut self, expr: &Expr) -> Option<Expr> {
+        let span = expr.span;
+        let literal = |value| Expr {
+            kind: ExprKind::Literal(value),
+            span,
+        };
+        match &expr.kind {
+            ExprKind::Unary { op, expr: operand } => match &operand.kind {
+                ExprKind::Literal(value) if !matches!(value, Value::Nil) => self
+                    .evaluator
+                    .unary(*op, value.clone(), span)
+                    .ok()
+                    .map(literal),
+                _ => None,
+            },
+            ExprKind::Binary { left, op, right } => match (&left.kind, &right.kind) {
+                (ExprKind::Literal(a), ExprKind::Literal(b))
+                    if !matches!(a, Value::Nil) && !matches!(b, Value::Nil) =>
+                {
+                    self.evaluator
+                        .arithmetic(*op, a.clone(), b.clone(), span)
+                        .ok()
+                        .map(literal)
+                }
+                _ => Self::identity(left, *op, right).cloned(),
+            },
+            ExprKind::Logical { left, op, right } => match (op, &left.kind, &right.kind) {
+                (LogicalOp::And, ExprKind::Literal(Value::Boolean(false)), _)
+                | (LogicalOp::Or, ExprKind::Literal(Value::Boolean(true)), _) => {
+                    Some((**left).clone())
+                }
+                (
+                    LogicalOp::And | LogicalOp::Or,
+                    ExprKind::Literal(Value::Boolean(_)),
+                    ExprKind::Literal(Value::Boolean(_)),
+                ) => Some((**right).clone()),
+                (LogicalOp::Coalesce, ExprKind::Literal(Value::Nil), _) => Some((**right).clone()),
+                (LogicalOp::Coalesce, ExprKind::Literal(_), _) => Some((**left).clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // The operand an operation with 0 or 1 gives back unchanged. Only a number does: `+ 0` turns
+    // nil into 0, and fails for a string, so the operand must be sure to give one. Adding 0 turns
+    // the float -0.0 into 0.0, so that needs an integer.
+    fn identity<'e>(left: &'e Expr, op: BinaryOp, right: &'e Expr) -> Option<&'e Expr> {
+        let is = |expr: &Expr, n: i64| matches!(expr.kind, ExprKind::Literal(Value::Integer(v)) if v == n);
+        let integer = |expr: &Expr| Self::numeric(expr) == Some(Numeric::Integer);
+        let number = |expr: &Expr| Self::numeric(expr).is_some();
+        match op {
+            BinaryOp::Add | BinaryOp::BitOr | BinaryOp::BitXor => {
+                if is(right, 0) && integer(left) {
+                    Some(left)
+                } else if is(left, 0) && integer(right) {
+                    Some(right)
+                } else {
+                    None
+                }
+            }
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRight if is(right, 0) && integer(left) => {
+                Some(left)
+            }
+            BinaryOp::FloorDivide if is(right, 1) && integer(left) => Some(left),
+            BinaryOp::Subtract if is(right, 0) && number(left) => Some(left),
+            BinaryOp::Power if is(right, 1) && number(left) => Some(left),
+            BinaryOp::Multiply => {
+                if is(right, 1) && number(left) {
+                    Some(left)
+                } else if is(left, 1) && number(right) {
+                    Some(right)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Whether an expression either fails or gives a number, and whether that is an integer
+    fn numeric(expr: &Expr) -> Option<Numeric> {
+        match &expr.kind {
+            ExprKind::Literal(Value::Integer(_) | Value::BigInt(_)) => Some(Numeric::Integer),
+            ExprKind::Literal(Value::Float(_)) => Some(Numeric::Number),
+            ExprKind::Unary {
+                op: UnaryOp::BitNot,
+                ..
+            } => Some(Numeric::Integer),
+            ExprKind::Unary {
+                op: UnaryOp::Negate,
+                expr,
+            } => Self::numeric(expr),
+            ExprKind::Binary { left, op, right } => {
+                let (left, right) = (Self::numeric(left), Self::numeric(right));
+                match op {
+                    BinaryOp::BitAnd
+                    | BinaryOp::BitOr
+                    | BinaryOp::BitXor
+                    | BinaryOp::ShiftLeft
+                    | BinaryOp::ShiftRight => Some(Numeric::Integer),
+                    // Only numbers and nil can be subtracted, multiplied or divided
+                    BinaryOp::Subtract
+                    | BinaryOp::Multiply
+                    | BinaryOp::FloorDivide
+                    | BinaryOp::Modulo
+                        if left == Some(Numeric::Integer) && right == Some(Numeric::Integer) =>
+                    {
+                        Some(Numeric::Integer)
+                    }
+                    BinaryOp::Subtract
+                    | BinaryOp::Multiply
+                    | BinaryOp::Divide
+                    | BinaryOp::FloorDivide
+                    | BinaryOp::Modulo
+                    | BinaryOp::Power => Some(Numeric::Number),
+                    // Strings can be added too
+                    BinaryOp::Add => match (left?, right?) {
+                        (Numeric::Integer, Numeric::Integer) => Some(Numeric::Integer),
+                        _ => Some(Numeric::Number),
+                    },
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Moves the operations in a loop condition that give the same value every time into hidden
+    // variables declared before the loop, returning the declarations. A loop that calls or
+    // creates a function is left alone, as a function could change any variable. Only operands
+    // the condition always evaluates are moved, so nothing is worked out that would not have been.
+    fn hoist(&mut self, condition: &mut Expr, body: &[Stmt], update: Option<&Stmt>) -> Vec<Stmt> {
+        if !self.hoist {
+            return Vec::new();
+        }
+        let mut stored = Vec::new();
+        let changes_known = !Self::calls(condition)
+            && Self::stores(body, &mut stored)
+            && update.is_none_or(|update| Self::store(update, &mut stored));
+        let mut hoisted = Vec::new();
+        if changes_known {
+            self.hoist_operands(condition, &stored, &mut hoisted);
+        }
+        hoisted
+    }
+
+    fn hoist_operands(&mut self, expr: &mut Expr, stored: &[Slot], hoisted: &mut Vec<Stmt>) {
+        let operands: Vec<&mut Expr> = match &mut expr.kind {
+            ExprKind::Unary { expr, .. } => vec![expr],
+            ExprKind::Binary { left, right, .. } => vec![left, right],
+            // The right side of `and`, `or` and `??` does not always run
+            ExprKind::Logical { left, .. } => vec![left],
+            _ => Vec::new(),
+        };
+        for operand in operands {
+            let operation = matches!(
+                operand.kind,
+                ExprKind::Unary { .. } | ExprKind::Binary { .. }
+            );
+            if operation && Self::invariant(operand, stored) {
+                hoisted.push(self.hoisted_variable(operand));
+            } else {
+                self.hoist_operands(operand, stored, hoisted);
+            }
+        }
+    }
+
+    // Whether an expression is made of constants and variables the loop never stores to
+    fn invariant(expr: &Expr, stored: &[Slot]) -> bool {
+        match &expr.kind {
+            ExprKind::Literal(_) => true,
+            ExprKind::Variable { slot, .. } => slot.is_none_or(|slot| !stored.contains(&slot)),
+            ExprKind::Unary { expr, .. } => Self::invariant(expr, stored),
+            ExprKind::Binary { left, right, .. } => {
+                Self::invariant(left, stored) && Self::invariant(right, stored)
+            }
+            _ => false,
+        }
+    }
+
+    // Replaces an operand with a new variable in the current scope, giving its declaration
+    fn hoisted_variable(&mut self, operand: &mut Expr) -> Stmt {
+        self.hoisted += 1;
+        // `$` cannot start an identifier, so no script can use the name
+        let name = Symbol::intern(&format!("${}", self.hoisted));
+        let slots = self.scopes.last_mut().expect("a scope");
+        let index = *slots;
+        *slots += 1;
+        if self.scopes.len() == 1 {
+            self.globals.push(name);
+        }
+        let span = operand.span;
+        let variable = Expr {
+            kind: ExprKind::Variable {
+                name,
+                slot: Some(Slot { depth: 0, index }),
+            },
+            span,
+        };
+        Stmt {
+            kind: StmtKind::Variable {
+                name,
+                ty: None,
+                init: std::mem::replace(operand, variable),
+                constant: false,
+                slot: index,
+            },
+            span,
+        }
+    }
+
+    // Collects the variables some statements store to, or gives false if they call or create a
+    // function
+    fn stores(statements: &[Stmt], stored: &mut Vec<Slot>) -> bool {
+        statements
+            .iter()
+            .all(|statement| Self::store(statement, stored))
+    }
+
+    fn store(statement: &Stmt, stored: &mut Vec<Slot>) -> bool {
+        let local = |index| Slot { depth: 0, index };
+        match &statement.kind {
+            StmtKind::Print(print) => {
+                !Self::calls_any(print.values.iter().chain(&print.sep).chain(&print.end))
+            }
+            StmtKind::Variable { init, slot, .. } => {
+                stored.push(local(*slot));
+                !Self::calls(init)
+            }
+            StmtKind::Update { value, slot, .. } => {
+                stored.push(*slot);
+                !Self::calls_any(value)
+            }
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                !Self::calls(condition)
+                    && Self::stores(then_branch, stored)
+                    && else_branch
+                        .as_ref()
+                        .is_none_or(|branch| Self::stores(branch, stored))
+            }
+            StmtKind::While { condition, body } => {
+                !Self::calls(condition) && Self::stores(body, stored)
+            }
+            StmtKind::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                init.iter()
+                    .chain(update)
+                    .all(|clause| Self::store(clause, stored))
+                    && !Self::calls_any(condition)
+                    && Self::stores(body, stored)
+            }
+            StmtKind::Function { .. } => false,
+            StmtKind::Return(value) => !Self::calls_any(value),
+            StmtKind::Throw(expr) | StmtKind::Expression(expr) => !Self::calls(expr),
+            StmtKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                if let Some(catch) = catch {
+                    stored.push(local(catch.slot));
+                }
+                Self::stores(body, stored)
+                    && catch
+                        .as_ref()
+                        .is_none_or(|catch| Self::stores(&catch.body, stored))
+                    && finally
+                        .as_ref()
+                        .is_none_or(|finally| Self::stores(finally, stored))
+            }
+            // A module runs in its own scope, so all it changes here is its own variable
+            StmtKind::Import { slot, .. } => {
+                stored.push(local(*slot));
+                true
+            }
+            StmtKind::Export(_) => true,
+        }
+    }
+
+    fn calls_any<'e>(exprs: impl IntoIterator<Item = &'e Expr>) -> bool {
+        exprs.into_iter().any(Self::calls)
+    }
+
+    // Whether an expression calls or creates a function
+    fn calls(expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Literal(_) | ExprKind::Variable { .. } => false,
+            ExprKind::Unary { expr, .. } | ExprKind::Get { object: expr, .. } => Self::calls(expr),
+            ExprKind::Binary { left, right, .. }
+            | ExprKind::Logical { left, right, .. }
+            | ExprKind::Index {
+                object: left,
+                index: right,
+            } => Self::calls(left) || Self::calls(right),
+            ExprKind::List(items) => items.iter().any(Self::calls),
+            ExprKind::Call { .. } | ExprKind::Function(_) => true,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::utils::sandbox::SandboxPolicy;
+use std::time::Duration;
+
+// How deeply functions may call each other when `max_depth` is not given
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+// How arithmetic and conditions treat `nil` operands
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NilMode {
+    // `nil` quietly stands in for 0, 0.0, "" or false
+    #[default]
+    Coerce,
+    // Coerce, but print a warning to stderr each time
+    Warn,
+    // Raise a TypeError instead
+    Strict,
+}
+
+// What math functions do with arguments outside their domain, such as `sqrt(-1)`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MathDomain {
+    // Return NaN, as the underlying floating point operation would
+    #[default]
+    Nan,
+    // Raise a ValueError
+    Error,
+}
+
+// What happens when an integer result does not fit in 64 bits
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Overflow {
+    // Raise an OverflowError
+    #[default]
+    Checked,
+    // Wrap around, as two's complement arithmetic does
+    Wrapping,
+    // Stop at the largest or smallest integer
+    Saturating,
+    // Carry on with arbitrary precision integers
+    Big,
+}
+
+// Runtime settings for an `Interpreter`
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub nil_mode: NilMode,
+    pub math_domain: MathDomain,
+    pub overflow: Overflow,
+    // What the script may do outside the interpreter
+    pub sandbox: SandboxPolicy,
+    // Scripts and the modules they import are optimized before they run
+    pub optimize: bool,
+    // The most steps a script may take: statements and loop iterations for the tree-walker,
+    // instructions for the VM
+    pub max_steps: Option<u64>,
+    // How long a script may run, counted from when the interpreter is created
+    pub timeout: Option<Duration>,
+    // Roughly how many bytes the script's values may take up, counted from when the interpreter is
+    // created
+    pub max_memory: Option<usize>,
+    // How many function calls may be running at once, or `DEFAULT_MAX_DEPTH`
+    pub max_depth: Option<usize>,
+}
@@ -0,0 +1,861 @@
+use crate::utils::ast::*;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::symbols::Symbol;
+use crate::utils::tokeniser::{Span, Token};
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+use std::rc::Rc;
+
+pub struct Parser {
+    tokens: Vec<(Token, Span)>,
+    current: usize,
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<(Token, Span)>) -> Self {
+        Self { tokens, current: 0 }
+    }
+
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, BugError> {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, BugError> {
+        let span = self.span();
+        let kind = self.statement_kind()?;
+        Ok(Stmt { kind, span })
+    }
+
+    fn statement_kind(&mut self) -> Result<StmtKind, BugError> {
+        if self.match_word("print") {
+            return self.print_statement(PrintTarget::Stdout, true);
+        }
+        if self.match_word("write") {
+            return self.print_statement(PrintTarget::Stdout, false);
+        }
+        if self.match_word("eprint") {
+            return self.print_statement(PrintTarget::Stderr, true);
+        }
+        if self.match_word("variable") {
+            let stmt = self.variable_declaration(false)?;
+            self.expect_symbol(';', "after variable declaration")?;
+            return Ok(stmt);
+        }
+        if self.match_word("const") {
+            let stmt = self.variable_declaration(true)?;
+            self.expect_symbol(';', "after constant declaration")?;
+            return Ok(stmt);
+        }
+        if self.match_word("update") {
+            let stmt = self.update_clause()?;
+            self.expect_symbol(';', "after update statement")?;
+            return Ok(stmt);
+        }
+        if self.match_word("if") {
+            return self.if_statement();
+        }
+        if self.match_word("while") {
+            self.expect_symbol('(', "after 'while'")?;
+            let condition = self.expression()?;
+            self.expect_symbol(')', "after while condition")?;
+            let body = self.block()?;
+            return Ok(StmtKind::While { condition, body });
+        }
+        if self.match_word("for") {
+            return self.for_statement();
+        }
+        if self.match_word("function") {
+            let name = self.expect_identifier("after 'function'")?;
+            let decl = self.function_body(Some(name))?;
+            return Ok(StmtKind::Function { decl, slot: 0 });
+        }
+        if self.match_word("return") {
+            let value = if self.check_symbol(';') {
+                None
+            } else {
+                Some(self.expression()?)
+            };
+            self.expect_symbol(';', "after return value")?;
+            return Ok(StmtKind::Return(value));
+        }
+        if self.match_word("throw") {
+            let value = self.expression()?;
+            self.expect_symbol(';', "after thrown value")?;
+            return Ok(StmtKind::Throw(value));
+        }
+        if self.match_word("try") {
+            return self.try_statement();
+        }
+        if self.match_word("import") {
+            return self.import_statement();
+        }
+        if self.match_word("export") {
+            let mut names = vec![self.expect_identifier("after 'export'")?];
+            while self.match_symbol(',') {
+                names.push(self.expect_identifier("in export list")?);
+            }
+            self.expect_symbol(';', "after export list")?;
+            return Ok(StmtKind::Export(names));
+        }
+
+        let expr = self.expression()?;
+        self.expect_symbol(';', "after expression")?;
+        Ok(StmtKind::Expression(expr))
+    }
+
+    fn variable_declaration(&mut self, constant: bool) -> Result<StmtKind, BugError> {
+        let name = self.expect_identifier("in variable declaration")?;
+        let ty = self.type_annotation()?;
+        // `variable x;` declares x as nil, but a constant needs a value
+        if !constant && self.check_symbol(';') {
+            let init = Expr {
+                kind: ExprKind::Literal(Value::Nil),
+                span: self.span(),
+            };
+            return Ok(StmtKind::Variable {
+                name,
+                ty,
+                init,
+                constant,
+                slot: 0,
+            });
+        }
+        if !self.match_operator("=") {
+            return Err(self.error(if constant {
+                "Expected '=' operator in constant declaration."
+            } else {
+                "Expected '=' operator in variable declaration."
+            }));
+        }
+        let init = self.expression()?;
+        Ok(StmtKind::Variable {
+            name,
+            ty,
+            init,
+            constant,
+            slot: 0,
+        })
+    }
+
+    // An optional `: type` after a variable or parameter name
+    fn type_annotation(&mut self) -> Result<Option<Type>, BugError> {
+        if !self.match_symbol(':') {
+            return Ok(None);
+        }
+        self.type_name().map(Some)
+    }
+
+    fn type_name(&mut self) -> Result<Type, BugError> {
+        let name = match self.peek() {
+            Some(Token::Identifier(name)) | Some(Token::Keyword(name)) => *name,
+            _ => return Err(self.error("Expected a type name")),
+        };
+        match Type::from_name(name.as_str()) {
+            Some(ty) => {
+                self.advance();
+                Ok(ty)
+            }
+            None => Err(self.error(&format!("Unknown type '{}'", name))),
+        }
+    }
+
+    // Parses `x++`, `x--`, `x = v`, `x += v`, `x -= v` and the older `x + v` / `x - v`
+    fn update_clause(&mut self) -> Result<StmtKind, BugError> {
+        let name = self.expect_identifier("in update statement")?;
+        let (op, value) = if self.match_symbol('+') {
+            if self.match_symbol('+') {
+                (UpdateOp::Increment, None)
+            } else {
+                self.match_operator("=");
+                (UpdateOp::Add, Some(self.expression()?))
+            }
+        } else if self.match_symbol('-') {
+            if self.match_symbol('-') {
+                (UpdateOp::Decrement, None)
+            } else {
+                self.match_operator("=");
+                (UpdateOp::Subtract, Some(self.expression()?))
+            }
+        } else if self.match_operator("=") {
+            (UpdateOp::Assign, Some(self.expression()?))
+        } else {
+            return Err(self.error("Invalid variable update syntax"));
+        };
+        Ok(StmtKind::Update {
+            name,
+            op,
+            value,
+            slot: Slot::default(),
+        })
+    }
+
+    fn if_statement(&mut self) -> Result<StmtKind, BugError> {
+        self.expect_symbol('(', "after 'if'")?;
+        let condition = self.expression()?;
+        self.expect_symbol(')', "after if condition")?;
+        let then_branch = self.block()?;
+        let else_branch = if self.match_word("else") {
+            let span = self.span();
+            if self.match_word("if") {
+                let kind = self.if_statement()?;
+                Some(vec![Stmt { kind, span }])
+            } else {
+                Some(self.block()?)
+            }
+        } else {
+            None
+        };
+        Ok(StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    fn for_statement(&mut self) -> Result<StmtKind, BugError> {
+        self.expect_symbol('(', "after 'for'")?;
+        let init = if self.check_symbol(';') {
+            None
+        } else {
+            Some(Box::new(self.for_clause()?))
+        };
+        self.expect_symbol(';', "after for loop initialization")?;
+        let condition = if self.check_symbol(';') {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.expect_symbol(';', "after for loop condition")?;
+        let update = if self.check_symbol(')') {
+            None
+        } else {
+            Some(Box::new(self.for_clause()?))
+        };
+        self.expect_symbol(')', "after for loop header")?;
+        let body = self.block()?;
+        Ok(StmtKind::For {
+            init,
+            condition,
+            update,
+            body,
+        })
+    }
+
+    // A for loop header clause: a declaration or an update, with the keyword optional
+    fn for_clause(&mut self) -> Result<Stmt, BugError> {
+        let span = self.span();
+        if self.match_word("variable") {
+            let kind = self.variable_declaration(false)?;
+            return Ok(Stmt { kind, span });
+        }
+        self.match_word("update");
+        if let (Some(Token::Identifier(_)), Some(Token::Operator(op))) =
+            (self.peek(), self.peek_at(1))
+        {
+            if op == "=" {
+                let kind = self.variable_declaration(false)?;
+                return Ok(Stmt { kind, span });
+            }
+        }
+        let kind = self.update_clause()?;
+        Ok(Stmt { kind, span })
+    }
+
+    fn try_statement(&mut self) -> Result<StmtKind, BugError> {
+        let body = self.block()?;
+        let catch = if self.match_word("catch") {
+            self.expect_symbol('(', "after 'catch'")?;
+            let name = self.expect_identifier("in catch clause")?;
+            self.expect_symbol(')', "after catch variable")?;
+            let body = self.block()?;
+            Some(CatchClause {
+                name,
+                slot: 0,
+                body,
+            })
+        } else {
+            None
+        };
+        let finally = if self.match_word("finally") {
+            Some(self.block()?)
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error("Expected 'catch' or 'finally' after try block"));
+        }
+        Ok(StmtKind::Try {
+            body,
+            catch,
+            finally,
+        })
+    }
+
+    fn import_statement(&mut self) -> Result<StmtKind, BugError> {
+        let path = match self.advance() {
+            Some(Token::StringLiteral(path)) => path.clone(),
+            _ => return Err(self.error("Expected a file path after 'import'")),
+        };
+        // Without `as`, the module is named after its file
+        let alias = if self.match_word("as") {
+            self.expect_identifier("after 'as'")?
+        } else {
+            std::path::Path::new(&path)
+                .file_stem()
+                .map(|stem| Symbol::intern(&stem.to_string_lossy()))
+                .unwrap_or_else(|| Symbol::intern(""))
+        };
+        self.expect_symbol(';', "after import")?;
+        Ok(StmtKind::Import {
+            path,
+            alias,
+            slot: 0,
+        })
+    }
+
+    fn function_body(&mut self, name: Option<Symbol>) -> Result<Rc<FunctionDecl>, BugError> {
+        self.expect_symbol('(', "before function parameters")?;
+        let mut params = Vec::new();
+        if !self.check_symbol(')') {
+            loop {
+                let name = self.expect_identifier("in parameter list")?;
+                let ty = self.type_annotation()?;
+                params.push(Param { name, ty });
+                if !self.match_symbol(',') {
+                    break;
+                }
+            }
+        }
+        self.expect_symbol(')', "after function parameters")?;
+        let return_type = if self.match_operator("->") {
+            Some(self.type_name()?)
+        } else {
+            None
+        };
+        let body = self.block()?;
+        Ok(Rc::new(FunctionDecl {
+            name,
+            params,
+            return_type,
+            body,
+        }))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, BugError> {
+        self.expect_symbol('{', "before block")?;
+        let mut statements = Vec::new();
+        while !self.check_symbol('}') {
+            if self.is_at_end() {
+                return Err(self.error("Unterminated block, expected '}'"));
+            }
+            statements.push(self.statement()?);
+        }
+        self.advance();
+        Ok(statements)
+    }
+
+    fn expression(&mut self) -> Result<Expr, BugError> {
+        self.coalesce()
+    }
+
+    fn coalesce(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.or()?;
+        loop {
+            let span = self.span();
+            if !self.match_operator("??") {
+                break;
+            }
+            let right = self.or()?;
+            expr = Self::logical(expr, LogicalOp::Coalesce, right, span);
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.and()?;
+        loop {
+            let span = self.span();
+            if !self.match_word("or") {
+                break;
+            }
+            let right = self.and()?;
+            expr = Self::logical(expr, LogicalOp::Or, right, span);
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.equality()?;
+        loop {
+            let span = self.span();
+            if !self.match_word("and") {
+                break;
+            }
+            let right = self.equality()?;
+            expr = Self::logical(expr, LogicalOp::And, right, span);
+        }
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.comparison()?;
+        loop {
+            let span = self.span();
+            // A single `=` inside a condition has always meant equality
+            let op = if self.match_operator("==") || self.match_operator("=") {
+                BinaryOp::Equal
+            } else if self.match_operator("!=") {
+                BinaryOp::NotEqual
+            } else {
+                break;
+            };
+            let right = self.comparison()?;
+            expr = Self::binary(expr, op, right, span);
+        }
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.bit_or()?;
+        loop {
+            let span = self.span();
+            let op = if self.match_operator("<") {
+                BinaryOp::Less
+            } else if self.match_operator("<=") {
+                BinaryOp::LessEqual
+            } else if self.match_operator(">") {
+                BinaryOp::Greater
+            } else if self.match_operator(">=") {
+                BinaryOp::GreaterEqual
+            } else {
+                break;
+            };
+            let right = self.bit_or()?;
+            expr = Self::binary(expr, op, right, span);
+        }
+        Ok(expr)
+    }
+
+    // Bitwise operators bind looser than arithmetic but tighter than comparisons, so
+    // `flags & 4 == 4` tests a bit
+    fn bit_or(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.bit_xor()?;
+        loop {
+            let span = self.span();
+            if !self.match_symbol('|') {
+                break;
+            }
+            let right = self.bit_xor()?;
+            expr = Self::binary(expr, BinaryOp::BitOr, right, span);
+        }
+        Ok(expr)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.bit_and()?;
+        loop {
+            let span = self.span();
+            if !self.match_symbol('^') {
+                break;
+            }
+            let right = self.bit_and()?;
+            expr = Self::binary(expr, BinaryOp::BitXor, right, span);
+        }
+        Ok(expr)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.shift()?;
+        loop {
+            let span = self.span();
+            if !self.match_symbol('&') {
+                break;
+            }
+            let right = self.shift()?;
+            expr = Self::binary(expr, BinaryOp::BitAnd, right, span);
+        }
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.term()?;
+        loop {
+            let span = self.span();
+            let op = if self.match_operator("<<") {
+                BinaryOp::ShiftLeft
+            } else if self.match_operator(">>") {
+                BinaryOp::ShiftRight
+            } else {
+                break;
+            };
+            let right = self.term()?;
+            expr = Self::binary(expr, op, right, span);
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.factor()?;
+        loop {
+            let span = self.span();
+            let op = if self.match_symbol('+') {
+                BinaryOp::Add
+            } else if self.match_symbol('-') {
+                BinaryOp::Subtract
+            } else {
+                break;
+            };
+            let right = self.factor()?;
+            expr = Self::binary(expr, op, right, span);
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.unary()?;
+        loop {
+            let span = self.span();
+            let op = if self.match_symbol('*') {
+                BinaryOp::Multiply
+            } else if self.match_symbol('/') {
+                BinaryOp::Divide
+            } else if self.match_operator("//") {
+                BinaryOp::FloorDivide
+            } else if self.match_symbol('%') {
+                BinaryOp::Modulo
+            } else {
+                break;
+            };
+            let right = self.unary()?;
+            expr = Self::binary(expr, op, right, span);
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, BugError> {
+        let span = self.span();
+        let op = if self.match_symbol('-') {
+            UnaryOp::Negate
+        } else if self.match_word("not") || self.match_operator("!") {
+            UnaryOp::Not
+        } else if self.match_word("typeof") {
+            UnaryOp::TypeOf
+        } else if self.match_symbol('~') {
+            UnaryOp::BitNot
+        } else {
+            return self.power();
+        };
+        let expr = self.unary()?;
+        Ok(Expr {
+            kind: ExprKind::Unary {
+                op,
+                expr: Box::new(expr),
+            },
+            span,
+        })
+    }
+
+    // `**` binds tighter than a minus sign on its left and groups to the right, so `-2 ** 2` is
+    // -4 and `2 ** 3 ** 2` is 2 ** 9
+    fn power(&mut self) -> Result<Expr, BugError> {
+        let base = self.call()?;
+        let span = self.span();
+        if !self.match_operator("**") {
+            return Ok(base);
+        }
+        let exponent = self.unary()?;
+        Ok(Self::binary(base, BinaryOp::Power, exponent, span))
+    }
+
+    fn call(&mut self) -> Result<Expr, BugError> {
+        let mut expr = self.primary()?;
+        loop {
+            let span = self.span();
+            if self.match_symbol('(') {
+                let mut args = Vec::new();
+                if !self.check_symbol(')') {
+                    loop {
+                        args.push(self.expression()?);
+                        if !self.match_symbol(',') {
+                            break;
+                        }
+                    }
+                }
+                self.expect_symbol(')', "after call arguments")?;
+                expr = Expr {
+                    kind: ExprKind::Call {
+                        callee: Box::new(expr),
+                        args,
+                    },
+                    span,
+                };
+            } else if self.match_symbol('[') {
+                let index = self.expression()?;
+                self.expect_symbol(']', "after index")?;
+                expr = Expr {
+                    kind: ExprKind::Index {
+                        object: Box::new(expr),
+                        index: Box::new(index),
+                    },
+                    span,
+                };
+            } else if self.match_symbol('.') || self.match_operator("?.") {
+                let optional = matches!(self.previous(), Some(Token::Operator(_)));
+                let name = self.expect_identifier("after '.'")?;
+                expr = Expr {
+                    kind: ExprKind::Get {
+                        object: Box::new(expr),
+                        name,
+                        optional,
+                    },
+                    span,
+                };
+            } else {
+                break;
+            }
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, BugError> {
+        let span = self.span();
+        if self.match_word("nil") {
+            return Ok(Expr {
+                kind: ExprKind::Literal(Value::Nil),
+                span,
+            });
+        }
+        if self.match_word("fn") {
+            let decl = self.function_body(None)?;
+            return Ok(Expr {
+                kind: ExprKind::Function(decl),
+                span,
+            });
+        }
+        let token = match self.advance() {
+            Some(token) => token.clone(),
+            None => return Err(self.error("Unexpected end of input")),
+        };
+        let kind = match token {
+            Token::Number(n) => ExprKind::Literal(Value::Integer(n)),
+            Token::Float(f) => ExprKind::Literal(Value::Float(f)),
+            Token::StringLiteral(s) => ExprKind::Literal(Value::String(s.into())),
+            Token::Boolean(b) => ExprKind::Literal(Value::Boolean(b)),
+            Token::Identifier(name) => ExprKind::Variable { name, slot: None },
+            Token::Symbol('(') => {
+                let expr = self.expression()?;
+                self.expect_symbol(')', "after expression")?;
+                return Ok(expr);
+            }
+            Token::Symbol('[') => {
+                let mut items = Vec::new();
+                if !self.check_symbol(']') {
+                    loop {
+                        items.push(self.expression()?);
+                        if !self.match_symbol(',') {
+                            break;
+                        }
+                    }
+                }
+                self.expect_symbol(']', "after list items")?;
+                ExprKind::List(items)
+            }
+            // Most likely a comment from before they were written with `#`
+            Token::Operator(op) if op == "//" => {
+                return Err(BugError::new(
+                    ErrorKind::Syntax,
+                    "Unexpected '//', which is floor division: comments start with '#'",
+                    span,
+                ))
+            }
+            _ => {
+                return Err(BugError::new(
+                    ErrorKind::Syntax,
+                    format!("Unexpected token: {:?}", token),
+                    span,
+                ))
+            }
+        };
+        Ok(Expr { kind, span })
+    }
+
+    fn binary(left: Expr, op: BinaryOp, right: Expr, span: Span) -> Expr {
+        Expr {
+            kind: ExprKind::Binary {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            },
+            span,
+        }
+    }
+
+    fn logical(left: Expr, op: LogicalOp, right: Expr, span: Span) -> Expr {
+        Expr {
+            kind: ExprKind::Logical {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            },
+            span,
+        }
+    }
+
+    fn error(&self, message: &str) -> BugError {
+        BugError::new(ErrorKind::Syntax, message, self.span())
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.current >= self.tokens.len()
+    }
+
+    // The span of the next token, or of the last one at the end of input
+    fn span(&self) -> Span {
+        self.tokens
+            .get(self.current)
+            .or(self.tokens.last())
+            .map(|(_, span)| *span)
+            .unwrap_or_default()
+    }
+
+    // Values to print, separated by commas, then the optional `sep = ...` and `end = ...`
+    fn print_statement(
+        &mut self,
+        target: PrintTarget,
+        newline: bool,
+    ) -> Result<StmtKind, BugError> {
+        let mut stmt = PrintStmt {
+            values: Vec::new(),
+            sep: None,
+            end: None,
+            newline,
+            target,
+        };
+        if !self.check_symbol(';') {
+            loop {
+                match self.named_print_argument() {
+                    Some(name) => {
+                        let value = self.expression()?;
+                        let slot = if name == "sep" {
+                            &mut stmt.sep
+                        } else {
+                            &mut stmt.end
+                        };
+                        if slot.replace(value).is_some() {
+                            return Err(self.error(&format!("'{}' is given more than once", name)));
+                        }
+                    }
+                    None if stmt.sep.is_some() || stmt.end.is_some() => {
+                        return Err(self.error("Values to print must come before 'sep' and 'end'"));
+                    }
+                    None => stmt.values.push(self.expression()?),
+                }
+                if !self.match_symbol(',') {
+                    break;
+                }
+            }
+        }
+        self.expect_symbol(';', "after print statement")?;
+        Ok(StmtKind::Print(stmt))
+    }
+
+    // `sep =` or `end =`; anywhere else a single `=` would be read as equality
+    fn named_print_argument(&mut self) -> Option<&'static str> {
+        let name = match self.peek() {
+            Some(Token::Identifier(word)) if word == "sep" => "sep",
+            Some(Token::Identifier(word)) if word == "end" => "end",
+            _ => return None,
+        };
+        if !matches!(self.peek_at(1), Some(Token::Operator(op)) if op == "=") {
+            return None;
+        }
+        self.current += 2;
+        Some(name)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    fn previous(&self) -> Option<&Token> {
+        self.current
+            .checked_sub(1)
+            .and_then(|index| self.tokens.get(index))
+            .map(|(token, _)| token)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens
+            .get(self.current + offset)
+            .map(|(token, _)| token)
+    }
+
+    fn advance(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.current).map(|(token, _)| token);
+        if token.is_some() {
+            self.current += 1;
+        }
+        token
+    }
+
+    fn check_symbol(&self, symbol: char) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(c)) if *c == symbol)
+    }
+
+    fn match_symbol(&mut self, symbol: char) -> bool {
+        if self.check_symbol(symbol) {
+            self.current += 1;
+            return true;
+        }
+        false
+    }
+
+    fn match_operator(&mut self, operator: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Operator(op)) if op == operator) {
+            self.current += 1;
+            return true;
+        }
+        false
+    }
+
+    // Keywords are split between `Keyword` and `Identifier` tokens by the tokeniser
+    fn match_word(&mut self, word: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Keyword(w)) | Some(Token::Identifier(w)) if w == word)
+        {
+            self.current += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_symbol(&mut self, symbol: char, context: &str) -> Result<(), BugError> {
+        if self.match_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!(
+                "Expected '{}' {}, found {:?}",
+                symbol,
+                context,
+                self.peek()
+            )))
+        }
+    }
+
+    fn expect_identifier(&mut self, context: &str) -> Result<Symbol, BugError> {
+        match self.peek() {
+            Some(Token::Identifier(name)) => {
+                let name = *name;
+                self.current += 1;
+                Ok(name)
+            }
+            other => Err(self.error(&format!(
+                "Expected identifier {}, found {:?}",
+                context, other
+            ))),
+        }
+    }
+}
@@ -0,0 +1,300 @@
+// Writes a syntax tree back out as source, for `buglang dump`. Operations inside other operations
+// are always bracketed, so the listing shows exactly how the program was grouped.
+use crate::utils::ast::*;
+use crate::utils::variables::Value;
+use std::fmt::Write;
+
+const INDENT: &str = "    ";
+
+pub fn program(statements: &[Stmt]) -> String {
+    let mut listing = String::new();
+    block(&mut listing, statements, 0);
+    listing
+}
+
+fn block(listing: &mut String, statements: &[Stmt], depth: usize) {
+    for statement in statements {
+        listing.push_str(&INDENT.repeat(depth));
+        self::statement(listing, statement, depth);
+        listing.push('\n');
+    }
+}
+
+// A statement's body in braces, ending at the indentation of the statement itself
+fn body(listing: &mut String, statements: &[Stmt], depth: usize) {
+    listing.push_str("{\n");
+    block(listing, statements, depth + 1);
+    listing.push_str(&INDENT.repeat(depth));
+    listing.push('}');
+}
+
+fn statement(listing: &mut String, statement: &Stmt, depth: usize) {
+    match &statement.kind {
+        StmtKind::Print(print) => {
+            let keyword = match (print.target, print.newline) {
+                (PrintTarget::Stderr, _) => "eprint",
+                (PrintTarget::Stdout, true) => "print",
+                (PrintTarget::Stdout, false) => "write",
+            };
+            let mut arguments: Vec<String> = print
+                .values
+                .iter()
+                .map(|value| expression(value, depth))
+                .collect();
+            for (name, option) in [("sep", &print.sep), ("end", &print.end)] {
+                if let Some(option) = option {
+                    arguments.push(format!("{} = {}", name, expression(option, depth)));
+                }
+            }
+            if arguments.is_empty() {
+                write!(listing, "{};", keyword).unwrap();
+            } else {
+                write!(listing, "{} {};", keyword, arguments.join(", ")).unwrap();
+            }
+        }
+        StmtKind::If { .. } => if_statement(listing, statement, depth),
+        StmtKind::While {
+            condition,
+            body: statements,
+        } => {
+            write!(listing, "while ({}) ", expression(condition, depth)).unwrap();
+            body(listing, statements, depth);
+        }
+        StmtKind::For {
+            init,
+            condition,
+            update,
+            body: statements,
+        } => {
+            let clause = |clause: &Option<Box<Stmt>>| match clause {
+                Some(clause) => self::clause(clause, depth),
+                None => String::new(),
+            };
+            let condition = match condition {
+                Some(condition) => expression(condition, depth),
+                None => String::new(),
+            };
+            write!(
+                listing,
+                "for ({}; {}; {}) ",
+                clause(init),
+                condition,
+                clause(update)
+            )
+            .unwrap();
+            body(listing, statements, depth);
+        }
+        StmtKind::Function { decl, .. } => {
+            listing.push_str(&function("function ", decl, depth));
+        }
+        StmtKind::Return(None) => listing.push_str("return;"),
+        StmtKind::Return(Some(value)) => {
+            write!(listing, "return {};", expression(value, depth)).unwrap()
+        }
+        StmtKind::Throw(value) => write!(listing, "throw {};", expression(value, depth)).unwrap(),
+        StmtKind::Try {
+            body: statements,
+            catch,
+            finally,
+        } => {
+            listing.push_str("try ");
+            body(listing, statements, depth);
+            if let Some(catch) = catch {
+                write!(listing, " catch ({}) ", catch.name).unwrap();
+                body(listing, &catch.body, depth);
+            }
+            if let Some(finally) = finally {
+                listing.push_str(" finally ");
+                body(listing, finally, depth);
+            }
+        }
+        StmtKind::Import { path, alias, .. } => {
+            write!(listing, "import \"{}\" as {};", path, alias).unwrap()
+        }
+        StmtKind::Export(names) => {
+            let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+            write!(listing, "export {};", names.join(", ")).unwrap()
+        }
+        StmtKind::Expression(expr) => write!(listing, "{};", expression(expr, depth)).unwrap(),
+        StmtKind::Variable { .. } | StmtKind::Update { .. } => {
+            write!(listing, "{};", clause(statement, depth)).unwrap()
+        }
+    }
+}
+
+// `else if` chains are written as they would be in source
+fn if_statement(listing: &mut String, statement: &Stmt, depth: usize) {
+    let StmtKind::If {
+        condition,
+        then_branch,
+        else_branch,
+    } = &statement.kind
+    else {
+        return;
+    };
+    write!(listing, "if ({}) ", expression(condition, depth)).unwrap();
+    body(listing, then_branch, depth);
+    match else_branch.as_deref() {
+        Some(
+            [chained @ Stmt {
+                kind: StmtKind::If { .. },
+                ..
+            }],
+        ) => {
+            listing.push_str(" else ");
+            if_statement(listing, chained, depth);
+        }
+        Some(else_branch) => {
+            listing.push_str(" else ");
+            body(listing, else_branch, depth);
+        }
+        None => {}
+    }
+}
+
+// A declaration or update without its semicolon, as in a `for` header
+fn clause(statement: &Stmt, depth: usize) -> String {
+    match &statement.kind {
+        StmtKind::Variable {
+            name,
+            ty,
+            init,
+            constant,
+            ..
+        } => {
+            let keyword = if *constant { "const" } else { "variable" };
+            let annotation = match ty {
+                Some(ty) => format!(": {}", ty.name()),
+                None => String::new(),
+            };
+            format!(
+                "{} {}{} = {}",
+                keyword,
+                name,
+                annotation,
+                expression(init, depth)
+            )
+        }
+        StmtKind::Update {
+            name, op, value, ..
+        } => {
+            let value = value.as_ref().map(|value| expression(value, depth));
+            match (op, value) {
+                (UpdateOp::Increment, _) => format!("update {}++", name),
+                (UpdateOp::Decrement, _) => format!("update {}--", name),
+                (UpdateOp::Assign, Some(value)) => format!("update {} = {}", name, value),
+                (UpdateOp::Add, Some(value)) => format!("update {} += {}", name, value),
+                (UpdateOp::Subtract, Some(value)) => format!("update {} -= {}", name, value),
+                (_, None) => format!("update {}", name),
+            }
+        }
+        _ => {
+            let mut listing = String::new();
+            self::statement(&mut listing, statement, depth);
+            listing
+        }
+    }
+}
+
+fn function(keyword: &str, decl: &FunctionDecl, depth: usize) -> String {
+    let params: Vec<String> = decl
+        .params
+        .iter()
+        .map(|param| match param.ty {
+            Some(ty) => format!("{}: {}", param.name, ty.name()),
+            None => param.name.to_string(),
+        })
+        .collect();
+    let mut listing = format!(
+        "{}{}({}) ",
+        keyword,
+        decl.name.unwrap_or_default(),
+        params.join(", ")
+    );
+    if let Some(ty) = decl.return_type {
+        write!(listing, "-> {} ", ty.name()).unwrap();
+    }
+    body(&mut listing, &decl.body, depth);
+    listing
+}
+
+fn expression(expr: &Expr, depth: usize) -> String {
+    match &expr.kind {
+        ExprKind::Literal(value) => literal(value),
+        ExprKind::Variable { name, .. } => name.to_string(),
+        ExprKind::Unary { op, expr } => match op {
+            UnaryOp::Not | UnaryOp::TypeOf => format!("{} {}", op, operand(expr, depth)),
+            UnaryOp::Negate | UnaryOp::BitNot => format!("{}{}", op, operand(expr, depth)),
+        },
+        ExprKind::Binary { left, op, right } => {
+            format!("{} {} {}", operand(left, depth), op, operand(right, depth))
+        }
+        ExprKind::Logical { left, op, right } => {
+            let op = match op {
+                LogicalOp::And => "and",
+                LogicalOp::Or => "or",
+                LogicalOp::Coalesce => "??",
+            };
+            format!("{} {} {}", operand(left, depth), op, operand(right, depth))
+        }
+        ExprKind::Call { callee, args } => {
+            let args: Vec<String> = args.iter().map(|arg| expression(arg, depth)).collect();
+            format!("{}({})", target(callee, depth), args.join(", "))
+        }
+        ExprKind::List(items) => {
+            let items: Vec<String> = items.iter().map(|item| expression(item, depth)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        ExprKind::Index { object, index } => {
+            format!("{}[{}]", target(object, depth), expression(index, depth))
+        }
+        ExprKind::Get {
+            object,
+            name,
+            optional,
+        } => {
+            let dot = if *optional { "?." } else { "." };
+            format!("{}{}{}", target(object, depth), dot, name)
+        }
+        ExprKind::Function(decl) => function("fn", decl, depth),
+    }
+}
+
+// Floats keep their decimal point, so they can be told apart from integers
+fn literal(value: &Value) -> String {
+    match value {
+        Value::Float(f) => format!("{:?}", f),
+        Value::String(s) => format!("\"{}\"", s),
+        other => other.to_string(),
+    }
+}
+
+// An operand of an operator, bracketed if it is an operation itself or a negative number
+fn operand(expr: &Expr, depth: usize) -> String {
+    let bracketed = match &expr.kind {
+        ExprKind::Unary { .. } | ExprKind::Binary { .. } | ExprKind::Logical { .. } => true,
+        ExprKind::Literal(Value::Integer(i)) => *i < 0,
+        ExprKind::Literal(Value::BigInt(n)) => n.sign() == num_bigint::Sign::Minus,
+        ExprKind::Literal(Value::Float(f)) => f.is_sign_negative(),
+        _ => false,
+    };
+    let text = expression(expr, depth);
+    if bracketed {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+// What is called, indexed or has a property taken, bracketed unless it already ends in a name,
+// a call or an index
+fn target(expr: &Expr, depth: usize) -> String {
+    match &expr.kind {
+        ExprKind::Variable { .. }
+        | ExprKind::Call { .. }
+        | ExprKind::Index { .. }
+        | ExprKind::Get { .. }
+        | ExprKind::List(_) => expression(expr, depth),
+        _ => format!("({})", expression(expr, depth)),
+    }
+}
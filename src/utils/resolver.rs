@@ -0,0 +1,319 @@
+// Binds every variable to the slot it is stored in before a program runs, so finding one is an
+// index into its scope rather than a search by name, and names declared nowhere are reported
+// without running anything.
+//
+// Each function body is a scope, as is the top level of a file; blocks share their function's.
+// In its own scope a name means the declaration once that has been reached, and until then the
+// variable of an enclosing scope, if one declares it. An inner function only runs once the
+// scopes around it have started, so any declaration in an enclosing scope counts. A catch clause's
+// variable is the exception: it has a slot of its own, and means that slot only in the clause.
+use crate::utils::ast::*;
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::symbols::Symbol;
+use crate::utils::tokeniser::Span;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+#[derive(Default)]
+struct Scope {
+    // Every name declared anywhere in the scope, with its slot
+    slots: HashMap<Symbol, usize>,
+    // The name of each slot
+    names: Vec<Symbol>,
+    // The names whose declarations have been reached
+    declared: HashSet<Symbol>,
+    // The variables of the catch clauses being resolved, innermost last, with their slots. No name
+    // in `slots` refers to these, so they hide a variable of the same name only in the clause.
+    caught: Vec<(Symbol, usize)>,
+}
+
+impl Scope {
+    fn add(&mut self, name: Symbol) {
+        if !self.slots.contains_key(&name) {
+            self.slots.insert(name, self.names.len());
+            self.names.push(name);
+        }
+    }
+
+    // A slot for a catch clause's variable while the clause is resolved. Its name starts with `$`,
+    // which no script can write, so nothing that looks up slots by name finds it.
+    fn catch(&mut self, name: Symbol) -> usize {
+        let slot = self.names.len();
+        self.names.push(Symbol::intern(&format!("${}", name)));
+        self.caught.push((name, slot));
+        slot
+    }
+
+    fn caught(&self, name: Symbol) -> Option<usize> {
+        self.caught
+            .iter()
+            .rev()
+            .find(|(caught, _)| *caught == name)
+            .map(|(_, slot)| *slot)
+    }
+}
+
+pub struct Resolver<'a> {
+    scopes: Vec<Scope>,
+    is_builtin: &'a dyn Fn(&str) -> bool,
+    errors: Vec<BugError>,
+}
+
+impl<'a> Resolver<'a> {
+    // Resolves a file, giving its top-level variable names by slot and every undefined name
+    pub fn resolve(
+        statements: &mut [Stmt],
+        is_builtin: &'a dyn Fn(&str) -> bool,
+    ) -> (Vec<Symbol>, Vec<BugError>) {
+        let mut resolver = Resolver {
+            scopes: Vec::new(),
+            is_builtin,
+            errors: Vec::new(),
+        };
+        let mut scope = Scope::default();
+        Self::collect(&mut scope, statements);
+        resolver.scopes.push(scope);
+        resolver.block(statements);
+        let scope = resolver.scopes.pop().expect("the top-level scope");
+        (scope.names, resolver.errors)
+    }
+
+    // Finds the declarations in a scope, without going into the functions it declares
+    fn collect(scope: &mut Scope, statements: &[Stmt]) {
+        for statement in statements {
+            match &statement.kind {
+                StmtKind::Variable { name, .. } | StmtKind::Import { alias: name, .. } => {
+                    scope.add(*name)
+                }
+                StmtKind::Function { decl, .. } => scope.add(decl.name.unwrap_or_default()),
+                StmtKind::If {
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    Self::collect(scope, then_branch);
+                    if let Some(else_branch) = else_branch {
+                        Self::collect(scope, else_branch);
+                    }
+                }
+                StmtKind::While { body, .. } => Self::collect(scope, body),
+                StmtKind::For {
+                    init, update, body, ..
+                } => {
+                    for clause in init.iter().chain(update) {
+                        Self::collect(scope, std::slice::from_ref(clause));
+                    }
+                    Self::collect(scope, body);
+                }
+                StmtKind::Try {
+                    body,
+                    catch,
+                    finally,
+                } => {
+                    Self::collect(scope, body);
+                    if let Some(catch) = catch {
+                        Self::collect(scope, &catch.body);
+                    }
+                    if let Some(finally) = finally {
+                        Self::collect(scope, finally);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn current(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("a scope")
+    }
+
+    // The slot a declaration in the current scope uses, which from here on the name refers to.
+    // Declaring a catch clause's variable again in the clause replaces the error.
+    fn declare(&mut self, name: Symbol) -> usize {
+        let scope = self.current();
+        if let Some(slot) = scope.caught(name) {
+            return slot;
+        }
+        scope.declared.insert(name);
+        scope.slots[&name]
+    }
+
+    // Where a name used here is stored, if any scope declares it
+    fn binding(&self, name: Symbol) -> Option<Slot> {
+        let current = self.scopes.last().expect("a scope");
+        if let Some(index) = current.caught(name) {
+            return Some(Slot { depth: 0, index });
+        }
+        if current.declared.contains(&name) {
+            return Some(Slot {
+                depth: 0,
+                index: current.slots[&name],
+            });
+        }
+        for (depth, scope) in self.scopes.iter().rev().enumerate().skip(1) {
+            if let Some(index) = scope.caught(name).or(scope.slots.get(&name).copied()) {
+                return Some(Slot { depth, index });
+            }
+        }
+        // Declared further on in this scope, which a loop may already have run
+        current.slots.get(&name).map(|index| Slot {
+            depth: 0,
+            index: *index,
+        })
+    }
+
+    fn error(&mut self, message: String, span: Span) {
+        self.errors
+            .push(BugError::new(ErrorKind::Name, message, span));
+    }
+
+    fn block(&mut self, statements: &mut [Stmt]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &mut Stmt) {
+        let span = statement.span;
+        match &mut statement.kind {
+            StmtKind::Print(print) => {
+                for expr in print
+                    .values
+                    .iter_mut()
+                    .chain(&mut print.sep)
+                    .chain(&mut print.end)
+                {
+                    self.expression(expr);
+                }
+            }
+            StmtKind::Variable {
+                name, init, slot, ..
+            } => {
+                // The initializer still sees any variable the declaration shadows
+                self.expression(init);
+                *slot = self.declare(*name);
+            }
+            StmtKind::Update {
+                name, value, slot, ..
+            } => {
+                match self.binding(*name) {
+                    Some(binding) => *slot = binding,
+                    None => self.error(
+                        format!("Tried updating a non-existing variable: {}", name),
+                        span,
+                    ),
+                }
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.block(else_branch);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.expression(condition);
+                self.block(body);
+            }
+            StmtKind::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                if let Some(condition) = condition {
+                    self.expression(condition);
+                }
+                self.block(body);
+                if let Some(update) = update {
+                    self.statement(update);
+                }
+            }
+            StmtKind::Function { decl, slot } => {
+                *slot = self.declare(decl.name.unwrap_or_default());
+                self.function(decl);
+            }
+            StmtKind::Return(Some(expr)) | StmtKind::Throw(expr) | StmtKind::Expression(expr) => {
+                self.expression(expr)
+            }
+            StmtKind::Return(None) | StmtKind::Export(_) => {}
+            StmtKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.block(body);
+                if let Some(catch) = catch {
+                    catch.slot = self.current().catch(catch.name);
+                    self.block(&mut catch.body);
+                    self.current().caught.pop();
+                }
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+            }
+            StmtKind::Import { alias, slot, .. } => *slot = self.declare(*alias),
+        }
+    }
+
+    // A function body is a new scope, starting with its parameters
+    fn function(&mut self, decl: &mut Rc<FunctionDecl>) {
+        let decl = Rc::get_mut(decl).expect("functions are not shared until they are resolved");
+        let mut scope = Scope::default();
+        for param in &decl.params {
+            // A repeated parameter takes the later argument, as it is bound last
+            scope.slots.insert(param.name, scope.names.len());
+            scope.names.push(param.name);
+            scope.declared.insert(param.name);
+        }
+        Self::collect(&mut scope, &decl.body);
+        self.scopes.push(scope);
+        self.block(&mut decl.body);
+        self.scopes.pop();
+    }
+
+    fn expression(&mut self, expr: &mut Expr) {
+        let span = expr.span;
+        match &mut expr.kind {
+            ExprKind::Literal(_) => {}
+            ExprKind::Variable { name, slot } => {
+                *slot = self.binding(*name);
+                if slot.is_none() && !(self.is_builtin)(name.as_str()) {
+                    self.error(format!("Undefined variable '{}'.", name), span);
+                }
+            }
+            ExprKind::Unary { expr, .. } => self.expression(expr),
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExprKind::Call { callee, args } => {
+                self.expression(callee);
+                for arg in args {
+                    self.expression(arg);
+                }
+            }
+            ExprKind::List(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            }
+            ExprKind::Index { object, index } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            ExprKind::Get { object, .. } => self.expression(object),
+            ExprKind::Function(decl) => self.function(decl),
+        }
+    }
+}
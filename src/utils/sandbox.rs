@@ -0,0 +1,319 @@
+// What a script may do beyond computing: touch files, read the environment, start processes, read
+// the clock and print. Everything is denied by default and output is unlimited, so a policy only
+// lists what it allows.
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::tokeniser::Span;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// Which file builtins a script may use
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileAccess {
+    #[default]
+    Off,
+    #[serde(rename = "read")]
+    ReadOnly,
+    Full,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxPolicy {
+    pub files: FileAccess,
+    // When set, file builtins only reach paths inside this directory, and relative paths start here
+    pub file_root: Option<PathBuf>,
+    // Reading environment variables with `env()`
+    pub env: bool,
+    // Starting other programs with `run()`
+    pub process: bool,
+    // Reading the time with `time()`
+    pub clock: bool,
+    // The most bytes a script may print, counting stdout, stderr and input prompts together
+    pub max_output: Option<usize>,
+}
+
+// Something a policy has to allow before a script can do it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    ReadFiles,
+    WriteFiles,
+    Env,
+    Process,
+    Clock,
+}
+
+impl Capability {
+    // How error messages describe the capability
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::ReadFiles => "read access to files",
+            Capability::WriteFiles => "write access to files",
+            Capability::Env => "environment access",
+            Capability::Process => "permission to start processes",
+            Capability::Clock => "clock access",
+        }
+    }
+}
+
+impl SandboxPolicy {
+    // Reads a policy from a JSON file, or from TOML for any other extension
+    pub fn load(path: &Path) -> Result<SandboxPolicy, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            serde_json::from_str(&text).map_err(|error| error.to_string())
+        } else {
+            toml::from_str(&text).map_err(|error| error.to_string())
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::ReadFiles => self.files != FileAccess::Off,
+            Capability::WriteFiles => self.files == FileAccess::Full,
+            Capability::Env => self.env,
+            Capability::Process => self.process,
+            Capability::Clock => self.clock,
+        }
+    }
+
+    // Fails with a PermissionError naming the capability unless the policy allows it
+    pub fn require(
+        &self,
+        capability: Capability,
+        native: &str,
+        span: Span,
+    ) -> Result<(), BugError> {
+        self.require_for(capability, &format!("{}()", native), span)
+    }
+
+    // Like `require`, for something other than a builtin, described by `what`
+    fn require_for(&self, capability: Capability, what: &str, span: Span) -> Result<(), BugError> {
+        if self.allows(capability) {
+            return Ok(());
+        }
+        Err(BugError::new(
+            ErrorKind::Permission,
+            format!("{} needs {}, which is not allowed", what, capability.name()),
+            span,
+        ))
+    }
+
+    // Checks a path against `files` and `file_root`, and returns the path to use. Inside a file
+    // root, a path that is itself a link is refused, since a link that leads nowhere yet could
+    // lead outside once written through. `what` names the builtin or statement in errors.
+    pub fn allowed_path(
+        &self,
+        what: &str,
+        path: &str,
+        write: bool,
+        span: Span,
+    ) -> Result<PathBuf, BugError> {
+        let capability = if write {
+            Capability::WriteFiles
+        } else {
+            Capability::ReadFiles
+        };
+        self.require_for(capability, what, span)?;
+
+        let Some(root) = &self.file_root else {
+            return Ok(PathBuf::from(path));
+        };
+        let outside = || Self::outside(what, path, root, span);
+        let root = root.canonicalize().map_err(|_| outside())?;
+        let candidate = root.join(path);
+        // Files that don't exist yet are checked through their directory
+        let resolved = match candidate.canonicalize() {
+            Ok(resolved) => resolved,
+            Err(_) => match (candidate.parent(), candidate.file_name()) {
+                (Some(parent), Some(file)) => match parent.canonicalize() {
+                    Ok(parent) => parent.join(file),
+                    // An unresolvable path can only be trusted if it never steps upwards
+                    Err(_) if !candidate.components().any(|c| c == Component::ParentDir) => {
+                        candidate.clone()
+                    }
+                    Err(_) => return Err(outside()),
+                },
+                _ => return Err(outside()),
+            },
+        };
+        let link = fs::symlink_metadata(&resolved).is_ok_and(|metadata| metadata.is_symlink());
+        if resolved.starts_with(&root) && !link {
+            Ok(resolved)
+        } else {
+            Err(outside())
+        }
+    }
+
+    // Checks a file that has been opened is the one inside the file root that `path` names. A link
+    // swapped in between `allowed_path` and the open would give a handle to another file, which is
+    // caught by comparing the handle's device and inode with those of the checked path. Without
+    // those (off Unix), only the path is checked again, which a swap racing this check could pass.
+    pub fn check_opened(
+        &self,
+        what: &str,
+        path: &Path,
+        file: &fs::File,
+        span: Span,
+    ) -> Result<(), BugError> {
+        let Some(root) = &self.file_root else {
+            return Ok(());
+        };
+        let outside = || Self::outside(what, &path.display().to_string(), root, span);
+        let (Ok(canonical), Ok(checked)) = (root.canonicalize(), path.canonicalize()) else {
+            return Err(outside());
+        };
+        if !checked.starts_with(&canonical) {
+            return Err(outside());
+        }
+        if Self::same_file(file, &checked) {
+            Ok(())
+        } else {
+            Err(outside())
+        }
+    }
+
+    #[cfg(unix)]
+    fn same_file(file: &fs::File, path: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        match (file.metadata(), fs::metadata(path)) {
+            (Ok(opened), Ok(checked)) => {
+                opened.dev() == checked.dev() && opened.ino() == checked.ino()
+            }
+            _ => false,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn same_file(_file: &fs::File, _path: &Path) -> bool {
+        true
+    }
+
+    fn outside(what: &str, path: &str, root: &Path, span: Span) -> BugError {
+        BugError::new(
+            ErrorKind::Permission,
+            format!(
+                "{} cannot access '{}' outside {}",
+                what,
+                path,
+                root.display()
+            ),
+            span,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    // A fresh file root holding `inside.txt`, in a directory that also holds `outside.txt`
+    fn workspace(name: &str) -> PathBuf {
+        let workspace =
+            env::temp_dir().join(format!("buglang-policy-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&workspace);
+        fs::create_dir_all(workspace.join("root").join("sub")).unwrap();
+        fs::write(workspace.join("root").join("inside.txt"), "").unwrap();
+        fs::write(workspace.join("outside.txt"), "").unwrap();
+        workspace
+    }
+
+    fn policy(files: FileAccess, root: Option<PathBuf>) -> SandboxPolicy {
+        SandboxPolicy {
+            files,
+            file_root: root,
+            ..SandboxPolicy::default()
+        }
+    }
+
+    fn allowed(policy: &SandboxPolicy, path: &str, write: bool) -> Result<PathBuf, BugError> {
+        policy.allowed_path("read_file()", path, write, Span::default())
+    }
+
+    #[test]
+    fn file_access_must_be_allowed() {
+        let error = allowed(&policy(FileAccess::Off, None), "a.txt", false).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Permission);
+        let read_only = policy(FileAccess::ReadOnly, None);
+        assert!(allowed(&read_only, "a.txt", true).is_err());
+        assert_eq!(
+            allowed(&read_only, "a.txt", false).unwrap(),
+            PathBuf::from("a.txt")
+        );
+    }
+
+    #[test]
+    fn paths_stay_inside_the_file_root() {
+        let workspace = workspace("root");
+        let root = workspace.join("root");
+        let policy = policy(FileAccess::Full, Some(root.clone()));
+        let canonical = root.canonicalize().unwrap();
+        assert_eq!(
+            allowed(&policy, "inside.txt", false).unwrap(),
+            canonical.join("inside.txt")
+        );
+        assert_eq!(
+            allowed(&policy, "sub/../inside.txt", false).unwrap(),
+            canonical.join("inside.txt")
+        );
+        // Files that do not exist yet are checked through their directory
+        assert_eq!(
+            allowed(&policy, "sub/new.txt", true).unwrap(),
+            canonical.join("sub").join("new.txt")
+        );
+        for path in [
+            "../outside.txt",
+            "sub/../../outside.txt",
+            "missing/../../x.txt",
+        ] {
+            let error = allowed(&policy, path, false).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::Permission, "{}", path);
+        }
+        let absolute = workspace.join("outside.txt");
+        assert!(allowed(&policy, &absolute.to_string_lossy(), false).is_err());
+        fs::remove_dir_all(&workspace).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_inside_the_file_root_are_refused() {
+        use std::os::unix::fs::symlink;
+
+        let workspace = workspace("links");
+        let root = workspace.join("root");
+        symlink(workspace.join("outside.txt"), root.join("out.txt")).unwrap();
+        symlink(root.join("inside.txt"), root.join("in.txt")).unwrap();
+        symlink(workspace.join("missing.txt"), root.join("dangling.txt")).unwrap();
+        let policy = policy(FileAccess::Full, Some(root.clone()));
+        assert!(allowed(&policy, "out.txt", false).is_err());
+        assert!(allowed(&policy, "dangling.txt", true).is_err());
+        // A link that stays inside resolves to its target
+        assert_eq!(
+            allowed(&policy, "in.txt", false).unwrap(),
+            root.canonicalize().unwrap().join("inside.txt")
+        );
+        fs::remove_dir_all(&workspace).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn an_opened_file_must_be_the_one_checked() {
+        let workspace = workspace("opened");
+        let root = workspace.join("root");
+        let policy = policy(FileAccess::Full, Some(root.clone()));
+        let path = allowed(&policy, "inside.txt", false).unwrap();
+        let check =
+            |file: &fs::File| policy.check_opened("read_file()", &path, file, Span::default());
+        assert!(check(&fs::File::open(&path).unwrap()).is_ok());
+        // As if a link to another file had been swapped in before the open
+        let other = fs::File::open(workspace.join("outside.txt")).unwrap();
+        assert_eq!(check(&other).unwrap_err().kind, ErrorKind::Permission);
+        fs::remove_dir_all(&workspace).unwrap();
+    }
+}
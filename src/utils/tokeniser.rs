@@ -1,7 +1,10 @@
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::symbols;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
-    Keyword(String),
-    Identifier(String),
+    Keyword(symbols::Symbol),
+    Identifier(symbols::Symbol),
     Number(i64),
     Symbol(char),
     StringLiteral(String),
@@ -10,45 +13,63 @@ pub enum Token {
     Boolean(bool),
 }
 
+// Where a token starts in the source, counted from 1
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
 pub struct Tokenizer;
 
 impl Tokenizer {
-    pub fn tokenize(input: &str) -> Vec<Token> {
+    pub fn tokenize(input: &str) -> Result<Vec<(Token, Span)>, BugError> {
         let mut tokens = Vec::new();
-        let mut chars = input.chars().peekable();
+        let mut spans = Vec::new();
+        let mut chars = input.char_indices().peekable();
+        let mut line = 1;
+        let mut line_start = 0;
 
-        while let Some(&c) = chars.peek() {
+        while let Some(&(offset, c)) = chars.peek() {
+            let span = Span {
+                line,
+                column: input[line_start..offset].chars().count() + 1,
+            };
             match c {
                 // Identifiers and keywords
                 'a'..='z' | 'A'..='Z' => {
-                    let mut identifier = String::new();
-                    while let Some(&ch) = chars.peek() {
+                    let mut end = offset;
+                    while let Some(&(position, ch)) = chars.peek() {
                         if ch.is_alphanumeric() || ch == '_' {
-                            identifier.push(ch);
+                            end = position + ch.len_utf8();
                             chars.next();
                         } else {
                             break;
                         }
                     }
-                    if ["if", "else", "while", "and", "or", "not", "for"]
-                        .contains(&identifier.as_str())
-                    {
-                        tokens.push(Token::Identifier(identifier));
-                    } else if ["print", "variable", "while", "update"]
-                        .contains(&identifier.as_str())
+                    // Names are interned straight from the source, without a copy of their own
+                    let identifier = &input[offset..end];
+                    if ["if", "else", "while", "and", "or", "not", "for"].contains(&identifier) {
+                        tokens.push(Token::Identifier(symbols::Symbol::intern(identifier)));
+                    } else if [
+                        "print", "variable", "while", "update", "function", "fn", "return",
+                        "throw", "try", "catch", "finally", "nil", "import", "as", "export",
+                        "typeof", "const",
+                    ]
+                    .contains(&identifier)
                     {
-                        tokens.push(Token::Keyword(identifier));
-                    } else if ["true", "false"].contains(&identifier.as_str()) {
+                        tokens.push(Token::Keyword(symbols::Symbol::intern(identifier)));
+                    } else if ["true", "false"].contains(&identifier) {
                         tokens.push(Token::Boolean(identifier.parse().unwrap()));
                     } else {
-                        tokens.push(Token::Identifier(identifier));
+                        tokens.push(Token::Identifier(symbols::Symbol::intern(identifier)));
                     }
                 }
                 '0'..='9' => {
                     let mut number = String::new();
                     // Consume the integer part.
-                    while let Some(&ch) = chars.peek() {
-                        if ch.is_digit(10) {
+                    while let Some(&(_, ch)) = chars.peek() {
+                        if ch.is_ascii_digit() {
                             number.push(ch);
                             chars.next();
                         } else {
@@ -56,17 +77,17 @@ impl Tokenizer {
                         }
                     }
                     // Check for a fractional part.
-                    if let Some(&'.') = chars.peek() {
+                    if let Some(&(_, '.')) = chars.peek() {
                         // Peek ahead to see if there is a digit after the dot.
                         let mut clone = chars.clone();
                         clone.next(); // consume the dot in the clone
-                        if let Some(&next_digit) = clone.peek() {
-                            if next_digit.is_digit(10) {
+                        if let Some(&(_, next_digit)) = clone.peek() {
+                            if next_digit.is_ascii_digit() {
                                 // It's a float literal.
                                 number.push('.');
                                 chars.next(); // consume the dot
-                                while let Some(&ch) = chars.peek() {
-                                    if ch.is_digit(10) {
+                                while let Some(&(_, ch)) = chars.peek() {
+                                    if ch.is_ascii_digit() {
                                         number.push(ch);
                                         chars.next();
                                     } else {
@@ -74,19 +95,33 @@ impl Tokenizer {
                                     }
                                 }
                                 tokens.push(Token::Float(number.parse().unwrap()));
+                                spans.push(span);
                                 continue;
                             }
                         }
                     }
-                    tokens.push(Token::Number(number.parse().unwrap()));
+                    match number.parse() {
+                        Ok(n) => tokens.push(Token::Number(n)),
+                        Err(_) => {
+                            return Err(BugError::new(
+                                ErrorKind::Syntax,
+                                format!("Integer literal too large: {}", number),
+                                span,
+                            ))
+                        }
+                    }
                 }
                 '"' => {
                     chars.next(); // Consume opening quote
                     let mut string_literal = String::new();
-                    while let Some(&ch) = chars.peek() {
+                    while let Some(&(position, ch)) = chars.peek() {
                         if ch == '"' {
                             break;
                         }
+                        if ch == '\n' {
+                            line += 1;
+                            line_start = position + 1;
+                        }
                         string_literal.push(ch);
                         chars.next();
                     }
@@ -97,61 +132,84 @@ impl Tokenizer {
                     let mut operator = String::new();
                     operator.push(c);
                     chars.next();
-                    if let Some(&next_char) = chars.peek() {
-                        if next_char == '=' {
+                    if let Some(&(_, next_char)) = chars.peek() {
+                        // `<<` and `>>` are shifts
+                        if next_char == '=' || (next_char == c && (c == '<' || c == '>')) {
                             operator.push(next_char);
                             chars.next();
                         }
                     }
                     tokens.push(Token::Operator(operator));
                 }
-                ':' | '+' | '-' | '*' | '/' | '{' | '}' | '(' | ')' | ';' => {
+                '*' if matches!(chars.clone().nth(1), Some((_, '*'))) => {
+                    tokens.push(Token::Operator("**".to_string()));
+                    chars.next();
+                    chars.next();
+                }
+                '/' if matches!(chars.clone().nth(1), Some((_, '/'))) => {
+                    tokens.push(Token::Operator("//".to_string()));
+                    chars.next();
+                    chars.next();
+                }
+                '-' if matches!(chars.clone().nth(1), Some((_, '>'))) => {
+                    tokens.push(Token::Operator("->".to_string()));
+                    chars.next();
+                    chars.next();
+                }
+                '?' => {
+                    chars.next();
+                    match chars.peek() {
+                        Some(&(_, next_char)) if next_char == '.' || next_char == '?' => {
+                            tokens.push(Token::Operator(format!("?{}", next_char)));
+                            chars.next();
+                        }
+                        _ => {
+                            return Err(BugError::new(
+                                ErrorKind::Syntax,
+                                "Expected '?.' or '??'",
+                                span,
+                            ))
+                        }
+                    }
+                }
+                ':' | '+' | '-' | '*' | '%' | '&' | '|' | '^' | '~' | '{' | '}' | '(' | ')'
+                | '[' | ']' | ';' | ',' | '.' => {
                     tokens.push(Token::Symbol(c));
                     chars.next();
                 }
                 '/' => {
-                    chars.next(); // Consume '/'
-                    if let Some(&next_char) = chars.peek() {
-                        if next_char == '/' {
-                            // Single-line comment
-                            while let Some(&ch) = chars.peek() {
-                                if ch == '\n' {
-                                    break;
-                                }
-                                chars.next();
-                            }
-                        } else {
-                            tokens.push(Token::Symbol('/'));
+                    tokens.push(Token::Symbol('/'));
+                    chars.next();
+                }
+                // A comment runs to the end of the line
+                '#' => {
+                    while let Some(&(_, ch)) = chars.peek() {
+                        if ch == '\n' {
+                            break;
                         }
+                        chars.next();
                     }
                 }
                 _ if c.is_whitespace() => {
+                    if c == '\n' {
+                        line += 1;
+                        line_start = offset + 1;
+                    }
                     chars.next();
                 }
 
                 _ => {
-                    panic!("Unexpected character: {}", c);
+                    return Err(BugError::new(
+                        ErrorKind::Syntax,
+                        format!("Unexpected character: {}", c),
+                        span,
+                    ));
                 }
             }
+            // Every token pushed this iteration starts where it began
+            spans.resize(tokens.len(), span);
         }
 
-        tokens
-    }
-
-    pub fn reconstruct(tokens: &[Token]) -> String {
-        tokens
-            .iter()
-            .map(|token| match token {
-                Token::Keyword(kw) => kw.clone(),
-                Token::Identifier(id) => id.clone(),
-                Token::Number(num) => num.to_string(),
-                Token::Symbol(sym) => sym.to_string(),
-                Token::StringLiteral(lit) => format!("\"{}\"", lit),
-                Token::Operator(op) => op.clone(),
-                Token::Float(f) => f.to_string(),
-                Token::Boolean(b) => b.to_string(),
-            })
-            .collect::<Vec<_>>()
-            .join(" ")
+        Ok(tokens.into_iter().zip(spans).collect())
     }
 }
@@ -0,0 +1,121 @@
+use crate::utils::variables::Value;
+use std::fmt;
+
+// The types a value can have, as named in annotations and by `typeof`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    String,
+    Bool,
+    Nil,
+    List,
+    Function,
+    Error,
+    Module,
+    // Not known until the program runs
+    Any,
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "int" => Type::Int,
+            "float" => Type::Float,
+            "string" => Type::String,
+            "bool" | "boolean" => Type::Bool,
+            "nil" => Type::Nil,
+            "list" => Type::List,
+            "function" => Type::Function,
+            "error" => Type::Error,
+            "module" => Type::Module,
+            "any" => Type::Any,
+            _ => return None,
+        })
+    }
+
+    pub fn of(value: &Value) -> Type {
+        match value {
+            Value::Integer(_) | Value::BigInt(_) => Type::Int,
+            Value::Float(_) => Type::Float,
+            Value::String(_) => Type::String,
+            Value::Boolean(_) => Type::Bool,
+            Value::Nil => Type::Nil,
+            Value::List(_) => Type::List,
+            Value::Function(_) | Value::Closure(_) | Value::Native(_) => Type::Function,
+            Value::Error(_) => Type::Error,
+            Value::Module(_) => Type::Module,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::String => "string",
+            Type::Bool => "bool",
+            Type::Nil => "nil",
+            Type::List => "list",
+            Type::Function => "function",
+            Type::Error => "error",
+            Type::Module => "module",
+            Type::Any => "any",
+        }
+    }
+
+    // Whether a value of type `other` may be stored where `self` is declared. Integers widen to
+    // floats, and nil is accepted anywhere.
+    pub fn accepts(self, other: Type) -> bool {
+        self == other
+            || self == Type::Any
+            || other == Type::Any
+            || other == Type::Nil
+            || (self == Type::Float && other == Type::Int)
+    }
+
+    // A type covering both, for a value that may be either
+    pub fn join(self, other: Type) -> Type {
+        match (self, other) {
+            _ if self == other => self,
+            (Type::Nil, other) | (other, Type::Nil) => other,
+            _ => Type::Any,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_its_own_type_any_and_nil() {
+        assert!(Type::Int.accepts(Type::Int));
+        assert!(Type::Int.accepts(Type::Nil));
+        assert!(Type::Int.accepts(Type::Any));
+        assert!(Type::Any.accepts(Type::List));
+        assert!(!Type::Int.accepts(Type::String));
+        assert!(!Type::Nil.accepts(Type::Int));
+    }
+
+    #[test]
+    fn integers_widen_to_floats_only() {
+        assert!(Type::Float.accepts(Type::Int));
+        assert!(!Type::Int.accepts(Type::Float));
+    }
+
+    #[test]
+    fn join_keeps_a_type_shared_or_beside_nil() {
+        assert_eq!(Type::Int.join(Type::Int), Type::Int);
+        assert_eq!(Type::Nil.join(Type::String), Type::String);
+        assert_eq!(Type::Error.join(Type::Nil), Type::Error);
+        assert_eq!(Type::Nil.join(Type::Nil), Type::Nil);
+        assert_eq!(Type::Int.join(Type::Float), Type::Any);
+        assert_eq!(Type::Any.join(Type::Int), Type::Any);
+    }
+}
@@ -1,79 +1,318 @@
-#[derive(Debug,Clone)]
+use crate::stdlib::Native;
+use crate::utils::ast::{FunctionDecl, Slot};
+use crate::utils::errors::BugError;
+use crate::utils::integers;
+use crate::utils::symbols::Symbol;
+use crate::utils::types::Type;
+use crate::vm::Closure;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone)]
 pub struct Variable {
-    pub Value: Value,
+    pub value: Value,
+    // Declared with `const`, so it can never be assigned again
+    pub constant: bool,
+    // The type it was declared with, which every later value must have
+    pub ty: Option<Type>,
 }
-#[derive(Debug,Clone)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Integer(i64),
+    // Only made under `Overflow::Big`, for integers too large for an i64
+    BigInt(Rc<BigInt>),
     Float(f64),
-    String(String),
+    String(Rc<str>),
     Boolean(bool),
+    Nil,
+    List(Rc<Vec<Value>>),
+    Function(Rc<Function>),
+    // A function compiled for the bytecode VM
+    Closure(Rc<Closure>),
+    Native(Native),
+    Error(Rc<BugError>),
+    Module(Rc<Module>),
+}
+
+// A function value: its declaration plus the scopes it closed over
+pub struct Function {
+    pub decl: Rc<FunctionDecl>,
+    pub closure: Vec<Scope>,
+}
+
+// An imported file: the names it exports, evaluated once and shared by every importer
+#[derive(Debug)]
+pub struct Module {
+    pub name: String,
+    pub exports: HashMap<Symbol, Value>,
+}
+
+impl fmt::Debug for Function {
+    // The closure may contain this function again, so only the name is printed
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Function({:?})", self.decl.name)
+    }
+}
+
+impl Value {
+    // The name `typeof` reports for this value
+    pub fn type_name(&self) -> &'static str {
+        Type::of(self).name()
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::BigInt(n) => write!(f, "{}", n),
+            Value::Float(fl) => write!(f, "{}", fl),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Nil => write!(f, "nil"),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    // Quote strings so list elements stay distinguishable
+                    match item {
+                        Value::String(s) => write!(f, "{:?}", s)?,
+                        other => write!(f, "{}", other)?,
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Function(func) => match &func.decl.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
+            },
+            Value::Closure(closure) => match &closure.prototype.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
+            },
+            Value::Native(native) => write!(f, "<native fn {}>", native.name),
+            Value::Error(error) => write!(f, "{}: {}", error.kind.name(), error.message),
+            Value::Module(module) => write!(f, "<module {}>", module.name),
+        }
+    }
 }
-use std::collections::HashMap;
+
+// Why `VarManager::assign` did not store a value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AssignError {
+    Undefined,
+    Constant,
+    // The variable holds a value of this type, which the new value cannot be stored as
+    Type(&'static str),
+}
+
+// A scope's variables by slot, as assigned by the resolver; None until a slot's declaration runs.
+// Scopes are reference counted so closures can keep them alive and see later updates.
+pub type Scope = Rc<RefCell<Vec<Option<Variable>>>>;
 
 pub struct VarManager {
-    scopes: Vec<HashMap<String, Variable>>,
+    scopes: Vec<Scope>,
 }
 
 impl VarManager {
     pub fn new() -> Self {
         Self {
-            scopes: vec![HashMap::new()], // Start with a global scope
+            scopes: vec![Scope::default()], // Start with a global scope
         }
     }
 
-    // Add a variable to the current scope
-    pub fn define(&mut self, name: String, value: Variable) {
-        if let Some(current_scope) = self.scopes.last_mut() {
-            current_scope.insert(name, value);
+    fn scope(&self, depth: usize) -> &Scope {
+        &self.scopes[self.scopes.len() - 1 - depth]
+    }
+
+    // Add a variable to a slot of the current scope
+    pub fn define(&mut self, slot: usize, value: Variable) {
+        if let Some(current_scope) = self.scopes.last() {
+            let mut current_scope = current_scope.borrow_mut();
+            if current_scope.len() <= slot {
+                current_scope.resize(slot + 1, None);
+            }
+            current_scope[slot] = Some(value);
         }
     }
 
-    // Retrieve a variable, searching from the innermost scope outward
-    pub fn get(&self, name: &str) -> Option<&Variable> {
-        for scope in self.scopes.iter().rev() {
-            if let Some(var) = scope.get(name) {
-                return Some(var);
+    // Retrieve a variable, if its declaration has run
+    pub fn get(&self, slot: Slot) -> Option<Variable> {
+        self.scope(slot.depth)
+            .borrow()
+            .get(slot.index)
+            .cloned()
+            .flatten()
+    }
+
+    // Declares a variable in the current scope, or assigns to it if it is already declared without
+    // a type. Constants can be shadowed by an inner scope, but not declared again in their own.
+    pub fn declare(
+        &mut self,
+        slot: usize,
+        value: Value,
+        constant: bool,
+        ty: Option<Type>,
+    ) -> Result<(), AssignError> {
+        let local = Slot {
+            depth: 0,
+            index: slot,
+        };
+        match self.get(local) {
+            Some(variable) if variable.constant => Err(AssignError::Constant),
+            Some(_) if !constant && ty.is_none() => self.assign(local, value),
+            _ => {
+                self.define(
+                    slot,
+                    Variable {
+                        value,
+                        constant,
+                        ty,
+                    },
+                );
+                Ok(())
             }
         }
-        None
-    }
-
-    // Update a variable in the nearest scope where it is defined
-    pub fn assign(&mut self, name: &str, new_value: &str) -> Result<(), String> {
-        // Iterate over scopes from innermost to outermost
-        for scope in self.scopes.iter_mut().rev() {
-            if let Some(variable) = scope.get_mut(name) {
-                match &variable.Value {
-                    Value::Integer(_i) => {
-                        if let Ok(int_val) = new_value.parse::<i64>() {
-                            variable.Value = Value::Integer(int_val);
-                        }
-                    }
-                    Value::Float(_f) => {
-                        if let Ok(float_val) = new_value.parse::<f64>() {
-                            variable.Value = Value::Float(float_val);
-                        }
-                    }
-                    Value::Boolean(_b) => {
-                        if let Ok(bool_val) = new_value.parse::<bool>(){
-                            variable.Value = Value::Boolean(bool_val)
-                        }
-                    }
-                    Value::String(_s) => {
-                        // Directly assign the new string value.
-                        variable.Value = Value::String(new_value.to_string());
-                    }
+    }
+
+    // The type a variable was declared with, if it has been declared with one
+    pub fn annotation(&self, slot: Slot) -> Option<Type> {
+        match self.scope(slot.depth).borrow().get(slot.index) {
+            Some(Some(variable)) => variable.ty,
+            _ => None,
+        }
+    }
+
+    // Update a variable whose declaration has run. A value for a variable declared with a type
+    // must already have been checked against it, and is stored as it is. Any other variable keeps
+    // the type of its value, and a value that cannot be stored as that type is refused.
+    pub fn assign(&mut self, slot: Slot, new_value: Value) -> Result<(), AssignError> {
+        let mut scope = self.scope(slot.depth).borrow_mut();
+        if let Some(Some(variable)) = scope.get_mut(slot.index) {
+            if variable.constant {
+                return Err(AssignError::Constant);
+            }
+            if variable.ty.is_some() {
+                variable.value = new_value;
+                return Ok(());
+            }
+            match (&variable.value, new_value) {
+                (
+                    Value::Integer(_) | Value::BigInt(_),
+                    value @ (Value::Integer(_) | Value::BigInt(_)),
+                ) => variable.value = value,
+                (Value::Float(_), Value::Float(f)) => variable.value = Value::Float(f),
+                (Value::Float(_), Value::Integer(i)) => variable.value = Value::Float(i as f64),
+                // Mixed arithmetic promotes integers to floats
+                (Value::Integer(_) | Value::BigInt(_), Value::Float(f)) => {
+                    variable.value = Value::Float(f)
+                }
+                (Value::Float(_), Value::BigInt(n)) => {
+                    variable.value = Value::Float(n.to_f64().unwrap_or(f64::NAN))
+                }
+                (Value::Boolean(_), Value::Boolean(b)) => variable.value = Value::Boolean(b),
+                // Strings are parsed as the variable's existing type
+                (Value::BigInt(_), Value::String(s)) => match s.parse::<BigInt>() {
+                    Ok(n) => variable.value = integers::normalize(n),
+                    Err(_) => return Err(AssignError::Type("int")),
+                },
+                (Value::Integer(_), Value::String(s)) => match s.parse::<i64>() {
+                    Ok(int_val) => variable.value = Value::Integer(int_val),
+                    Err(_) => return Err(AssignError::Type("int")),
+                },
+                (Value::Float(_), Value::String(s)) => match s.parse::<f64>() {
+                    Ok(float_val) => variable.value = Value::Float(float_val),
+                    Err(_) => return Err(AssignError::Type("float")),
+                },
+                (Value::Boolean(_), Value::String(s)) => match s.to_lowercase().as_str() {
+                    "true" => variable.value = Value::Boolean(true),
+                    "false" => variable.value = Value::Boolean(false),
+                    _ => return Err(AssignError::Type("bool")),
+                },
+                // Strings take the printed form of whatever is assigned
+                (Value::String(_), other) => {
+                    variable.value = Value::String(other.to_string().into())
                 }
-                return Ok(()); // Successfully updated
+                (
+                    Value::Nil
+                    | Value::List(_)
+                    | Value::Function(_)
+                    | Value::Closure(_)
+                    | Value::Native(_)
+                    | Value::Error(_)
+                    | Value::Module(_),
+                    other,
+                ) => variable.value = other,
+                (current, _) => return Err(AssignError::Type(current.type_name())),
             }
+            return Ok(()); // Successfully updated
         }
         // If the variable is not found, return an error
-        Err(format!("Undefined variable `{}`", name))
+        Err(AssignError::Undefined)
     }
 
+    // A snapshot of the variables in the innermost scope, by slot
+    pub fn locals(&self) -> Vec<Option<Variable>> {
+        self.scopes
+            .last()
+            .map(|scope| scope.borrow().clone())
+            .unwrap_or_default()
+    }
+
+    // The current scope chain, shared with a closure created at this point
+    pub fn capture(&self) -> Vec<Scope> {
+        self.scopes.clone()
+    }
+
+    // Switch to a function's closure with a fresh scope on top, returning the caller's scopes
+    pub fn enter(&mut self, closure: &[Scope]) -> Vec<Scope> {
+        let mut scopes = closure.to_vec();
+        scopes.push(Scope::default());
+        std::mem::replace(&mut self.scopes, scopes)
+    }
+
+    // Goes back to the caller's scopes once a function or module has finished
+    pub fn restore(&mut self, scopes: Vec<Scope>) {
+        let finished = std::mem::replace(&mut self.scopes, scopes);
+        if let Some(frame) = finished.last() {
+            Self::release(frame);
+        }
+    }
+
+    // A function declared in a scope captures that scope, so the two keep each other alive after
+    // the call has returned. When nothing else holds either of them, the scope's variables are
+    // dropped to break the cycle.
+    fn release(frame: &Scope) {
+        let captured_by_own = frame
+            .borrow()
+            .iter()
+            .flatten()
+            .filter(|variable| Self::held_only_by(&variable.value, frame))
+            .count();
+        // The finished scope chain holds one reference itself
+        if Rc::strong_count(frame) == 1 + captured_by_own {
+            let variables = std::mem::take(&mut *frame.borrow_mut());
+            drop(variables);
+        }
+    }
+
+    // Whether a value is a function that captured this scope and is kept nowhere but in it
+    fn held_only_by(value: &Value, frame: &Scope) -> bool {
+        let (count, scopes) = match value {
+            Value::Function(function) => (Rc::strong_count(function), &function.closure),
+            Value::Closure(closure) => (Rc::strong_count(closure), &closure.scopes),
+            _ => return false,
+        };
+        count == 1 && scopes.last().is_some_and(|scope| Rc::ptr_eq(scope, frame))
+    }
 
-    pub fn parse_value(&mut self, input: &str) -> Option<Value> {
+    pub fn parse_value(input: &str) -> Option<Value> {
         // Try parsing as an integer
         if let Ok(int_val) = input.parse::<i64>() {
             return Some(Value::Integer(int_val));
@@ -84,12 +323,14 @@ impl VarManager {
             return Some(Value::Float(float_val));
         }
 
-        // Try parsing as a boolean
-        if let "true" | "false" = input.to_lowercase().as_str() {
-            return Some(Value::Boolean(input.parse().unwrap()));
+        // Try parsing as a boolean, in any case
+        match input.to_lowercase().as_str() {
+            "true" => return Some(Value::Boolean(true)),
+            "false" => return Some(Value::Boolean(false)),
+            _ => {}
         }
 
         // If it doesn't match any type, return it as a String
-        Some(Value::String(input.to_string()))
+        Some(Value::String(input.into()))
     }
 }
@@ -0,0 +1,154 @@
+use crate::utils::ast::{BinaryOp, Param, PrintTarget, UnaryOp, UpdateOp};
+use crate::utils::symbols::Symbol;
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::{Scope, Value};
+use std::fmt;
+use std::rc::Rc;
+
+// One instruction. Operands index the chunk's constants, names or functions, are the position of
+// another instruction in the same chunk, or locate a variable as the resolver does: `slot` in the
+// scope `depth` functions out. Variable instructions also keep the name, for errors and for
+// finding a builtin of that name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Constant(u32),
+    Nil,
+    True,
+    False,
+    Pop,
+    // Pushes a variable, or the builtin of that name if the variable is not declared yet
+    GetVar {
+        name: u32,
+        depth: u32,
+        slot: u32,
+    },
+    // Pushes a builtin that no variable shadows
+    GetBuiltin(u32),
+    // Pops a value into a new variable, or into one already declared in the current scope. A
+    // variable declared with a type keeps it, so later updates are checked against it.
+    Declare {
+        name: u32,
+        slot: u32,
+        ty: Option<Type>,
+    },
+    DeclareConst {
+        name: u32,
+        slot: u32,
+    },
+    // Pops a caught error into a variable in the current scope, replacing one already there unless
+    // it is a constant or declared with a type
+    Define {
+        name: u32,
+        slot: u32,
+    },
+    // Pushes the current value of a variable about to be updated, which must exist
+    LoadUpdate {
+        name: u32,
+        depth: u32,
+        slot: u32,
+    },
+    // Pops a value into an existing variable
+    Assign {
+        name: u32,
+        depth: u32,
+        slot: u32,
+    },
+    // Replaces the current value below it with the result of the update, popping the amount of
+    // `+=` and `-=` first
+    Update(UpdateOp),
+    // Checks the top value against a declared type, naming the variable in any error
+    Conform(Type, u32),
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+    // Replaces the top value with the boolean it gives as a condition
+    Test,
+    Jump(u32),
+    // Pops a condition and jumps if it is false
+    JumpIfFalse(u32),
+    // For `and` and `or`: jumps past the right side if the boolean on top already decides the
+    // result, keeping it, or pops it otherwise
+    JumpIfFalseOrPop(u32),
+    JumpIfTrueOrPop(u32),
+    // For `??`: jumps past the fallback, keeping the top value, unless it is nil
+    JumpIfNotNil(u32),
+    // Calls the function below the given number of arguments
+    Call(u32),
+    Return,
+    // Creates a function from one of the chunk's prototypes, closing over the current scopes
+    Closure(u32),
+    List(u32),
+    Index,
+    Get(u32),
+    // `?.`, which gives nil for a nil object
+    GetOptional(u32),
+    // Checks that the value given for print's `sep` or `end` is a string
+    PrintOption(u32),
+    // Pops the values to print, then `end` and `sep` if they were given
+    Print {
+        values: u32,
+        sep: bool,
+        end: bool,
+        newline: bool,
+        target: PrintTarget,
+    },
+    Throw,
+    // Starts a `try` block whose errors jump to the given position, with the error pushed
+    PushHandler(u32),
+    PopHandler,
+    // Raises the caught error on top of the stack again, once `finally` has run
+    Rethrow,
+    // Pushes the module at a path, running it first if it has not been imported yet
+    Import(u32),
+    // The end of a script
+    Halt,
+}
+
+// Compiled code, with the source position of every instruction for error messages
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+    // Variable, property and module names used by the instructions
+    pub names: Vec<Symbol>,
+    pub functions: Vec<Rc<Prototype>>,
+}
+
+impl Chunk {
+    pub fn name(&self, index: u32) -> Symbol {
+        self.names[index as usize]
+    }
+}
+
+// A compiled function, before it is closed over any scopes
+#[derive(Debug)]
+pub struct Prototype {
+    pub name: Option<Symbol>,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+    pub chunk: Chunk,
+}
+
+// A compiled script or module
+#[derive(Debug)]
+pub struct Program {
+    pub main: Rc<Prototype>,
+    // The names of its top-level variables, by slot
+    pub globals: Vec<Symbol>,
+    // Every name listed by `export`; empty when the script exports everything
+    pub exports: Vec<Symbol>,
+}
+
+// A function value made by the VM: its prototype plus the scopes it closed over
+pub struct Closure {
+    pub prototype: Rc<Prototype>,
+    pub scopes: Vec<Scope>,
+}
+
+impl fmt::Debug for Closure {
+    // The scopes may contain this closure again, so only the name is printed
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Closure({:?})", self.prototype.name)
+    }
+}
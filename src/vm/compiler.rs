@@ -0,0 +1,489 @@
+// Turns the syntax tree into bytecode. The compiled code keeps the tree-walker's behaviour,
+// including its error messages and positions, so either engine can run any script.
+use super::chunk::{Chunk, Op, Program, Prototype};
+use crate::utils::ast::*;
+use crate::utils::symbols::Symbol;
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::{Value, VarManager};
+use std::rc::Rc;
+
+pub struct Compiler<'a> {
+    chunk: Chunk,
+    // The `finally` blocks of the `try` statements around the code being compiled, innermost
+    // last. Each of them has a handler installed.
+    tries: Vec<Option<&'a [Stmt]>>,
+    // The top-level statement being compiled, when compiling a script. A `return` outside a
+    // function is reported there, as the tree-walker does.
+    statement: Option<Span>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new() -> Self {
+        Self {
+            chunk: Chunk::default(),
+            tries: Vec::new(),
+            statement: None,
+        }
+    }
+
+    pub fn compile(script: &'a Script) -> Program {
+        let statements = &script.statements;
+        let mut compiler = Compiler::new();
+        for statement in statements {
+            compiler.statement = Some(statement.span);
+            compiler.statement(statement);
+        }
+        let end = statements.last().map_or(Span::default(), |last| last.span);
+        compiler.emit(Op::Halt, end);
+        let exports = statements
+            .iter()
+            .filter_map(|statement| match &statement.kind {
+                StmtKind::Export(names) => Some(names),
+                _ => None,
+            })
+            .flatten()
+            .copied()
+            .collect();
+        Program {
+            main: Rc::new(Prototype {
+                name: None,
+                params: Vec::new(),
+                return_type: None,
+                chunk: compiler.chunk,
+            }),
+            globals: script.globals.clone(),
+            exports,
+        }
+    }
+
+    fn function(decl: &FunctionDecl, span: Span) -> Prototype {
+        let mut compiler = Compiler::new();
+        compiler.block(&decl.body);
+        // Falling off the end returns nil
+        compiler.emit(Op::Nil, span);
+        compiler.emit(Op::Return, span);
+        Prototype {
+            name: decl.name,
+            params: decl.params.clone(),
+            return_type: decl.return_type,
+            chunk: compiler.chunk,
+        }
+    }
+
+    fn emit(&mut self, op: Op, span: Span) -> usize {
+        self.chunk.code.push(op);
+        self.chunk.spans.push(span);
+        self.chunk.code.len() - 1
+    }
+
+    // Points a jump emitted earlier at the next instruction
+    fn patch(&mut self, jump: usize) {
+        let target = self.chunk.code.len() as u32;
+        match &mut self.chunk.code[jump] {
+            Op::Jump(to)
+            | Op::JumpIfFalse(to)
+            | Op::JumpIfFalseOrPop(to)
+            | Op::JumpIfTrueOrPop(to)
+            | Op::JumpIfNotNil(to)
+            | Op::PushHandler(to) => *to = target,
+            op => unreachable!("{:?} is not a jump", op),
+        }
+    }
+
+    fn here(&self) -> u32 {
+        self.chunk.code.len() as u32
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        let known = self
+            .chunk
+            .constants
+            .iter()
+            .position(|known| match (known, &value) {
+                (Value::Integer(a), Value::Integer(b)) => a == b,
+                // Compared by bits, so 0.0 and -0.0 stay apart
+                (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+                (Value::String(a), Value::String(b)) => a == b,
+                _ => false,
+            });
+        match known {
+            Some(index) => index as u32,
+            None => {
+                self.chunk.constants.push(value);
+                self.chunk.constants.len() as u32 - 1
+            }
+        }
+    }
+
+    fn name(&mut self, name: Symbol) -> u32 {
+        match self.chunk.names.iter().position(|known| *known == name) {
+            Some(index) => index as u32,
+            None => {
+                self.chunk.names.push(name);
+                self.chunk.names.len() as u32 - 1
+            }
+        }
+    }
+
+    fn block(&mut self, statements: &'a [Stmt]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &'a Stmt) {
+        let span = statement.span;
+        match &statement.kind {
+            StmtKind::Print(print) => self.print(print, span),
+            StmtKind::Variable {
+                name,
+                ty,
+                init,
+                constant,
+                slot,
+            } => {
+                match &init.kind {
+                    // String literals are still read as whatever type they look like, unless
+                    // declared as strings
+                    ExprKind::Literal(Value::String(s)) if *ty != Some(Type::String) => {
+                        let value = VarManager::parse_value(s).unwrap_or(Value::String(s.clone()));
+                        self.literal(value, init.span);
+                    }
+                    _ => self.expression(init),
+                }
+                let name = self.name(*name);
+                if let Some(ty) = ty {
+                    self.emit(Op::Conform(*ty, name), init.span);
+                }
+                let slot = *slot as u32;
+                let op = if *constant {
+                    Op::DeclareConst { name, slot }
+                } else {
+                    Op::Declare {
+                        name,
+                        slot,
+                        ty: *ty,
+                    }
+                };
+                self.emit(op, span);
+            }
+            StmtKind::Update {
+                name,
+                op,
+                value,
+                slot,
+            } => {
+                let name = self.name(*name);
+                let (depth, slot) = (slot.depth as u32, slot.index as u32);
+                if *op != UpdateOp::Assign {
+                    self.emit(Op::LoadUpdate { name, depth, slot }, span);
+                }
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                if *op != UpdateOp::Assign {
+                    self.emit(Op::Update(*op), span);
+                }
+                self.emit(Op::Assign { name, depth, slot }, span);
+            }
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                let skip_then = self.emit(Op::JumpIfFalse(0), condition.span);
+                self.block(then_branch);
+                match else_branch {
+                    Some(else_branch) => {
+                        let skip_else = self.emit(Op::Jump(0), span);
+                        self.patch(skip_then);
+                        self.block(else_branch);
+                        self.patch(skip_else);
+                    }
+                    None => self.patch(skip_then),
+                }
+            }
+            StmtKind::While { condition, body } => {
+                let start = self.here();
+                self.expression(condition);
+                let exit = self.emit(Op::JumpIfFalse(0), condition.span);
+                self.block(body);
+                self.emit(Op::Jump(start), span);
+                self.patch(exit);
+            }
+            StmtKind::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                let start = self.here();
+                let exit = condition.as_ref().map(|condition| {
+                    self.expression(condition);
+                    self.emit(Op::JumpIfFalse(0), condition.span)
+                });
+                self.block(body);
+                if let Some(update) = update {
+                    self.statement(update);
+                }
+                self.emit(Op::Jump(start), span);
+                if let Some(exit) = exit {
+                    self.patch(exit);
+                }
+            }
+            StmtKind::Function { decl, slot } => {
+                let function = self.closure(decl, span);
+                self.emit(function, span);
+                let name = self.name(decl.name.unwrap_or_default());
+                let slot = *slot as u32;
+                self.emit(
+                    Op::Declare {
+                        name,
+                        slot,
+                        ty: None,
+                    },
+                    span,
+                );
+            }
+            StmtKind::Return(value) => self.return_statement(value.as_ref(), span),
+            StmtKind::Throw(expr) => {
+                self.expression(expr);
+                self.emit(Op::Throw, expr.span);
+            }
+            StmtKind::Try {
+                body,
+                catch,
+                finally,
+            } => self.try_statement(body, catch.as_ref(), finally.as_deref(), span),
+            StmtKind::Import { path, alias, slot } => {
+                let path = self.name(Symbol::intern(path));
+                self.emit(Op::Import(path), span);
+                let name = self.name(*alias);
+                let slot = *slot as u32;
+                self.emit(
+                    Op::Declare {
+                        name,
+                        slot,
+                        ty: None,
+                    },
+                    span,
+                );
+            }
+            // The export list is collected once the whole script is compiled
+            StmtKind::Export(_) => {}
+            StmtKind::Expression(expr) => {
+                self.expression(expr);
+                self.emit(Op::Pop, span);
+            }
+        }
+    }
+
+    fn print(&mut self, print: &PrintStmt, span: Span) {
+        for value in &print.values {
+            self.expression(value);
+        }
+        for (option, name) in [(&print.sep, "sep"), (&print.end, "end")] {
+            if let Some(expr) = option {
+                self.expression(expr);
+                let name = self.name(Symbol::intern(name));
+                self.emit(Op::PrintOption(name), expr.span);
+            }
+        }
+        self.emit(
+            Op::Print {
+                values: print.values.len() as u32,
+                sep: print.sep.is_some(),
+                end: print.end.is_some(),
+                newline: print.newline,
+                target: print.target,
+            },
+            span,
+        );
+    }
+
+    fn return_statement(&mut self, value: Option<&Expr>, span: Span) {
+        match value {
+            Some(value) => self.expression(value),
+            None => {
+                self.emit(Op::Nil, span);
+            }
+        }
+        // Leaving through the enclosing `try` statements removes their handlers and runs their
+        // `finally` blocks, innermost first. Each block is compiled as if only the statements
+        // outside it were still around, so a `return` inside one leaves through those.
+        let tries = self.tries.clone();
+        while let Some(finally) = self.tries.pop() {
+            self.emit(Op::PopHandler, span);
+            if let Some(finally) = finally {
+                self.block(finally);
+            }
+        }
+        self.tries = tries;
+        self.emit(Op::Return, self.statement.unwrap_or(span));
+    }
+
+    // The body runs with a handler installed that jumps to the catch clause. A `finally` block
+    // is compiled twice: once for leaving normally, and once for leaving with an error, which
+    // is raised again after it. With a catch clause too, a second handler leading to the error
+    // path is installed first, at the same stack height, so it still guards the catch clause
+    // once the first handler has been used.
+    fn try_statement(
+        &mut self,
+        body: &'a [Stmt],
+        catch: Option<&'a CatchClause>,
+        finally: Option<&'a [Stmt]>,
+        span: Span,
+    ) {
+        let failed = match (catch, finally) {
+            (Some(_), Some(_)) => {
+                let failed = self.emit(Op::PushHandler(0), span);
+                self.tries.push(finally);
+                Some(failed)
+            }
+            _ => None,
+        };
+        let handler = self.emit(Op::PushHandler(0), span);
+        self.tries
+            .push(if failed.is_some() { None } else { finally });
+        self.block(body);
+        self.emit(Op::PopHandler, span);
+        self.tries.pop();
+
+        let failed = match catch {
+            Some(catch) => {
+                if failed.is_some() {
+                    self.emit(Op::PopHandler, span);
+                }
+                let done = self.emit(Op::Jump(0), span);
+                self.patch(handler);
+                let name = self.name(catch.name);
+                let slot = catch.slot as u32;
+                self.emit(Op::Define { name, slot }, span);
+                self.block(&catch.body);
+                if failed.is_some() {
+                    self.emit(Op::PopHandler, span);
+                    self.tries.pop();
+                }
+                self.patch(done);
+                failed
+            }
+            None => Some(handler),
+        };
+
+        if let (Some(finally), Some(failed)) = (finally, failed) {
+            self.block(finally);
+            let end = self.emit(Op::Jump(0), span);
+            self.patch(failed);
+            self.block(finally);
+            self.emit(Op::Rethrow, span);
+            self.patch(end);
+        }
+    }
+
+    fn closure(&mut self, decl: &FunctionDecl, span: Span) -> Op {
+        let prototype = Self::function(decl, span);
+        self.chunk.functions.push(Rc::new(prototype));
+        Op::Closure(self.chunk.functions.len() as u32 - 1)
+    }
+
+    fn literal(&mut self, value: Value, span: Span) {
+        let op = match value {
+            Value::Nil => Op::Nil,
+            Value::Boolean(true) => Op::True,
+            Value::Boolean(false) => Op::False,
+            value => Op::Constant(self.constant(value)),
+        };
+        self.emit(op, span);
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Literal(value) => self.literal(value.clone(), span),
+            ExprKind::Variable { name, slot } => {
+                let name = self.name(*name);
+                let op = match slot {
+                    Some(slot) => Op::GetVar {
+                        name,
+                        depth: slot.depth as u32,
+                        slot: slot.index as u32,
+                    },
+                    None => Op::GetBuiltin(name),
+                };
+                self.emit(op, span);
+            }
+            ExprKind::Unary { op, expr } => {
+                self.expression(expr);
+                self.emit(Op::Unary(*op), span);
+            }
+            ExprKind::Binary { left, op, right } => {
+                self.expression(left);
+                self.expression(right);
+                self.emit(Op::Binary(*op), span);
+            }
+            ExprKind::Logical {
+                left,
+                op: LogicalOp::Coalesce,
+                right,
+            } => {
+                self.expression(left);
+                let skip = self.emit(Op::JumpIfNotNil(0), span);
+                self.expression(right);
+                self.patch(skip);
+            }
+            ExprKind::Logical { left, op, right } => {
+                self.expression(left);
+                self.emit(Op::Test, left.span);
+                let jump = match op {
+                    LogicalOp::And => Op::JumpIfFalseOrPop(0),
+                    _ => Op::JumpIfTrueOrPop(0),
+                };
+                let skip = self.emit(jump, span);
+                self.expression(right);
+                self.emit(Op::Test, right.span);
+                self.patch(skip);
+            }
+            ExprKind::Call { callee, args } => {
+                self.expression(callee);
+                for arg in args {
+                    self.expression(arg);
+                }
+                self.emit(Op::Call(args.len() as u32), span);
+            }
+            ExprKind::List(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+                self.emit(Op::List(items.len() as u32), span);
+            }
+            ExprKind::Index { object, index } => {
+                self.expression(object);
+                self.expression(index);
+                self.emit(Op::Index, span);
+            }
+            ExprKind::Get {
+                object,
+                name,
+                optional,
+            } => {
+                self.expression(object);
+                let name = self.name(*name);
+                let op = if *optional {
+                    Op::GetOptional(name)
+                } else {
+                    Op::Get(name)
+                };
+                self.emit(op, span);
+            }
+            ExprKind::Function(decl) => {
+                let function = self.closure(decl, span);
+                self.emit(function, span);
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// A readable listing of compiled code, one instruction per line with its source position. The
+// functions a chunk creates are listed after it.
+use super::chunk::{Chunk, Op, Program, Prototype};
+use crate::utils::ast::PrintTarget;
+use crate::utils::symbols::Symbol;
+use crate::utils::variables::Value;
+use std::fmt::Write;
+
+pub fn disassemble(program: &Program, source: &str) -> String {
+    let mut listing = String::new();
+    writeln!(listing, "== script {} ==", source).unwrap();
+    if !program.exports.is_empty() {
+        let exports: Vec<&str> = program.exports.iter().map(|name| name.as_str()).collect();
+        writeln!(listing, "exports: {}", exports.join(", ")).unwrap();
+    }
+    chunk(&mut listing, &program.main.chunk);
+    functions(&mut listing, &program.main.chunk);
+    listing
+}
+
+fn functions(listing: &mut String, parent: &Chunk) {
+    for function in &parent.functions {
+        writeln!(listing).unwrap();
+        writeln!(listing, "== {} ==", signature(function)).unwrap();
+        chunk(listing, &function.chunk);
+        functions(listing, &function.chunk);
+    }
+}
+
+fn signature(function: &Prototype) -> String {
+    let params: Vec<String> = function
+        .params
+        .iter()
+        .map(|param| match param.ty {
+            Some(ty) => format!("{}: {}", param.name, ty.name()),
+            None => param.name.to_string(),
+        })
+        .collect();
+    let mut signature = format!(
+        "fn {}({})",
+        function.name.map_or("<anonymous>", Symbol::as_str),
+        params.join(", ")
+    );
+    if let Some(ty) = function.return_type {
+        write!(signature, " -> {}", ty.name()).unwrap();
+    }
+    signature
+}
+
+fn chunk(listing: &mut String, chunk: &Chunk) {
+    for (offset, (op, span)) in chunk.code.iter().zip(&chunk.spans).enumerate() {
+        // Only the first of several instructions from the same position shows it
+        let position = if offset > 0 && chunk.spans[offset - 1] == *span {
+            "|".to_string()
+        } else {
+            format!("{}:{}", span.line, span.column)
+        };
+        let (name, operands) = instruction(chunk, *op);
+        let line = format!("{:04} {:>7}  {:<20} {}", offset, position, name, operands);
+        writeln!(listing, "{}", line.trim_end()).unwrap();
+    }
+}
+
+// The instruction's name, and its operands with whatever they refer to
+fn instruction(chunk: &Chunk, op: Op) -> (&'static str, String) {
+    let name = |index: u32| format!("{} {}", index, chunk.name(index));
+    let target = |index: u32| format!("-> {:04}", index);
+    // Where a variable is, as `depth:slot`
+    let variable =
+        |index: u32, depth: u32, slot: u32| format!("{} @{}:{}", name(index), depth, slot);
+    match op {
+        Op::Constant(index) => (
+            "CONSTANT",
+            format!("{} {}", index, constant(&chunk.constants[index as usize])),
+        ),
+        Op::Nil => ("NIL", String::new()),
+        Op::True => ("TRUE", String::new()),
+        Op::False => ("FALSE", String::new()),
+        Op::Pop => ("POP", String::new()),
+        Op::GetVar {
+            name: index,
+            depth,
+            slot,
+        } => ("GET_VAR", variable(index, depth, slot)),
+        Op::GetBuiltin(index) => ("GET_BUILTIN", name(index)),
+        Op::Declare {
+            name: index,
+            slot,
+            ty: None,
+        } => ("DECLARE", variable(index, 0, slot)),
+        Op::Declare {
+            name: index,
+            slot,
+            ty: Some(ty),
+        } => (
+            "DECLARE",
+            format!("{} {}", variable(index, 0, slot), ty.name()),
+        ),
+        Op::DeclareConst { name: index, slot } => ("DECLARE_CONST", variable(index, 0, slot)),
+        Op::Define { name: index, slot } => ("DEFINE", variable(index, 0, slot)),
+        Op::LoadUpdate {
+            name: index,
+            depth,
+            slot,
+        } => ("LOAD_UPDATE", variable(index, depth, slot)),
+        Op::Assign {
+            name: index,
+            depth,
+            slot,
+        } => ("ASSIGN", variable(index, depth, slot)),
+        Op::Update(op) => ("UPDATE", op.to_string()),
+        Op::Conform(ty, index) => ("CONFORM", format!("{} {}", ty.name(), name(index))),
+        Op::Unary(op) => ("UNARY", op.to_string()),
+        Op::Binary(op) => ("BINARY", op.to_string()),
+        Op::Test => ("TEST", String::new()),
+        Op::Jump(index) => ("JUMP", target(index)),
+        Op::JumpIfFalse(index) => ("JUMP_IF_FALSE", target(index)),
+        Op::JumpIfFalseOrPop(index) => ("JUMP_IF_FALSE_OR_POP", target(index)),
+        Op::JumpIfTrueOrPop(index) => ("JUMP_IF_TRUE_OR_POP", target(index)),
+        Op::JumpIfNotNil(index) => ("JUMP_IF_NOT_NIL", target(index)),
+        Op::Call(count) => ("CALL", count.to_string()),
+        Op::Return => ("RETURN", String::new()),
+        Op::Closure(index) => (
+            "CLOSURE",
+            format!("{} {}", index, signature(&chunk.functions[index as usize])),
+        ),
+        Op::List(count) => ("LIST", count.to_string()),
+        Op::Index => ("INDEX", String::new()),
+        Op::Get(index) => ("GET", name(index)),
+        Op::GetOptional(index) => ("GET_OPTIONAL", name(index)),
+        Op::PrintOption(index) => ("PRINT_OPTION", name(index)),
+        Op::Print {
+            values,
+            sep,
+            end,
+            newline,
+            target,
+        } => {
+            let mut operands = values.to_string();
+            for (set, flag) in [
+                (sep, "sep"),
+                (end, "end"),
+                (newline, "newline"),
+                (target == PrintTarget::Stderr, "stderr"),
+            ] {
+                if set {
+                    write!(operands, " {}", flag).unwrap();
+                }
+            }
+            ("PRINT", operands)
+        }
+        Op::Throw => ("THROW", String::new()),
+        Op::PushHandler(index) => ("PUSH_HANDLER", target(index)),
+        Op::PopHandler => ("POP_HANDLER", String::new()),
+        Op::Rethrow => ("RETHROW", String::new()),
+        Op::Import(index) => ("IMPORT", name(index)),
+        Op::Halt => ("HALT", String::new()),
+    }
+}
+
+// Strings are quoted so they can be told apart from numbers
+fn constant(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,599 @@
+// The `.bugc` file format, which stores a compiled script so it can be run without lexing or
+// parsing it again. All numbers are little-endian.
+//
+//     magic    "BUGC"
+//     version  u16
+//     source   string, the path the script was compiled from
+//     exports  u32 count, then that many strings
+//     globals  the same, naming each top-level variable slot
+//     main     prototype
+//
+// A prototype is its name (a flag byte, then a string), its parameters, its return type, its
+// constant pool, its names, its nested prototypes, its code, and a line table giving the source
+// position of each instruction as runs of instructions sharing one position.
+use super::chunk::{Chunk, Op, Program, Prototype};
+use super::verify;
+use crate::utils::ast::{BinaryOp, Param, PrintTarget, UnaryOp, UpdateOp};
+use crate::utils::symbols::Symbol;
+use crate::utils::tokeniser::Span;
+use crate::utils::types::Type;
+use crate::utils::variables::Value;
+use num_bigint::BigInt;
+use std::rc::Rc;
+
+pub const MAGIC: &[u8; 4] = b"BUGC";
+// Raised whenever the layout changes, so older files are rejected instead of misread
+pub const VERSION: u16 = 5;
+
+// Operand encodings for the enums instructions carry: each is stored as its position here
+const TYPES: [Type; 10] = [
+    Type::Int,
+    Type::Float,
+    Type::String,
+    Type::Bool,
+    Type::Nil,
+    Type::List,
+    Type::Function,
+    Type::Error,
+    Type::Module,
+    Type::Any,
+];
+const UNARY_OPS: [UnaryOp; 4] = [
+    UnaryOp::Negate,
+    UnaryOp::Not,
+    UnaryOp::TypeOf,
+    UnaryOp::BitNot,
+];
+const BINARY_OPS: [BinaryOp; 18] = [
+    BinaryOp::Add,
+    BinaryOp::Subtract,
+    BinaryOp::Multiply,
+    BinaryOp::Divide,
+    BinaryOp::FloorDivide,
+    BinaryOp::Modulo,
+    BinaryOp::Power,
+    BinaryOp::BitAnd,
+    BinaryOp::BitOr,
+    BinaryOp::BitXor,
+    BinaryOp::ShiftLeft,
+    BinaryOp::ShiftRight,
+    BinaryOp::Equal,
+    BinaryOp::NotEqual,
+    BinaryOp::Less,
+    BinaryOp::LessEqual,
+    BinaryOp::Greater,
+    BinaryOp::GreaterEqual,
+];
+const UPDATE_OPS: [UpdateOp; 5] = [
+    UpdateOp::Assign,
+    UpdateOp::Add,
+    UpdateOp::Subtract,
+    UpdateOp::Increment,
+    UpdateOp::Decrement,
+];
+
+// Tags for the kinds of value a constant pool holds
+const INTEGER: u8 = 0;
+const FLOAT: u8 = 1;
+const STRING: u8 = 2;
+const BIG_INT: u8 = 3;
+
+// Flags in the operand of a print instruction
+const PRINT_SEP: u8 = 1;
+const PRINT_END: u8 = 2;
+const PRINT_NEWLINE: u8 = 4;
+const PRINT_STDERR: u8 = 8;
+
+// Whether a file's contents are a compiled script rather than source code
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn encode(program: &Program, source: &str) -> Vec<u8> {
+    let mut writer = Writer { bytes: Vec::new() };
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.bytes.extend_from_slice(&VERSION.to_le_bytes());
+    writer.string(source);
+    for names in [&program.exports, &program.globals] {
+        writer.u32(names.len());
+        for name in names {
+            writer.string(name.as_str());
+        }
+    }
+    writer.prototype(&program.main);
+    writer.bytes
+}
+
+// Reads a compiled script, giving the program and the path it was compiled from
+pub fn decode(bytes: &[u8]) -> Result<(Program, String), String> {
+    if !is_compiled(bytes) {
+        return Err("is not a compiled script".to_string());
+    }
+    let mut reader = Reader {
+        bytes,
+        position: MAGIC.len(),
+    };
+    let version = u16::from_le_bytes([reader.u8()?, reader.u8()?]);
+    if version != VERSION {
+        return Err(format!(
+            "was compiled for format version {}, but this build reads version {}",
+            version, VERSION
+        ));
+    }
+    let source = reader.string()?;
+    let exports = reader.symbols()?;
+    let globals = reader.symbols()?;
+    let main = Rc::new(reader.prototype()?);
+    if reader.position != bytes.len() {
+        return Err("has unexpected data at its end".to_string());
+    }
+    let program = Program {
+        main,
+        globals,
+        exports,
+    };
+    verify::verify(&program)?;
+    Ok((program, source))
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: impl TryInto<u32>) {
+        let value = value
+            .try_into()
+            .unwrap_or_else(|_| panic!("value too large for the bytecode format"));
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, text: &str) {
+        self.u32(text.len());
+        self.bytes.extend_from_slice(text.as_bytes());
+    }
+
+    // An enum operand, as its position in the table of all its variants
+    fn index<T: PartialEq>(&mut self, table: &[T], value: T) {
+        let index = table
+            .iter()
+            .position(|known| *known == value)
+            .expect("every variant is listed");
+        self.u8(index as u8);
+    }
+
+    fn optional_type(&mut self, ty: Option<Type>) {
+        match ty {
+            Some(ty) => {
+                self.u8(1);
+                self.index(&TYPES, ty);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn prototype(&mut self, prototype: &Prototype) {
+        match &prototype.name {
+            Some(name) => {
+                self.u8(1);
+                self.string(name.as_str());
+            }
+            None => self.u8(0),
+        }
+        self.u32(prototype.params.len());
+        for param in &prototype.params {
+            self.string(param.name.as_str());
+            self.optional_type(param.ty);
+        }
+        self.optional_type(prototype.return_type);
+        self.chunk(&prototype.chunk);
+    }
+
+    fn chunk(&mut self, chunk: &Chunk) {
+        self.u32(chunk.constants.len());
+        for constant in &chunk.constants {
+            self.constant(constant);
+        }
+        self.u32(chunk.names.len());
+        for name in &chunk.names {
+            self.string(name.as_str());
+        }
+        self.u32(chunk.functions.len());
+        for function in &chunk.functions {
+            self.prototype(function);
+        }
+        self.u32(chunk.code.len());
+        for op in &chunk.code {
+            self.op(*op);
+        }
+        self.lines(&chunk.spans);
+    }
+
+    fn constant(&mut self, value: &Value) {
+        match value {
+            Value::Integer(i) => {
+                self.u8(INTEGER);
+                self.bytes.extend_from_slice(&i.to_le_bytes());
+            }
+            Value::Float(f) => {
+                self.u8(FLOAT);
+                self.bytes.extend_from_slice(&f.to_le_bytes());
+            }
+            Value::String(s) => {
+                self.u8(STRING);
+                self.string(s);
+            }
+            Value::BigInt(n) => {
+                self.u8(BIG_INT);
+                self.string(&n.to_string());
+            }
+            other => unreachable!("{:?} cannot be a constant", other),
+        }
+    }
+
+    // Runs of instructions that share a source position
+    fn lines(&mut self, spans: &[Span]) {
+        let mut runs: Vec<(usize, Span)> = Vec::new();
+        for span in spans {
+            match runs.last_mut() {
+                Some((count, last)) if last == span => *count += 1,
+                _ => runs.push((1, *span)),
+            }
+        }
+        self.u32(runs.len());
+        for (count, span) in runs {
+            self.u32(count);
+            self.u32(span.line);
+            self.u32(span.column);
+        }
+    }
+
+    fn op(&mut self, op: Op) {
+        match op {
+            Op::Constant(index) => self.opcode(0, index),
+            Op::Nil => self.u8(1),
+            Op::True => self.u8(2),
+            Op::False => self.u8(3),
+            Op::Pop => self.u8(4),
+            Op::GetVar { name, depth, slot } => self.variable(5, name, depth, slot),
+            Op::Declare { name, slot, ty } => {
+                self.local(6, name, slot);
+                self.optional_type(ty);
+            }
+            Op::DeclareConst { name, slot } => self.local(7, name, slot),
+            Op::Define { name, slot } => self.local(8, name, slot),
+            Op::LoadUpdate { name, depth, slot } => self.variable(9, name, depth, slot),
+            Op::Assign { name, depth, slot } => self.variable(10, name, depth, slot),
+            Op::Update(op) => {
+                self.u8(11);
+                self.index(&UPDATE_OPS, op);
+            }
+            Op::Conform(ty, name) => {
+                self.u8(12);
+                self.index(&TYPES, ty);
+                self.u32(name);
+            }
+            Op::Unary(op) => {
+                self.u8(13);
+                self.index(&UNARY_OPS, op);
+            }
+            Op::Binary(op) => {
+                self.u8(14);
+                self.index(&BINARY_OPS, op);
+            }
+            Op::Test => self.u8(15),
+            Op::Jump(target) => self.opcode(16, target),
+            Op::JumpIfFalse(target) => self.opcode(17, target),
+            Op::JumpIfFalseOrPop(target) => self.opcode(18, target),
+            Op::JumpIfTrueOrPop(target) => self.opcode(19, target),
+            Op::JumpIfNotNil(target) => self.opcode(20, target),
+            Op::Call(count) => self.opcode(21, count),
+            Op::Return => self.u8(22),
+            Op::Closure(index) => self.opcode(23, index),
+            Op::List(count) => self.opcode(24, count),
+            Op::Index => self.u8(25),
+            Op::Get(name) => self.opcode(26, name),
+            Op::GetOptional(name) => self.opcode(27, name),
+            Op::PrintOption(name) => self.opcode(28, name),
+            Op::Print {
+                values,
+                sep,
+                end,
+                newline,
+                target,
+            } => {
+                self.opcode(29, values);
+                let mut flags = 0;
+                for (set, flag) in [
+                    (sep, PRINT_SEP),
+                    (end, PRINT_END),
+                    (newline, PRINT_NEWLINE),
+                    (target == PrintTarget::Stderr, PRINT_STDERR),
+                ] {
+                    if set {
+                        flags |= flag;
+                    }
+                }
+                self.u8(flags);
+            }
+            Op::Throw => self.u8(30),
+            Op::PushHandler(target) => self.opcode(31, target),
+            Op::PopHandler => self.u8(32),
+            Op::Rethrow => self.u8(33),
+            Op::Import(path) => self.opcode(34, path),
+            Op::Halt => self.u8(35),
+            Op::GetBuiltin(name) => self.opcode(36, name),
+        }
+    }
+
+    fn opcode(&mut self, code: u8, operand: u32) {
+        self.u8(code);
+        self.u32(operand);
+    }
+
+    fn local(&mut self, code: u8, name: u32, slot: u32) {
+        self.opcode(code, name);
+        self.u32(slot);
+    }
+
+    fn variable(&mut self, code: u8, name: u32, depth: u32, slot: u32) {
+        self.opcode(code, name);
+        self.u32(depth);
+        self.u32(slot);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], String> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or("is truncated")?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().expect("four bytes")))
+    }
+
+    fn u64(&mut self) -> Result<[u8; 8], String> {
+        Ok(self.take(8)?.try_into().expect("eight bytes"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u32()? as usize;
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "contains a malformed string".to_string())
+    }
+
+    // Names are interned as they are read, like those the tokenizer finds
+    fn symbol(&mut self) -> Result<Symbol, String> {
+        Ok(Symbol::intern(&self.string()?))
+    }
+
+    fn symbols(&mut self) -> Result<Vec<Symbol>, String> {
+        (0..self.u32()?).map(|_| self.symbol()).collect()
+    }
+
+    fn index<T: Copy>(&mut self, table: &[T]) -> Result<T, String> {
+        let index = self.u8()? as usize;
+        table
+            .get(index)
+            .copied()
+            .ok_or_else(|| "contains an unknown operand".to_string())
+    }
+
+    fn optional_type(&mut self) -> Result<Option<Type>, String> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.index(&TYPES)?)),
+        }
+    }
+
+    fn prototype(&mut self) -> Result<Prototype, String> {
+        let name = match self.u8()? {
+            0 => None,
+            _ => Some(self.symbol()?),
+        };
+        let params = (0..self.u32()?)
+            .map(|_| {
+                Ok(Param {
+                    name: self.symbol()?,
+                    ty: self.optional_type()?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        let return_type = self.optional_type()?;
+        let chunk = self.chunk()?;
+        Ok(Prototype {
+            name,
+            params,
+            return_type,
+            chunk,
+        })
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, String> {
+        let constants: Vec<Value> = (0..self.u32()?)
+            .map(|_| self.constant())
+            .collect::<Result<_, _>>()?;
+        let names = self.symbols()?;
+        let functions = (0..self.u32()?)
+            .map(|_| self.prototype().map(Rc::new))
+            .collect::<Result<_, _>>()?;
+        let code: Vec<Op> = (0..self.u32()?)
+            .map(|_| self.op())
+            .collect::<Result<_, _>>()?;
+        let spans = self.lines(code.len())?;
+        let chunk = Chunk {
+            code,
+            spans,
+            constants,
+            names,
+            functions,
+        };
+        if chunk.spans.len() != chunk.code.len() {
+            return Err("has a line table that does not match its code".to_string());
+        }
+        if !chunk.code.iter().all(|op| Self::in_bounds(&chunk, *op)) {
+            return Err("contains an instruction with an operand out of range".to_string());
+        }
+        Ok(chunk)
+    }
+
+    // Whether an instruction only refers to things its chunk has. What running the code needs
+    // beyond that, such as values on the stack, is checked once the whole program is read.
+    fn in_bounds(chunk: &Chunk, op: Op) -> bool {
+        let constant = |index: u32| (index as usize) < chunk.constants.len();
+        let name = |index: u32| (index as usize) < chunk.names.len();
+        let target = |index: u32| (index as usize) < chunk.code.len();
+        match op {
+            Op::Constant(index) => constant(index),
+            Op::GetVar { name: index, .. }
+            | Op::GetBuiltin(index)
+            | Op::Declare { name: index, .. }
+            | Op::DeclareConst { name: index, .. }
+            | Op::Define { name: index, .. }
+            | Op::LoadUpdate { name: index, .. }
+            | Op::Assign { name: index, .. }
+            | Op::Conform(_, index)
+            | Op::Get(index)
+            | Op::GetOptional(index)
+            | Op::PrintOption(index)
+            | Op::Import(index) => name(index),
+            Op::Jump(index)
+            | Op::JumpIfFalse(index)
+            | Op::JumpIfFalseOrPop(index)
+            | Op::JumpIfTrueOrPop(index)
+            | Op::JumpIfNotNil(index)
+            | Op::PushHandler(index) => target(index),
+            Op::Closure(index) => (index as usize) < chunk.functions.len(),
+            _ => true,
+        }
+    }
+
+    fn constant(&mut self) -> Result<Value, String> {
+        Ok(match self.u8()? {
+            INTEGER => Value::Integer(i64::from_le_bytes(self.u64()?)),
+            FLOAT => Value::Float(f64::from_le_bytes(self.u64()?)),
+            STRING => Value::String(self.string()?.into()),
+            BIG_INT => match self.string()?.parse::<BigInt>() {
+                Ok(n) => Value::BigInt(Rc::new(n)),
+                Err(_) => return Err("contains a malformed integer".to_string()),
+            },
+            _ => return Err("contains a constant of an unknown kind".to_string()),
+        })
+    }
+
+    fn lines(&mut self, instructions: usize) -> Result<Vec<Span>, String> {
+        let mut spans = Vec::new();
+        for _ in 0..self.u32()? {
+            let count = self.u32()? as usize;
+            let span = Span {
+                line: self.u32()? as usize,
+                column: self.u32()? as usize,
+            };
+            if spans.len() + count > instructions {
+                return Err("has a line table that does not match its code".to_string());
+            }
+            spans.extend(std::iter::repeat_n(span, count));
+        }
+        Ok(spans)
+    }
+
+    fn op(&mut self) -> Result<Op, String> {
+        Ok(match self.u8()? {
+            0 => Op::Constant(self.u32()?),
+            1 => Op::Nil,
+            2 => Op::True,
+            3 => Op::False,
+            4 => Op::Pop,
+            5 => Op::GetVar {
+                name: self.u32()?,
+                depth: self.u32()?,
+                slot: self.u32()?,
+            },
+            6 => Op::Declare {
+                name: self.u32()?,
+                slot: self.u32()?,
+                ty: self.optional_type()?,
+            },
+            7 => Op::DeclareConst {
+                name: self.u32()?,
+                slot: self.u32()?,
+            },
+            8 => Op::Define {
+                name: self.u32()?,
+                slot: self.u32()?,
+            },
+            9 => Op::LoadUpdate {
+                name: self.u32()?,
+                depth: self.u32()?,
+                slot: self.u32()?,
+            },
+            10 => Op::Assign {
+                name: self.u32()?,
+                depth: self.u32()?,
+                slot: self.u32()?,
+            },
+            11 => Op::Update(self.index(&UPDATE_OPS)?),
+            12 => Op::Conform(self.index(&TYPES)?, self.u32()?),
+            13 => Op::Unary(self.index(&UNARY_OPS)?),
+            14 => Op::Binary(self.index(&BINARY_OPS)?),
+            15 => Op::Test,
+            16 => Op::Jump(self.u32()?),
+            17 => Op::JumpIfFalse(self.u32()?),
+            18 => Op::JumpIfFalseOrPop(self.u32()?),
+            19 => Op::JumpIfTrueOrPop(self.u32()?),
+            20 => Op::JumpIfNotNil(self.u32()?),
+            21 => Op::Call(self.u32()?),
+            22 => Op::Return,
+            23 => Op::Closure(self.u32()?),
+            24 => Op::List(self.u32()?),
+            25 => Op::Index,
+            26 => Op::Get(self.u32()?),
+            27 => Op::GetOptional(self.u32()?),
+            28 => Op::PrintOption(self.u32()?),
+            29 => {
+                let values = self.u32()?;
+                let flags = self.u8()?;
+                Op::Print {
+                    values,
+                    sep: flags & PRINT_SEP != 0,
+                    end: flags & PRINT_END != 0,
+                    newline: flags & PRINT_NEWLINE != 0,
+                    target: if flags & PRINT_STDERR != 0 {
+                        PrintTarget::Stderr
+                    } else {
+                        PrintTarget::Stdout
+                    },
+                }
+            }
+            30 => Op::Throw,
+            31 => Op::PushHandler(self.u32()?),
+            32 => Op::PopHandler,
+            33 => Op::Rethrow,
+            34 => Op::Import(self.u32()?),
+            35 => Op::Halt,
+            36 => Op::GetBuiltin(self.u32()?),
+            code => return Err(format!("contains an unknown instruction {}", code)),
+        })
+    }
+}
@@ -0,0 +1,525 @@
+// Runs compiled programs on a value stack. Variables, builtins, modules and input live in an
+// `Interpreter`, whose operations on values the VM shares so both engines behave the same.
+use super::chunk::{Closure, Op, Program, Prototype};
+use super::compiler::Compiler;
+use super::format;
+use crate::interpreter::Interpreter;
+use crate::utils::ast::{Slot, UpdateOp};
+use crate::utils::errors::{BugError, ErrorKind};
+use crate::utils::memory;
+use crate::utils::options::Options;
+use crate::utils::tokeniser::Span;
+use crate::utils::variables::{Module, Scope, Value, Variable};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+struct Frame {
+    prototype: Rc<Prototype>,
+    // The function being run, or None for a script
+    closure: Option<Rc<Closure>>,
+    ip: usize,
+    // Where the callee sits on the stack; everything from there on is dropped when it returns
+    base: usize,
+    // The caller's scopes, put back when a function returns
+    caller_scopes: Option<Vec<Scope>>,
+    // Where a function was called from
+    span: Span,
+}
+
+// An installed `try` handler
+struct Handler {
+    frame: usize,
+    // The stack height to go back to before pushing the error
+    stack: usize,
+    target: usize,
+}
+
+const STACK_RESERVED: usize = 256;
+const FRAMES_RESERVED: usize = 64;
+
+pub struct Vm {
+    interpreter: Interpreter,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    handlers: Vec<Handler>,
+}
+
+impl Vm {
+    pub fn new(options: Options) -> Self {
+        // Room for the stacks is set aside before the interpreter starts counting memory, the way
+        // the tree-walker's own stack is not counted
+        let stack = Vec::with_capacity(STACK_RESERVED);
+        let frames = Vec::with_capacity(FRAMES_RESERVED);
+        let handlers = Vec::with_capacity(FRAMES_RESERVED);
+        Self {
+            interpreter: Interpreter::new(options),
+            stack,
+            frames,
+            handlers,
+        }
+    }
+
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.interpreter.set_input(input);
+    }
+
+    pub fn run_file(&mut self, path: &Path) -> Result<(), BugError> {
+        let canonical = Interpreter::resolve(path, Span::default())?;
+        let before = memory::allocated();
+        let source = Interpreter::read_source(&canonical, path, Span::default())?;
+        self.interpreter.exclude_memory(before);
+        self.interpreter.enter_file(canonical);
+        let result = self.run(&source);
+        self.interpreter.leave_file();
+        result.map_err(|error| error.in_file(path))
+    }
+
+    // Runs a script read from a `.bugc` file. Errors name the source file it was compiled from.
+    pub fn run_compiled(&mut self, path: &Path, bytes: &[u8]) -> Result<(), BugError> {
+        let canonical = Interpreter::resolve(path, Span::default())?;
+        let before = memory::allocated();
+        let (program, source) = format::decode(bytes).map_err(|problem| {
+            BugError::new(
+                ErrorKind::Import,
+                format!("'{}' {}", path.display(), problem),
+                Span::default(),
+            )
+        })?;
+        self.interpreter.exclude_memory(before);
+        self.interpreter.enter_file(canonical);
+        let result = self.execute(&program);
+        self.interpreter.leave_file();
+        result.map_err(|error| error.in_file(Path::new(&source)))
+    }
+
+    pub fn run(&mut self, source: &str) -> Result<(), BugError> {
+        let before = memory::allocated();
+        let script = Interpreter::parse(source, self.interpreter.options())?;
+        let program = Compiler::compile(&script);
+        self.interpreter.exclude_memory(before);
+        self.execute(&program)
+    }
+
+    // Runs a program to its end in the current scope
+    pub fn execute(&mut self, program: &Program) -> Result<(), BugError> {
+        let depth = self.frames.len();
+        self.frames.push(Frame {
+            prototype: Rc::clone(&program.main),
+            closure: None,
+            ip: 0,
+            base: self.stack.len(),
+            caller_scopes: None,
+            span: Span::default(),
+        });
+        loop {
+            let error = match self.dispatch() {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+            // Only handlers installed while running this program may catch its errors
+            match self.handlers.last() {
+                Some(handler) if handler.frame >= depth && error.kind.is_catchable() => {
+                    let handler = self.handlers.pop().expect("a handler");
+                    self.unwind(handler.frame + 1);
+                    self.stack.truncate(handler.stack);
+                    self.stack.push(Value::Error(Rc::new(error)));
+                    self.frames[handler.frame].ip = handler.target;
+                }
+                _ => {
+                    let base = self.frames[depth].base;
+                    self.unwind(depth);
+                    self.stack.truncate(base);
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    // Drops every frame above the given number, restoring the scopes of their callers
+    fn unwind(&mut self, frames: usize) {
+        while self.frames.len() > frames {
+            let frame = self.frames.pop().expect("a frame");
+            if let Some(scopes) = frame.caller_scopes {
+                self.interpreter.var_manager().restore(scopes);
+            }
+            if frame.closure.is_some() {
+                self.interpreter.leave_call();
+            }
+        }
+        while self
+            .handlers
+            .last()
+            .is_some_and(|handler| handler.frame >= frames)
+        {
+            self.handlers.pop();
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the stack is empty")
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().expect("the stack is empty")
+    }
+
+    // Runs instructions until the current program halts or raises an error
+    fn dispatch(&mut self) -> Result<(), BugError> {
+        let frame = self.frames.last().expect("a frame");
+        let mut prototype = Rc::clone(&frame.prototype);
+        let mut ip = frame.ip;
+        loop {
+            let op = prototype.chunk.code[ip];
+            let span = prototype.chunk.spans[ip];
+            ip += 1;
+            self.interpreter.step(span)?;
+            match op {
+                Op::Constant(index) => {
+                    let value = prototype.chunk.constants[index as usize].clone();
+                    self.stack.push(value);
+                }
+                Op::Nil => self.stack.push(Value::Nil),
+                Op::True => self.stack.push(Value::Boolean(true)),
+                Op::False => self.stack.push(Value::Boolean(false)),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::GetVar { name, depth, slot } => {
+                    let name = prototype.chunk.name(name);
+                    let value = self
+                        .interpreter
+                        .lookup(name, Some(slot_at(depth, slot)), span)?;
+                    self.stack.push(value);
+                }
+                Op::GetBuiltin(name) => {
+                    let value = self
+                        .interpreter
+                        .lookup(prototype.chunk.name(name), None, span)?;
+                    self.stack.push(value);
+                }
+                Op::Declare { name, slot, ty } => {
+                    let value = self.pop();
+                    self.interpreter.declare(
+                        prototype.chunk.name(name),
+                        slot as usize,
+                        value,
+                        false,
+                        ty,
+                        span,
+                    )?;
+                }
+                Op::DeclareConst { name, slot } => {
+                    let value = self.pop();
+                    self.interpreter.declare(
+                        prototype.chunk.name(name),
+                        slot as usize,
+                        value,
+                        true,
+                        None,
+                        span,
+                    )?;
+                }
+                Op::Define { slot, .. } => {
+                    let value = self.pop();
+                    self.interpreter.bind_caught(slot as usize, value);
+                }
+                Op::LoadUpdate { name, depth, slot } => {
+                    match self.interpreter.var_manager().get(slot_at(depth, slot)) {
+                        Some(variable) => self.stack.push(variable.value),
+                        None => {
+                            let name = prototype.chunk.name(name);
+                            return Err(Interpreter::undeclared(name, span));
+                        }
+                    }
+                }
+                Op::Assign { name, depth, slot } => {
+                    let value = self.pop();
+                    self.interpreter.assign(
+                        prototype.chunk.name(name),
+                        slot_at(depth, slot),
+                        value,
+                        span,
+                    )?;
+                }
+                Op::Update(op) => {
+                    let amount = match op {
+                        UpdateOp::Add | UpdateOp::Subtract => Some(self.pop()),
+                        _ => None,
+                    };
+                    let current = self.pop();
+                    let value = self.interpreter.updated(op, current, amount, span)?;
+                    self.stack.push(value);
+                }
+                Op::Conform(ty, name) => {
+                    let value = self.pop();
+                    let value = Interpreter::conform(
+                        value,
+                        Some(ty),
+                        prototype.chunk.name(name).as_str(),
+                        span,
+                    )?;
+                    self.stack.push(value);
+                }
+                Op::Unary(op) => {
+                    let value = self.pop();
+                    let value = self.interpreter.unary(op, value, span)?;
+                    self.stack.push(value);
+                }
+                Op::Binary(op) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = self.interpreter.arithmetic(op, left, right, span)?;
+                    self.stack.push(value);
+                }
+                Op::Test => {
+                    let value = self.pop();
+                    let test = self.interpreter.condition(value, span)?;
+                    self.stack.push(Value::Boolean(test));
+                }
+                Op::Jump(target) => ip = target as usize,
+                Op::JumpIfFalse(target) => {
+                    let value = self.pop();
+                    if !self.interpreter.condition(value, span)? {
+                        ip = target as usize;
+                    }
+                }
+                Op::JumpIfFalseOrPop(target) | Op::JumpIfTrueOrPop(target) => {
+                    let decided = matches!(op, Op::JumpIfTrueOrPop(_));
+                    if matches!(self.peek(), Value::Boolean(b) if *b == decided) {
+                        ip = target as usize;
+                    } else {
+                        self.pop();
+                    }
+                }
+                Op::JumpIfNotNil(target) => {
+                    if matches!(self.peek(), Value::Nil) {
+                        self.pop();
+                    } else {
+                        ip = target as usize;
+                    }
+                }
+                Op::Call(count) => {
+                    let callee = self.stack.len() - count as usize - 1;
+                    let closure = match &self.stack[callee] {
+                        Value::Closure(closure) => Rc::clone(closure),
+                        Value::Native(native) => {
+                            let native = *native;
+                            Interpreter::check_arity(native, count as usize, span)?;
+                            let args = self.stack.split_off(callee + 1);
+                            self.pop();
+                            let value = (native.function)(&mut self.interpreter, &args, span)?;
+                            self.stack.push(value);
+                            continue;
+                        }
+                        other => {
+                            return Err(BugError::new(
+                                ErrorKind::Type,
+                                format!("{} is not a function", other),
+                                span,
+                            ))
+                        }
+                    };
+                    self.enter(closure, callee, span)?;
+                    let caller = self.frames.len() - 2;
+                    self.frames[caller].ip = ip;
+                    prototype = Rc::clone(&self.frames.last().expect("a frame").prototype);
+                    ip = 0;
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.last().expect("a frame");
+                    let Some(closure) = &frame.closure else {
+                        return Err(BugError::new(
+                            ErrorKind::Syntax,
+                            "'return' outside of a function",
+                            span,
+                        ));
+                    };
+                    let value = match closure.prototype.return_type {
+                        Some(ty) => {
+                            let what =
+                                format!("The result of {}", Value::Closure(Rc::clone(closure)));
+                            Interpreter::conform(value, Some(ty), &what, frame.span)?
+                        }
+                        None => value,
+                    };
+                    let base = frame.base;
+                    self.unwind(self.frames.len() - 1);
+                    self.stack.truncate(base);
+                    self.stack.push(value);
+                    let frame = self.frames.last().expect("a frame");
+                    prototype = Rc::clone(&frame.prototype);
+                    ip = frame.ip;
+                }
+                Op::Closure(index) => {
+                    let closure = Closure {
+                        prototype: Rc::clone(&prototype.chunk.functions[index as usize]),
+                        scopes: self.interpreter.var_manager().capture(),
+                    };
+                    self.stack.push(Value::Closure(Rc::new(closure)));
+                }
+                Op::List(count) => {
+                    let items = self.stack.split_off(self.stack.len() - count as usize);
+                    self.stack.push(Value::List(Rc::new(items)));
+                }
+                Op::Index => {
+                    let index = self.pop();
+                    let object = self.pop();
+                    let value = self.interpreter.get_index(object, index, span)?;
+                    self.stack.push(value);
+                }
+                Op::Get(name) | Op::GetOptional(name) => {
+                    let value = match self.pop() {
+                        Value::Nil if matches!(op, Op::GetOptional(_)) => Value::Nil,
+                        object => self.interpreter.get_property(
+                            object,
+                            prototype.chunk.name(name),
+                            span,
+                        )?,
+                    };
+                    self.stack.push(value);
+                }
+                Op::PrintOption(name) => {
+                    let value = self.pop();
+                    let text = Interpreter::print_string(
+                        prototype.chunk.name(name).as_str(),
+                        value,
+                        span,
+                    )?;
+                    self.stack.push(Value::String(text.into()));
+                }
+                Op::Print {
+                    values,
+                    sep,
+                    end,
+                    newline,
+                    target,
+                } => {
+                    let end = match end {
+                        true => self.pop().to_string(),
+                        false if newline => "\n".to_string(),
+                        false => String::new(),
+                    };
+                    let sep = match sep {
+                        true => self.pop().to_string(),
+                        false => " ".to_string(),
+                    };
+                    let values = self.stack.split_off(self.stack.len() - values as usize);
+                    let values: Vec<String> = values.iter().map(Value::to_string).collect();
+                    self.interpreter
+                        .write_output(target, &(values.join(&sep) + &end), span)?;
+                }
+                Op::Throw | Op::Rethrow => {
+                    let value = self.pop();
+                    return Err(Interpreter::raised(value, span));
+                }
+                Op::PushHandler(target) => self.handlers.push(Handler {
+                    frame: self.frames.len() - 1,
+                    stack: self.stack.len(),
+                    target: target as usize,
+                }),
+                Op::PopHandler => {
+                    self.handlers.pop();
+                }
+                Op::Import(path) => {
+                    let canonical = self
+                        .interpreter
+                        .locate_module(prototype.chunk.name(path).as_str(), span)?;
+                    let module = match self.interpreter.loaded_module(&canonical) {
+                        Some(module) => module,
+                        None => {
+                            self.frames.last_mut().expect("a frame").ip = ip;
+                            self.load_module(canonical, span)?
+                        }
+                    };
+                    self.stack.push(Value::Module(module));
+                }
+                Op::Halt => {
+                    let frame = self.frames.pop().expect("a frame");
+                    self.stack.truncate(frame.base);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // Starts running a closure whose arguments are on the stack above it
+    fn enter(&mut self, closure: Rc<Closure>, callee: usize, span: Span) -> Result<(), BugError> {
+        let params = &closure.prototype.params;
+        let count = self.stack.len() - callee - 1;
+        if count != params.len() {
+            return Err(BugError::new(
+                ErrorKind::Type,
+                format!(
+                    "{} expects {} arguments but got {}",
+                    Value::Closure(Rc::clone(&closure)),
+                    params.len(),
+                    count
+                ),
+                span,
+            ));
+        }
+        let args = self.stack.split_off(callee + 1);
+        let mut values = Vec::with_capacity(args.len());
+        for (param, value) in params.iter().zip(args) {
+            values.push(Interpreter::conform(
+                value,
+                param.ty,
+                param.name.as_str(),
+                span,
+            )?);
+        }
+
+        self.interpreter.enter_call(span)?;
+        let caller_scopes = self.interpreter.var_manager().enter(&closure.scopes);
+        for (slot, (param, value)) in params.iter().zip(values).enumerate() {
+            self.interpreter.var_manager().define(
+                slot,
+                Variable {
+                    value,
+                    constant: false,
+                    ty: param.ty,
+                },
+            );
+        }
+        self.frames.push(Frame {
+            prototype: Rc::clone(&closure.prototype),
+            closure: Some(closure),
+            ip: 0,
+            base: callee,
+            caller_scopes: Some(caller_scopes),
+            span,
+        });
+        Ok(())
+    }
+
+    // Compiles and runs a module in its own global scope
+    fn load_module(&mut self, canonical: PathBuf, span: Span) -> Result<Rc<Module>, BugError> {
+        let path = Interpreter::module_path(&canonical);
+        let before = memory::allocated();
+        let source = Interpreter::read_source(&canonical, &path, span)?;
+        let script = Interpreter::parse(&source, self.interpreter.options())
+            .map_err(|error| error.in_file(&path))?;
+        let program = Compiler::compile(&script);
+        self.interpreter.exclude_memory(before);
+
+        let importer_scopes = self.interpreter.begin_module(canonical.clone());
+        let result = self.execute(&program);
+        self.interpreter.end_module(
+            canonical,
+            importer_scopes,
+            result,
+            &program.globals,
+            &program.exports,
+            span,
+        )
+    }
+}
+
+fn slot_at(depth: u32, slot: u32) -> Slot {
+    Slot {
+        depth: depth as usize,
+        index: slot as usize,
+    }
+}
@@ -0,0 +1,13 @@
+// A bytecode backend: scripts are compiled to chunks of instructions and run on a stack machine
+mod chunk;
+mod compiler;
+mod disasm;
+mod format;
+mod machine;
+mod verify;
+
+pub use chunk::Closure;
+pub use compiler::Compiler;
+pub use disasm::disassemble;
+pub use format::{decode, encode, is_compiled};
+pub use machine::Vm;
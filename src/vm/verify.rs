@@ -0,0 +1,217 @@
+// Checks a decoded program before it runs, so that no instruction in it can make the VM panic. Each
+// chunk's code is followed along every path from its start, tracking how many values the stack
+// holds and which `try` handlers are installed. Compiled code reaches each instruction in the same
+// state whichever way it gets there, so any file where that does not hold is rejected.
+use super::chunk::{Op, Program, Prototype};
+use crate::utils::ast::UpdateOp;
+
+// What is known before an instruction runs: the stack height above what the frame started with,
+// and the height each installed handler goes back to when it catches an error, innermost last
+#[derive(Clone, PartialEq)]
+struct State {
+    height: usize,
+    handlers: Vec<usize>,
+}
+
+pub fn verify(program: &Program) -> Result<(), String> {
+    prototype(&program.main, 0, program.globals.len())
+}
+
+// `level` is how many functions the prototype is nested in: it runs with one scope more than that.
+// A script's top-level variables may also have the slots its globals name.
+fn prototype(prototype: &Prototype, level: usize, globals: usize) -> Result<(), String> {
+    let chunk = &prototype.chunk;
+    let declarations = chunk
+        .code
+        .iter()
+        .filter(|op| {
+            matches!(
+                op,
+                Op::Declare { .. } | Op::DeclareConst { .. } | Op::Define { .. }
+            )
+        })
+        .count();
+    // Every variable of a scope is a parameter or has a declaration in its chunk
+    let slots = globals.max(prototype.params.len() + declarations);
+    let script = level == 0;
+
+    let mut states: Vec<Option<State>> = vec![None; chunk.code.len()];
+    let mut pending = vec![0];
+    let start = State {
+        height: 0,
+        handlers: Vec::new(),
+    };
+    reach(&mut states, &mut pending, 0, start)?;
+    while let Some(ip) = pending.pop() {
+        let state = states[ip]
+            .clone()
+            .expect("a state for every pending instruction");
+        let op = chunk.code[ip];
+        match op {
+            Op::GetVar { depth, .. } | Op::LoadUpdate { depth, .. } | Op::Assign { depth, .. }
+                if depth as usize > level =>
+            {
+                return Err("uses a variable from a scope its code cannot reach".to_string());
+            }
+            Op::Declare { slot, .. } | Op::DeclareConst { slot, .. } | Op::Define { slot, .. }
+                if slot as usize >= slots =>
+            {
+                return Err("declares a variable in a slot its scope does not have".to_string());
+            }
+            _ => {}
+        }
+
+        let pops = pops(op);
+        if pops > state.height {
+            return Err(
+                "has an instruction that takes more values than the stack holds".to_string(),
+            );
+        }
+        // An error goes back to the innermost handler's height, which must still be there
+        if state
+            .handlers
+            .last()
+            .is_some_and(|&handler| state.height - pops < handler)
+        {
+            return Err("takes values from below where a try block started".to_string());
+        }
+        let after = State {
+            height: state.height - pops + pushes(op),
+            handlers: state.handlers.clone(),
+        };
+        match op {
+            Op::Jump(target) => reach(&mut states, &mut pending, target as usize, after)?,
+            Op::JumpIfFalse(target) => {
+                reach(&mut states, &mut pending, target as usize, after.clone())?;
+                reach(&mut states, &mut pending, ip + 1, after)?;
+            }
+            // These keep the value they test when they jump, and pop it when they do not
+            Op::JumpIfFalseOrPop(target)
+            | Op::JumpIfTrueOrPop(target)
+            | Op::JumpIfNotNil(target) => {
+                reach(&mut states, &mut pending, target as usize, state)?;
+                reach(&mut states, &mut pending, ip + 1, after)?;
+            }
+            Op::PushHandler(target) => {
+                let caught = State {
+                    height: state.height + 1,
+                    handlers: state.handlers.clone(),
+                };
+                reach(&mut states, &mut pending, target as usize, caught)?;
+                let mut installed = after;
+                installed.handlers.push(state.height);
+                reach(&mut states, &mut pending, ip + 1, installed)?;
+            }
+            Op::PopHandler => {
+                let mut removed = after;
+                if removed.handlers.pop().is_none() {
+                    return Err("removes a try handler that was never installed".to_string());
+                }
+                reach(&mut states, &mut pending, ip + 1, removed)?;
+            }
+            Op::Return | Op::Throw | Op::Rethrow => {}
+            Op::Halt => {
+                if !script || !state.handlers.is_empty() {
+                    return Err("ends the script inside a function or try block".to_string());
+                }
+            }
+            _ => reach(&mut states, &mut pending, ip + 1, after)?,
+        }
+    }
+
+    for function in &chunk.functions {
+        self::prototype(function, level + 1, 0)?;
+    }
+    Ok(())
+}
+
+// Records the state an instruction is reached in, queueing it the first time
+fn reach(
+    states: &mut [Option<State>],
+    pending: &mut Vec<usize>,
+    ip: usize,
+    state: State,
+) -> Result<(), String> {
+    match states.get_mut(ip) {
+        None => Err("has code that runs past its end".to_string()),
+        Some(Some(known)) if *known != state => {
+            Err("reaches an instruction with the stack in different states".to_string())
+        }
+        Some(Some(_)) => Ok(()),
+        Some(slot) => {
+            *slot = Some(state);
+            pending.push(ip);
+            Ok(())
+        }
+    }
+}
+
+// How many values an instruction takes off the stack, at most
+fn pops(op: Op) -> usize {
+    match op {
+        Op::Constant(_)
+        | Op::Nil
+        | Op::True
+        | Op::False
+        | Op::GetVar { .. }
+        | Op::GetBuiltin(_)
+        | Op::LoadUpdate { .. }
+        | Op::Jump(_)
+        | Op::Closure(_)
+        | Op::PushHandler(_)
+        | Op::PopHandler
+        | Op::Import(_)
+        | Op::Halt => 0,
+        Op::Pop
+        | Op::Declare { .. }
+        | Op::DeclareConst { .. }
+        | Op::Define { .. }
+        | Op::Assign { .. }
+        | Op::Conform(..)
+        | Op::Unary(_)
+        | Op::Test
+        | Op::JumpIfFalse(_)
+        | Op::JumpIfFalseOrPop(_)
+        | Op::JumpIfTrueOrPop(_)
+        | Op::JumpIfNotNil(_)
+        | Op::Return
+        | Op::Get(_)
+        | Op::GetOptional(_)
+        | Op::PrintOption(_)
+        | Op::Throw
+        | Op::Rethrow => 1,
+        Op::Update(UpdateOp::Add | UpdateOp::Subtract) => 2,
+        Op::Update(_) => 1,
+        Op::Binary(_) | Op::Index => 2,
+        // The function and its arguments
+        Op::Call(count) => count as usize + 1,
+        Op::List(count) => count as usize,
+        Op::Print {
+            values, sep, end, ..
+        } => values as usize + usize::from(sep) + usize::from(end),
+    }
+}
+
+// How many values an instruction leaves on the stack when it carries on to the next one
+fn pushes(op: Op) -> usize {
+    match op {
+        Op::Pop
+        | Op::Declare { .. }
+        | Op::DeclareConst { .. }
+        | Op::Define { .. }
+        | Op::Assign { .. }
+        | Op::Jump(_)
+        | Op::JumpIfFalse(_)
+        | Op::JumpIfFalseOrPop(_)
+        | Op::JumpIfTrueOrPop(_)
+        | Op::JumpIfNotNil(_)
+        | Op::Return
+        | Op::Print { .. }
+        | Op::Throw
+        | Op::PushHandler(_)
+        | Op::PopHandler
+        | Op::Rethrow
+        | Op::Halt => 0,
+        _ => 1,
+    }
+}
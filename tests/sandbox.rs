@@ -0,0 +1,161 @@
+// Runs scripts in a temporary directory with files outside the reach the sandbox gives them, to
+// check the file builtins and imports cannot get to those files
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const ENGINES: [&[&str]; 2] = [&[], &["--engine", "vm"]];
+
+// A fresh directory holding `root`, which scripts are limited to, and `outside.txt` next to it
+fn workspace(name: &str) -> PathBuf {
+    let workspace = env::temp_dir().join(format!("buglang-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&workspace);
+    fs::create_dir_all(workspace.join("root")).expect("cannot create the file root");
+    fs::write(workspace.join("outside.txt"), "secret").expect("cannot write outside the root");
+    workspace
+}
+
+// What the script printed, with errors after its output. It is written to `directory` inside the
+// workspace and run from the workspace.
+fn run(workspace: &Path, directory: &str, source: &str, options: &[&str]) -> String {
+    let script = Path::new(directory).join("script.bug");
+    fs::write(workspace.join(&script), source).expect("cannot write the script");
+    let output = Command::new(env!("CARGO_BIN_EXE_pseudolanguage"))
+        .current_dir(workspace)
+        .args(options)
+        .arg("-i")
+        .arg(&script)
+        .stdin(Stdio::null())
+        .output()
+        .expect("cannot run the interpreter");
+    format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+#[cfg(unix)]
+#[test]
+fn links_cannot_lead_out_of_the_file_root() {
+    use std::os::unix::fs::symlink;
+
+    let workspace = workspace("links");
+    let root = workspace.join("root");
+    symlink(workspace.join("outside.txt"), root.join("link.txt")).expect("cannot make a link");
+    symlink(workspace.join("missing.txt"), root.join("dangling.txt")).expect("cannot make a link");
+    let source = r#"
+try { print read_file("link.txt"); } catch (e) { print e.kind; }
+try { write_file("dangling.txt", "escaped"); } catch (e) { print e.kind; }
+try { append_file("dangling.txt", "escaped"); } catch (e) { print e.kind; }
+try { write_file("link.txt", "overwritten"); } catch (e) { print e.kind; }
+write_file("inside.txt", "kept");
+print read_file("inside.txt");
+"#;
+    let root = root.to_string_lossy();
+    for engine in ENGINES {
+        let options = [engine, &["--fs", "full", "--fs-root", &root]].concat();
+        let output = run(&workspace, "", source, &options);
+        assert_eq!(
+            output, "PermissionError\nPermissionError\nPermissionError\nPermissionError\nkept\n",
+            "with {:?}",
+            options
+        );
+        assert!(!workspace.join("missing.txt").exists());
+        assert_eq!(
+            fs::read_to_string(workspace.join("outside.txt")).unwrap(),
+            "secret"
+        );
+    }
+    let _ = fs::remove_dir_all(&workspace);
+}
+
+// Modules beside the script that was run can always be imported, but anything else is a file like
+// any other and needs the policy to allow reading it
+#[test]
+fn imports_follow_the_file_policy() {
+    let workspace = workspace("imports");
+    let app = workspace.join("app");
+    fs::create_dir_all(app.join("lib")).expect("cannot create the script's directory");
+    fs::write(app.join("lib").join("helpers.bug"), "variable answer = 42;")
+        .expect("cannot write the module");
+    let outside = workspace.join("outside.txt");
+    let source = format!(
+        r#"
+import "lib/helpers.bug";
+print helpers.answer;
+try {{ import "../outside.txt"; }} catch (e) {{ print e.kind; }}
+try {{ import "{}"; }} catch (e) {{ print e.kind; }}
+"#,
+        outside.display()
+    );
+    let root = workspace.join("root").to_string_lossy().into_owned();
+    let policies: [&[&str]; 3] = [&[], &["--fs", "read", "--fs-root", &root], &["--fs", "off"]];
+    for engine in ENGINES {
+        for policy in policies {
+            let options = [engine, policy].concat();
+            let output = run(&workspace, "app", &source, &options);
+            assert_eq!(
+                output, "42\nPermissionError\nPermissionError\n",
+                "with {:?}",
+                options
+            );
+        }
+        // Reading files at all lets the import through, where the text fails to parse
+        let options = [engine, &["--fs", "read"]].concat();
+        let output = run(&workspace, "app", &source, &options);
+        assert_eq!(
+            output, "42\nSyntaxError\nSyntaxError\n",
+            "with {:?}",
+            options
+        );
+    }
+    let _ = fs::remove_dir_all(&workspace);
+}
+
+// A program that outlives `--timeout` is killed rather than waited for
+#[cfg(unix)]
+#[test]
+fn programs_stop_at_the_timeout() {
+    let workspace = workspace("timeout");
+    fs::write(workspace.join("policy.toml"), "process = true\n").expect("cannot write the policy");
+    let source = r#"
+print run("echo", ["started"]);
+run("sleep", ["10"]);
+print "finished";
+"#;
+    for engine in ENGINES {
+        let options = [engine, &["--sandbox", "policy.toml", "--timeout", "0.5"]].concat();
+        let started = std::time::Instant::now();
+        let output = run(&workspace, "", source, &options);
+        assert!(
+            output.starts_with("started\n\nBudgetExceeded: Ran for longer than 500ms"),
+            "with {:?}: {}",
+            options,
+            output
+        );
+        assert!(started.elapsed().as_secs() < 5, "with {:?}", options);
+    }
+    let _ = fs::remove_dir_all(&workspace);
+}
+
+// A module outside the script's directory is loaded from the path the file policy checked
+#[test]
+fn imports_load_the_checked_file() {
+    let workspace = workspace("checked");
+    fs::create_dir_all(workspace.join("app")).expect("cannot create the script's directory");
+    fs::write(workspace.join("root").join("shared.bug"), "variable answer = 42;")
+        .expect("cannot write the module");
+    let source = r#"
+import "../root/shared.bug";
+print shared.answer;
+"#;
+    let root = workspace.join("root").to_string_lossy().into_owned();
+    for engine in ENGINES {
+        let options = [engine, &["--fs", "read", "--fs-root", &root]].concat();
+        let output = run(&workspace, "app", source, &options);
+        assert_eq!(output, "42\n", "with {:?}", options);
+    }
+    let _ = fs::remove_dir_all(&workspace);
+}
@@ -0,0 +1,254 @@
+// Runs every script in tests/scripts and compares what it prints with the files next to it:
+// `name.out` holds its stdout and `name.err` its stderr, which is empty when there is no such file.
+// A `name.in` file is given to the script as its input. A first line of `# args: ...` passes
+// extra options to the interpreter.
+//
+// Each script runs on both engines, with and without -O, and every run must give the same output.
+// `BLESS=1 cargo test` rewrites the expected files from the tree-walker's output instead.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const ENGINES: [&[&str]; 4] = [&[], &["--engine", "vm"], &["-O"], &["-O", "--engine", "vm"]];
+
+struct Output {
+    stdout: String,
+    stderr: String,
+}
+
+fn directory(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(name)
+}
+
+// The scripts directly inside a directory, in name order. Subdirectories hold the modules they
+// import.
+fn scripts(directory: &Path) -> Vec<PathBuf> {
+    let mut scripts: Vec<PathBuf> = fs::read_dir(directory)
+        .expect("cannot read the test directory")
+        .map(|entry| entry.expect("cannot read a test file").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "bug"))
+        .collect();
+    scripts.sort();
+    scripts
+}
+
+// The options on a script's `# args:` line
+fn arguments(script: &Path) -> Vec<String> {
+    let source = fs::read_to_string(script).expect("cannot read the script");
+    match source
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("# args:"))
+    {
+        Some(arguments) => arguments.split_whitespace().map(String::from).collect(),
+        None => Vec::new(),
+    }
+}
+
+// The interpreter set up to run from the script's directory, so paths in errors are just its
+// name, with the script's options and input
+fn interpreter(script: &Path, options: &[&str]) -> Command {
+    let mut interpreter = Command::new(env!("CARGO_BIN_EXE_pseudolanguage"));
+    interpreter
+        .current_dir(script.parent().expect("the script's directory"))
+        .args(options)
+        .args(arguments(script))
+        .stdin(Stdio::null());
+    let input = script.with_extension("in");
+    if input.exists() {
+        interpreter.arg("--stdin").arg(input);
+    }
+    interpreter
+}
+
+fn output(mut command: Command) -> Output {
+    let output = command.output().expect("cannot run the interpreter");
+    Output {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }
+}
+
+// Runs the script, or the subcommand given on it
+fn run(script: &Path, options: &[&str], command: &[&str]) -> Output {
+    let name = script.file_name().expect("the script's name");
+    let mut interpreter = interpreter(script, options);
+    if command.is_empty() {
+        interpreter.arg("-i").arg(name);
+    } else {
+        interpreter.args(command).arg(name);
+    }
+    output(interpreter)
+}
+
+fn expected(script: &Path, extension: &str) -> String {
+    fs::read_to_string(script.with_extension(extension)).unwrap_or_default()
+}
+
+// Writes the expected files from a run, leaving out an empty stderr
+fn bless(script: &Path, output: &Output) {
+    fs::write(script.with_extension("out"), &output.stdout).expect("cannot write the .out file");
+    let errors = script.with_extension("err");
+    if output.stderr.is_empty() {
+        let _ = fs::remove_file(errors);
+    } else {
+        fs::write(errors, &output.stderr).expect("cannot write the .err file");
+    }
+}
+
+// Describes how a run differs from what was expected, if it does
+fn compare(script: &Path, options: &[&str], output: &Output) -> Option<String> {
+    let stdout = expected(script, "out");
+    let stderr = expected(script, "err");
+    if output.stdout == stdout && output.stderr == stderr {
+        return None;
+    }
+    Some(format!(
+        "{} with {:?}:\n--- expected stdout\n{}--- got stdout\n{}--- expected stderr\n{}--- got stderr\n{}",
+        script.display(),
+        options,
+        stdout,
+        output.stdout,
+        stderr,
+        output.stderr
+    ))
+}
+
+fn check_all(scripts: &[PathBuf], runs: &[&[&str]], command: &[&str]) {
+    let blessing = env::var_os("BLESS").is_some();
+    let mut failures = Vec::new();
+    for script in scripts {
+        if blessing {
+            bless(script, &run(script, runs[0], command));
+        }
+        for options in runs {
+            let output = run(script, options, command);
+            failures.extend(compare(script, options, &output));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn scripts_give_their_expected_output() {
+    check_all(&scripts(&directory("scripts")), &ENGINES, &[]);
+}
+
+// `dump -O` shows each optimization, so the listing is compared as it is
+#[test]
+fn optimized_dumps_match() {
+    check_all(&scripts(&directory("dump")), &[&["-O"]], &["dump"]);
+}
+
+// A compiled script runs the same as its source. Scripts that import modules are left out, since
+// the .bugc file is written elsewhere and imports are found next to it.
+#[test]
+fn compiled_scripts_match_their_source() {
+    // The expected files may be being rewritten by the other tests
+    if env::var_os("BLESS").is_some() {
+        return;
+    }
+    let output_directory = env::temp_dir().join(format!("buglang-tests-{}", std::process::id()));
+    fs::create_dir_all(&output_directory).expect("cannot create the output directory");
+    let mut failures = Vec::new();
+    for script in scripts(&directory("scripts")) {
+        let source = fs::read_to_string(&script).expect("cannot read the script");
+        if source.contains("import ") {
+            continue;
+        }
+        let name = script.file_name().expect("the script's name");
+        let compiled = output_directory.join(name).with_extension("bugc");
+        let status = Command::new(env!("CARGO_BIN_EXE_pseudolanguage"))
+            .current_dir(script.parent().expect("the script's directory"))
+            .arg("compile")
+            .arg(name)
+            .arg("-o")
+            .arg(&compiled)
+            .output()
+            .expect("cannot run the compiler");
+        if !status.status.success() {
+            // Scripts that fail to check are covered by the source runs
+            continue;
+        }
+        let mut interpreter = interpreter(&script, &[]);
+        interpreter.arg("-i").arg(&compiled);
+        failures.extend(compare(&script, &["compiled"], &output(interpreter)));
+    }
+    let _ = fs::remove_dir_all(&output_directory);
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+// Changing bytes of a compiled script may make the VM refuse it or run it differently, but never
+// crash or hang it
+#[test]
+fn damaged_compiled_scripts_fail_cleanly() {
+    let output_directory = env::temp_dir().join(format!("buglang-damaged-{}", std::process::id()));
+    fs::create_dir_all(&output_directory).expect("cannot create the output directory");
+    // A fixed sequence of pseudo-random numbers, so a failure can be repeated
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut random = |below: usize| {
+        seed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (seed >> 33) as usize % below
+    };
+    let mut failures = Vec::new();
+    for script in scripts(&directory("scripts")) {
+        let name = script.file_name().expect("the script's name");
+        let compiled = output_directory.join(name).with_extension("bugc");
+        let status = Command::new(env!("CARGO_BIN_EXE_pseudolanguage"))
+            .current_dir(script.parent().expect("the script's directory"))
+            .arg("compile")
+            .arg(name)
+            .arg("-o")
+            .arg(&compiled)
+            .output()
+            .expect("cannot run the compiler");
+        if !status.status.success() {
+            continue;
+        }
+        let bytes = fs::read(&compiled).expect("cannot read the compiled script");
+        let damaged = output_directory.join("damaged.bugc");
+        for _ in 0..20 {
+            let mut copy = bytes.clone();
+            // The magic number and version stay, so the changes reach the code
+            for _ in 0..1 + random(4) {
+                let position = 6 + random(copy.len() - 6);
+                copy[position] = random(256) as u8;
+            }
+            fs::write(&damaged, &copy).expect("cannot write the damaged script");
+            let output = Command::new(env!("CARGO_BIN_EXE_pseudolanguage"))
+                .current_dir(&output_directory)
+                .args([
+                    "--max-steps",
+                    "100000",
+                    "--timeout",
+                    "5",
+                    "--max-memory",
+                    "64M",
+                ])
+                .arg("-i")
+                .arg(&damaged)
+                .stdin(Stdio::null())
+                .output()
+                .expect("cannot run the interpreter");
+            if !matches!(output.status.code(), Some(0 | 1)) {
+                let kept = output_directory
+                    .with_file_name(format!("buglang-crash-{}.bugc", failures.len()));
+                let _ = fs::write(&kept, &copy);
+                failures.push(format!(
+                    "{} damaged as {} exited with {:?}:\n{}",
+                    script.display(),
+                    kept.display(),
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+            }
+        }
+    }
+    let _ = fs::remove_dir_all(&output_directory);
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}